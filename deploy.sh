@@ -6,7 +6,7 @@ RES=$(neutrond tx wasm store artifacts/babu-aarch64.wasm --from babu $NODE -y --
 # CODE_ID=$(echo $RES | jq -r '.logs[0].events[-1].attributes[0].value')
 neutrond query wasm list-code $NODE --page 17
 CODE_ID=...
-INIT='{}'
+INIT='{"connection_id":"connection-10","interchain_account_id":"babu_neutron_osmosis_v1","redbank_addr":"osmo1c3ljch9dfw5kf52nfwpxd2zmj2ese7agnx0p9tenkrryasrle5sqf3ftpg","transfer_channel":"channel-186","return_channel":"channel-3515","target_denom":"uusdc","local_denom":"ibc/B559A80D62249C8AA07A380E2A2BEA6E5CA9A6F079C912C3A9E9B494105E4F81","fee_denom":"untrn","max_slippage":"0.05","remote_bech32_prefix":"osmo","name":"Babushka Vault Share","symbol":"BABU","decimals":6,"keeper":"neutron14jkyrmk8n0hsdqqr7vg5clhasxpt5ajd0e6zm9"}'
 neutrond tx wasm instantiate $CODE_ID "$INIT" --from babu --label "babu test" -y --admin "neutron14jkyrmk8n0hsdqqr7vg5clhasxpt5ajd0e6zm9" --gas-prices 0.025untrn --gas-adjustment 1.7 --gas auto $NODE
 CONTRACT=$(neutrond query wasm list-contract-by-code $CODE_ID $NODE --output json | jq -r '.contracts[-1]')
 echo $CONTRACT
@@ -0,0 +1,39 @@
+use cosmwasm_std::{Addr, Storage};
+
+use crate::{
+    error::{ContractError, ContractResult},
+    storage::{PAUSED, ROLES},
+};
+
+pub fn ensure_owner(store: &dyn Storage, sender: &Addr) -> ContractResult<()> {
+    let roles = ROLES.load(store)?;
+    if *sender != roles.owner {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(())
+}
+
+// the owner can always act as keeper
+pub fn ensure_keeper(store: &dyn Storage, sender: &Addr) -> ContractResult<()> {
+    let roles = ROLES.load(store)?;
+    if *sender != roles.keeper && *sender != roles.owner {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(())
+}
+
+// the owner can always act as guardian
+pub fn ensure_guardian(store: &dyn Storage, sender: &Addr) -> ContractResult<()> {
+    let roles = ROLES.load(store)?;
+    if *sender != roles.guardian && *sender != roles.owner {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(())
+}
+
+pub fn ensure_not_paused(store: &dyn Storage) -> ContractResult<()> {
+    if PAUSED.may_load(store)?.unwrap_or_default() {
+        return Err(ContractError::Paused);
+    }
+    Ok(())
+}
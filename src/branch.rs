@@ -0,0 +1,117 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::ops::Bound;
+
+use cosmwasm_std::{Order, Record, Storage};
+
+/// StorageBranch buffers the writes to a storage until they are committed, so a handler that
+/// fails half way leaves the storage untouched.
+pub struct StorageBranch<'a> {
+    inner: &'a mut dyn Storage,
+    // written values by key, None for removed keys
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> StorageBranch<'a> {
+    pub fn new(inner: &'a mut dyn Storage) -> Self {
+        StorageBranch {
+            inner,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// applies the buffered writes to the storage
+    pub fn commit(self) {
+        for (key, value) in self.writes {
+            match value {
+                Some(value) => self.inner.set(&key, &value),
+                None => self.inner.remove(&key),
+            }
+        }
+    }
+}
+
+impl Storage for StorageBranch<'_> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        match self.writes.get(key) {
+            Some(value) => value.clone(),
+            None => self.inner.get(key),
+        }
+    }
+
+    fn range<'b>(
+        &'b self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Record> + 'b> {
+        // an empty range, BTreeMap::range panics on it
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                return Box::new(std::iter::empty());
+            }
+        }
+        let bounds = (
+            start.map_or(Bound::Unbounded, |start| Bound::Included(start.to_vec())),
+            end.map_or(Bound::Unbounded, |end| Bound::Excluded(end.to_vec())),
+        );
+        let writes: Writes<'b> = match order {
+            Order::Ascending => Box::new(self.writes.range(bounds)),
+            Order::Descending => Box::new(self.writes.range(bounds).rev()),
+        };
+        Box::new(MergedRange {
+            base: self.inner.range(start, end, order).peekable(),
+            writes: writes.peekable(),
+            order,
+        })
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+}
+
+// buffered writes in range, a removal is written as None
+type Writes<'b> = Box<dyn Iterator<Item = (&'b Vec<u8>, &'b Option<Vec<u8>>)> + 'b>;
+
+// records of the storage with the buffered writes merged in, both in the same order
+struct MergedRange<'b> {
+    base: Peekable<Box<dyn Iterator<Item = Record> + 'b>>,
+    writes: Peekable<Writes<'b>>,
+    order: Order,
+}
+
+impl Iterator for MergedRange<'_> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        loop {
+            // which of the two comes first, a write shadows the record of the same key
+            let next_write = match (self.base.peek(), self.writes.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (Some((base_key, _)), Some((write_key, _))) => match self.order {
+                    Order::Ascending => write_key.as_slice().cmp(base_key.as_slice()),
+                    Order::Descending => base_key.as_slice().cmp(write_key.as_slice()),
+                },
+            };
+            match next_write {
+                Ordering::Greater => return self.base.next(),
+                Ordering::Equal => {
+                    self.base.next();
+                }
+                Ordering::Less => {}
+            }
+            // removed keys are skipped
+            if let Some((key, Some(value))) = self.writes.next() {
+                return Some((key.clone(), value.clone()));
+            }
+        }
+    }
+}
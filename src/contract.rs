@@ -1,12 +1,16 @@
+use cosmos_sdk_proto::cosmos::base::abci::v1beta1::MsgData;
 use cosmos_sdk_proto::ibc::applications::transfer::v1::{MsgTransfer, MsgTransferResponse};
 use cosmos_sdk_proto::traits::Message;
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, Addr, BankMsg, Binary, CosmosMsg, CustomQuery, Deps, DepsMut, Env, MessageInfo,
-    Reply, Response, StdError, StdResult, Storage, SubMsg, Uint128,
+    from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, CustomQuery, Decimal, Deps,
+    DepsMut, Env, Event, MessageInfo, Order, Reply, Response, StdError, StdResult, Storage, SubMsg,
+    Timestamp, Uint128,
 };
 use cw2::set_contract_version;
+use cw20::{Logo, LogoInfo, MarketingInfoResponse};
+use cw_storage_plus::Bound;
 use osmosis_std::types::cosmwasm::wasm::v1::MsgExecuteContractResponse;
 use osmosis_std::types::osmosis::gamm::v1beta1::MsgSwapExactAmountInResponse;
 use osmosis_std::types::{
@@ -15,26 +19,75 @@ use osmosis_std::types::{
 // use prost::Message;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
-    msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg},
-    storage::{BALANCES, TOKEN_INFO, TOTAL_SUPPLY, TOTAL_VALUE},
+    access::{ensure_guardian, ensure_keeper, ensure_not_paused, ensure_owner},
+    branch::StorageBranch,
+    error::{ContractError, ContractResult},
+    fees::{accrue_fees, charge_entry_fee, charge_exit_fee, initial_fee_state},
+    msg::{
+        BufferResponse, ChainNavResponse, ClaimableWithdrawal, ExecuteMsg, FeeSplit, HostChain,
+        HostChainResponse, IcaStatus, InstantiateMsg, MigrateMsg, NavResponse, QueryMsg,
+        ShareBackend, StrategyKind, StrategyResponse,
+    },
+    nav::{
+        ensure_nav_fresh, recompute_total_value, release_after_nav, release_proven, release_value,
+        reserve_value, DEFAULT_NAV_MAX_AGE, DEFAULT_NAV_UPDATE_PERIOD,
+    },
+    returns::{buffer_in_transit, confirm_returns, pay_epoch_funds},
+    shares::{shares_to_value, value_to_shares},
+    storage::{
+        create_deposit, create_return, create_ticket, evict_ack_results, open_epoch,
+        update_deposit, Allocation, Config, Deposit, DepositStage, EpochStage, ErrorKind,
+        FeeRecipient, MinterData, NavQueries, ReturnPurpose, Roles, StrategyInfo, TokenInfo,
+        WithdrawalEpoch, WithdrawalTicket, BUFFER, CONFIG, CURRENT_EPOCH, DEFAULT_ACK_RETENTION,
+        DEPOSITS, EPOCHS, ERRORS_QUEUE, FEE_STATE, HOME_CHAIN_ID, HOST_CHAINS, MARKETING_INFO, NAV,
+        NAV_QUERIES, NAV_QUERY_CHAIN, PAUSED, PRUNE_CURSOR, REBALANCE,
+        REGISTER_BALANCE_QUERY_REPLY_ID, REGISTER_POSITION_QUERY_REPLY_ID,
+        REGISTER_REWARD_QUERY_REPLY_ID, RESERVED_VALUE, ROLES, SHARE_DENOM, STRATEGIES,
+        SUDO_PAYLOAD, TICKETS, TOKEN_INFO, TOTAL_VALUE, USER_DEPOSITS, USER_TICKETS,
+    },
+    strategy::{
+        allocate_deposit, allocate_withdrawal, allocation_msgs, apply_allocations,
+        chain_strategies, deposit_chain, load_strategies, plan_rebalance, position_keys,
+        strategy_chains, update_positions, withdrawal_chain, DEFAULT_REBALANCE_THRESHOLD,
+        REDBANK_STRATEGY_ID,
+    },
+    token::{
+        burn_shares, ensure_redeemable, execute_burn, execute_burn_from,
+        execute_decrease_allowance, execute_increase_allowance, execute_send, execute_send_from,
+        execute_transfer, execute_transfer_from, lock_shares, mint_shares, query_all_accounts,
+        query_all_allowances, query_allowance, query_balance, query_marketing_info,
+        query_share_supply, query_token_info, share_denom, DEFAULT_LIMIT, MAX_LIMIT,
+        SHARE_SUBDENOM,
+    },
+    validation::{
+        validate_ack_retention, validate_buffer_ratio, validate_fees, validate_host_chain,
+        validate_nav_max_age, validate_rebalance_threshold, validate_remote_addr,
+        validate_slippage, validate_strategy, validate_transaction_fees, MAX_HOST_CHAINS,
+        MAX_STRATEGIES,
+    },
 };
 // use neutron_sdk::bindings::msg::IbcFee;
 use neutron_sdk::{
     bindings::{
-        msg::{IbcFee, MsgSubmitTxResponse, NeutronMsg},
+        msg::{IbcFee, MsgRegisterInterchainQueryResponse, MsgSubmitTxResponse, NeutronMsg},
         query::{NeutronQuery, QueryInterchainAccountAddressResponse},
         types::ProtobufAny,
         // types::ProtobufAny,
     },
+    interchain_queries::{
+        queries::get_raw_interchain_query_result,
+        types::{KVReconstruct, QueryPayload},
+        v045::{new_register_balance_query_msg, types::Balances},
+    },
     interchain_txs::helpers::{
         decode_acknowledgement_response, decode_message_response, get_port_id,
     },
     query::min_ibc_fee::query_min_ibc_fee,
     // query::min_ibc_fee::query_min_ibc_fee,
-    sudo::msg::{RequestPacket, SudoMsg},
-    NeutronError,
+    sudo::msg::{RequestPacket, RequestPacketTimeoutHeight, SudoMsg},
     // NeutronError,
     NeutronResult,
 };
@@ -44,28 +97,30 @@ use crate::storage::{
     read_errors_from_queue,
     read_reply_payload,
     read_sudo_payload,
+    remove_reply_payload,
+    save_ack_result,
     save_reply_payload,
     // save_reply_payload,
     save_sudo_payload,
+    take_sudo_payload,
     AcknowledgementResult,
+    SudoOperation,
     SudoPayload,
     //SudoPayload,
     ACKNOWLEDGEMENT_RESULTS,
+    ICA_STATUS,
     INTERCHAIN_ACCOUNTS,
-    SUDO_PAYLOAD_REPLY_ID,
+    SUDO_PAYLOAD_REPLY_ID_START,
 };
 
 // Default timeout for SubmitTX is two weeks
 const DEFAULT_TIMEOUT_SECONDS: u64 = 60 * 60 * 24 * 7 * 2;
-const FEE_DENOM: &str = "untrn";
+// most entries one PruneState removes
+const MAX_PRUNE_LIMIT: u32 = 100;
 
 const CONTRACT_NAME: &str = concat!("crates.io:neutron-sdk__", env!("CARGO_PKG_NAME"));
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 // const UMEE_CONNECTION_ID: &str = "connection-18"; // mainnet
-// const OSMOSIS_CONNECTION_ID: &str = "connection-10"; // testnet
-const OSMOSIS_IC_ACCOUNT_ID: &str = "babu_neutron_osmosis_v1";
-const REDBANK_OSMOSIS_ADDR: &str =
-    "osmo1c3ljch9dfw5kf52nfwpxd2zmj2ese7agnx0p9tenkrryasrle5sqf3ftpg";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -81,31 +136,170 @@ struct OpenAckVersion {
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut<NeutronQuery>,
-    _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
-) -> NeutronResult<Response<NeutronMsg>> {
+    env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> ContractResult<Response<NeutronMsg>> {
     deps.api.debug("WASMDEBUG: instantiate");
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
+    let home_chain = HostChain {
+        connection_id: msg.connection_id,
+        interchain_account_id: msg.interchain_account_id,
+        transfer_channel: msg.transfer_channel,
+        return_channel: msg.return_channel,
+        remote_denom: msg.target_denom,
+        remote_bech32_prefix: msg.remote_bech32_prefix,
+    };
+    validate_host_chain(HOME_CHAIN_ID, &home_chain)?;
+    validate_remote_addr(&msg.redbank_addr, &home_chain.remote_bech32_prefix)?;
+    if let Some(incentives_addr) = &msg.incentives_addr {
+        validate_remote_addr(incentives_addr, &home_chain.remote_bech32_prefix)?;
+    }
+    validate_slippage(msg.max_slippage)?;
+    validate_buffer_ratio(msg.buffer_ratio)?;
+    let rebalance_threshold = msg
+        .rebalance_threshold
+        .unwrap_or(DEFAULT_REBALANCE_THRESHOLD);
+    validate_rebalance_threshold(rebalance_threshold)?;
+    let nav_max_age = msg.nav_max_age.unwrap_or(DEFAULT_NAV_MAX_AGE);
+    validate_nav_max_age(nav_max_age)?;
+    let ack_retention = msg.ack_retention.unwrap_or(DEFAULT_ACK_RETENTION);
+    validate_ack_retention(ack_retention)?;
+    validate_fees(msg.performance_fee, msg.management_fee)?;
+    let fee_recipients = fee_recipients(deps.as_ref(), &msg.fee_splits)?;
+    validate_transaction_fees(
+        msg.entry_fee_bps,
+        msg.exit_fee_bps,
+        &fee_recipients.iter().map(|r| r.weight).collect::<Vec<_>>(),
+    )?;
+
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender,
+    };
+    let fee_recipient = match msg.fee_recipient {
+        Some(fee_recipient) => deps.api.addr_validate(&fee_recipient)?,
+        None => owner.clone(),
+    };
+
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            incentives_addr: msg.incentives_addr,
+            reward_denom: msg.reward_denom,
+            local_denom: msg.local_denom,
+            fee_denom: msg.fee_denom,
+            max_slippage: msg.max_slippage,
+            buffer_ratio: msg.buffer_ratio,
+            rebalance_threshold,
+            nav_update_period: msg.nav_update_period.unwrap_or(DEFAULT_NAV_UPDATE_PERIOD),
+            nav_max_age,
+            performance_fee: msg.performance_fee,
+            management_fee: msg.management_fee,
+            fee_recipient,
+            entry_fee_bps: msg.entry_fee_bps,
+            exit_fee_bps: msg.exit_fee_bps,
+            fee_recipients,
+            share_backend: msg.share_backend.clone(),
+            ack_retention,
+        },
+    )?;
+
+    let keeper = match msg.keeper {
+        Some(keeper) => deps.api.addr_validate(&keeper)?,
+        None => owner.clone(),
+    };
+    let guardian = match msg.guardian {
+        Some(guardian) => deps.api.addr_validate(&guardian)?,
+        None => owner.clone(),
+    };
+    ROLES.save(
+        deps.storage,
+        &Roles {
+            owner,
+            pending_owner: None,
+            keeper,
+            guardian,
+        },
+    )?;
+    PAUSED.save(deps.storage, &false)?;
+    TOTAL_VALUE.save(deps.storage, &Uint128::zero())?;
+    BUFFER.save(deps.storage, &Uint128::zero())?;
+    RESERVED_VALUE.save(deps.storage, &Uint128::zero())?;
+    FEE_STATE.save(deps.storage, &initial_fee_state(env.block.time))?;
+    HOST_CHAINS.save(deps.storage, HOME_CHAIN_ID, &home_chain)?;
+    STRATEGIES.save(
+        deps.storage,
+        REDBANK_STRATEGY_ID,
+        &StrategyInfo {
+            chain: HOME_CHAIN_ID.to_string(),
+            kind: StrategyKind::RedBank {
+                addr: msg.redbank_addr,
+            },
+            weight: 1,
+            value: Uint128::zero(),
+            cap: None,
+        },
+    )?;
+    open_epoch(deps.storage, 1, env.block.time)?;
+
+    // the vault is the only minter of its shares
+    TOKEN_INFO.save(
+        deps.storage,
+        &TokenInfo {
+            name: msg.name,
+            symbol: msg.symbol,
+            decimals: msg.decimals,
+            total_supply: Uint128::zero(),
+            mint: Some(MinterData {
+                minter: env.contract.address.clone(),
+                cap: None,
+            }),
+        },
+    )?;
+
+    let mut resp = Response::default();
+    if msg.share_backend == ShareBackend::TokenFactory {
+        SHARE_DENOM.save(deps.storage, &share_denom(&env.contract.address))?;
+        resp = resp.add_message(NeutronMsg::submit_create_denom(SHARE_SUBDENOM));
+    }
+    if let Some(marketing) = msg.marketing {
+        let logo = match marketing.logo {
+            Some(Logo::Url(url)) => Some(LogoInfo::Url(url)),
+            Some(Logo::Embedded(_)) => {
+                return Err(ContractError::Std(StdError::generic_err(
+                    "Embedded logos are not supported",
+                )))
+            }
+            None => None,
+        };
+        MARKETING_INFO.save(
+            deps.storage,
+            &MarketingInfoResponse {
+                project: marketing.project,
+                description: marketing.description,
+                marketing: marketing
+                    .marketing
+                    .map(|addr| deps.api.addr_validate(&addr))
+                    .transpose()?,
+                logo,
+            },
+        )?;
+    }
+
     // TODO upgrade checks
 
-    // let res = execute_register_ica(
-    //     deps.storage,
-    //     env,
-    //     OSMOSIS_CONNECTION_ID.to_string(),
-    //     OSMOSIS_IC_ACCOUNT_ID.to_string(),
-    // );
-
-    // if res.is_err() {
-    //     // TODO, prly do nothing as account exists
-    //     deps.api.debug("WASMDEBUG: failed to add ICA for Osmosis");
-    //     return Err(NeutronError::Std(StdError::generic_err(
-    //         "Failed to add ICA for Osmosis",
-    //     )));
-    // }
+    // operations on the home chain wait for the open ack of its interchain account
+    let register = execute_register_ica(
+        deps.storage,
+        env,
+        home_chain.connection_id,
+        home_chain.interchain_account_id,
+    )?;
+    resp = resp.add_submessages(register.messages);
 
-    Ok(Response::default())
+    Ok(resp)
 }
 
 pub fn execute_mint(
@@ -114,48 +308,31 @@ pub fn execute_mint(
     info: MessageInfo,
     recipient: String,
     amount: Uint128,
-) -> Result<Response, NeutronError> {
-    let mut config =
-        TOKEN_INFO
-            .may_load(deps.storage)?
-            .ok_or(NeutronError::Std(StdError::NotFound {
-                kind: "TokenInfo".to_string(),
-            }))?;
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = TOKEN_INFO
+        .may_load(deps.storage)?
+        .ok_or(ContractError::Std(StdError::NotFound {
+            kind: "TokenInfo".to_string(),
+        }))?;
 
     if config
         .mint
         .as_ref()
-        .ok_or(NeutronError::Std(StdError::NotFound {
+        .ok_or(ContractError::Std(StdError::NotFound {
             kind: "config.mint".to_string(),
         }))?
         .minter
         != info.sender
     {
-        return Err(NeutronError::Std(StdError::GenericErr {
-            msg: "Unauthorized".to_string(),
-        }));
-    }
-
-    // update supply and enforce cap
-    config.total_supply += amount;
-    if let Some(limit) = config.get_cap() {
-        if config.total_supply > limit {
-            return Err(NeutronError::Std(StdError::GenericErr {
-                msg: "Cannot Exceed Cap".to_string(),
-            }));
-        }
+        return Err(ContractError::Unauthorized);
     }
-    TOKEN_INFO.save(deps.storage, &config)?;
 
-    // add amount to recipient balance
+    // update supply, enforce cap and mint in the configured backend
     let rcpt_addr = deps.api.addr_validate(&recipient)?;
-    BALANCES.update(
-        deps.storage,
-        rcpt_addr,
-        |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
-    )?;
+    let msgs = mint_shares(deps.storage, &rcpt_addr, amount)?;
 
     let res = Response::new()
+        .add_messages(msgs)
         .add_attribute("action", "mint")
         .add_attribute("to", recipient)
         .add_attribute("amount", amount);
@@ -168,34 +345,45 @@ pub fn execute_fund(
     deps: &mut DepsMut<NeutronQuery>,
     env: Env,
     info: MessageInfo,
-) -> NeutronResult<Response<NeutronMsg>> {
-    let funds = info.funds.get(0);
-    if funds.is_none() {
-        return Err(NeutronError::Std(StdError::generic_err(
-            "No funds sent to contract",
-        )));
-    }
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_not_paused(deps.storage)?;
+
+    // other coins sent along would be kept without a deposit
+    let funds = match info.funds.as_slice() {
+        [] => return Err(ContractError::NoFunds),
+        [funds] => funds.clone(),
+        _ => {
+            return Err(ContractError::InvalidFunds {
+                expected: "exactly one coin".to_string(),
+            })
+        }
+    };
 
-    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee);
-    let (ica, connection_id) = get_ica(deps.as_ref(), &env, OSMOSIS_IC_ACCOUNT_ID)?;
+    let config = CONFIG.load(deps.storage)?;
+    let home = HOST_CHAINS.load(deps.storage, HOME_CHAIN_ID)?;
+    // the deposit is swapped and refunded by its denom on the home chain
+    host_denom(&home, &funds.denom)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &home.interchain_account_id)?;
+
+    let deposit = create_deposit(deps.storage, info.sender, funds.clone(), env.block.time)?;
 
     let deploy_msg: MsgTransfer = MsgTransfer {
         sender: env.contract.address.to_string(),
         receiver: ica,
         token: Some(cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
-            denom: funds.unwrap().denom.clone(),
-            amount: funds.unwrap().amount.clone().to_string(),
+            denom: funds.denom.clone(),
+            amount: funds.amount.to_string(),
         }),
-        source_channel: "channel-186".to_string(),
+        source_channel: home.transfer_channel.clone(),
         source_port: "transfer".to_string(),
         timeout_height: None,
-        timeout_timestamp: 0,
+        timeout_timestamp: env.block.time.plus_seconds(DEFAULT_TIMEOUT_SECONDS).nanos(),
     };
-    let mut buf = Vec::new();
-    buf.reserve(deploy_msg.encoded_len());
+    let mut buf = Vec::with_capacity(deploy_msg.encoded_len());
 
     if let Err(e) = deploy_msg.encode(&mut buf) {
-        return Err(NeutronError::Std(StdError::generic_err(format!(
+        return Err(ContractError::Std(StdError::generic_err(format!(
             "Encode error: {}",
             e
         ))));
@@ -208,7 +396,7 @@ pub fn execute_fund(
 
     let cosmos_msg = NeutronMsg::submit_tx(
         connection_id,
-        OSMOSIS_IC_ACCOUNT_ID.to_string(),
+        home.interchain_account_id.clone(),
         vec![any_msg],
         "".to_string(),
         DEFAULT_TIMEOUT_SECONDS,
@@ -221,55 +409,41 @@ pub fn execute_fund(
         deps,
         cosmos_msg,
         SudoPayload {
-            port_id: get_port_id(env.contract.address.as_str(), OSMOSIS_IC_ACCOUNT_ID),
-            message: "message".to_string(),
+            port_id: get_port_id(env.contract.address.as_str(), &home.interchain_account_id),
             sender: env.contract.address.to_string(),
-            executor: "execute_fund_to_osmosis".to_string(),
-            amount: Some(funds.unwrap().amount.clone()),
+            operation: SudoOperation::TransferDeposit {
+                deposit_id: deposit.id,
+                amount: funds.amount,
+            },
         },
     )?;
 
-    Ok(Response::default().add_submessages(vec![submsg]))
+    Ok(Response::default()
+        .add_submessages(vec![submsg])
+        .add_attribute("action", "fund")
+        .add_attribute("deposit_id", deposit.id.to_string()))
 }
 
-// contract deploys money from osmosis to redbank
+// contract deploys money from osmosis to the strategies
 pub fn execute_deploy(
     deps: &mut DepsMut<NeutronQuery>,
     env: Env,
-    sender: String,
+    deposit_id: u64,
     value: Uint128,
-) -> NeutronResult<Response<NeutronMsg>> {
-    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee);
-    let (ica, connection_id) = get_ica(deps.as_ref(), &env, OSMOSIS_IC_ACCOUNT_ID)?;
-
-    let deploy_msg = MsgExecuteContract {
-        sender: ica,
-        contract: REDBANK_OSMOSIS_ADDR.to_string(),
-        msg: "{\"deposit\":{}}".to_string().into(),
-        funds: vec![osmosis_std::types::cosmos::base::v1beta1::Coin {
-            denom: "uusdc".to_string(),
-            amount: value.to_string(),
-        }],
-    };
-    let mut buf = Vec::new();
-    buf.reserve(deploy_msg.encoded_len());
+) -> ContractResult<Response<NeutronMsg>> {
+    let deposit = DEPOSITS.load(deps.storage, deposit_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let home = HOST_CHAINS.load(deps.storage, HOME_CHAIN_ID)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &home.interchain_account_id)?;
 
-    if let Err(e) = deploy_msg.encode(&mut buf) {
-        return Err(NeutronError::Std(StdError::generic_err(format!(
-            "Encode error: {}",
-            e
-        ))));
-    }
-
-    let any_msg = ProtobufAny {
-        type_url: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
-        value: Binary::from(buf),
-    };
+    let allocations = allocate_deposit(deps.storage, HOME_CHAIN_ID, value)?;
+    let any_msgs = allocation_msgs(deps.storage, &ica, &allocations)?;
 
     let cosmos_msg = NeutronMsg::submit_tx(
         connection_id,
-        OSMOSIS_IC_ACCOUNT_ID.to_string(),
-        vec![any_msg],
+        home.interchain_account_id.clone(),
+        any_msgs,
         "".to_string(),
         DEFAULT_TIMEOUT_SECONDS,
         fee,
@@ -281,11 +455,13 @@ pub fn execute_deploy(
         deps,
         cosmos_msg,
         SudoPayload {
-            port_id: get_port_id(env.contract.address.as_str(), OSMOSIS_IC_ACCOUNT_ID),
-            message: "message".to_string(),
-            sender: sender.to_string(),
-            executor: "execute_fund".to_string(),
-            amount: Some(value),
+            port_id: get_port_id(env.contract.address.as_str(), &home.interchain_account_id),
+            sender: deposit.depositor.to_string(),
+            operation: SudoOperation::DeployDeposit {
+                deposit_id,
+                amount: value,
+                allocations,
+            },
         },
     )?;
 
@@ -296,149 +472,222 @@ pub fn execute_deploy(
 fn execute_tokens_to_user(
     deps: &mut DepsMut<NeutronQuery>,
     env: Env,
-    sender: String,
+    deposit_id: u64,
     value: Uint128,
-) -> NeutronResult<Response<NeutronMsg>> {
-    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+) -> ContractResult<Response<NeutronMsg>> {
+    update_deposit(deps.storage, deposit_id, env.block.time, |deposit| {
+        deposit.deployed = Some(value);
+        deposit.stage = DepositStage::AwaitingPrice;
+    })?;
+
+    // shares are only priced from a fresh NAV, otherwise the deposit is completed later
+    let nav_max_age = CONFIG.load(deps.storage)?.nav_max_age;
+    if ensure_nav_fresh(deps.storage, env.block.time, nav_max_age).is_err() {
+        return Ok(Response::new()
+            .add_attribute("action", "await_price")
+            .add_attribute("deposit_id", deposit_id.to_string()));
+    }
+    execute_complete_deposit(deps, env, deposit_id)
+}
+
+// issues the shares of a deployed deposit at the current price
+pub fn execute_complete_deposit(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    deposit_id: u64,
+) -> ContractResult<Response<NeutronMsg>> {
+    let deposit = DEPOSITS.load(deps.storage, deposit_id)?;
+    if deposit.stage != DepositStage::AwaitingPrice {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Deposit {} is not awaiting a price",
+            deposit_id
+        ))));
+    }
+    let config = CONFIG.load(deps.storage)?;
+    ensure_nav_fresh(deps.storage, env.block.time, config.nav_max_age)?;
+
+    let value = deposit
+        .deployed
+        .ok_or_else(|| StdError::generic_err("deployed amount not found"))?;
+    let fees = accrue_fees(deps.storage, env.block.time)?;
+    let supply = TOKEN_INFO.load(deps.storage)?.total_supply;
     let mut total_value = TOTAL_VALUE.load(deps.storage)?;
 
-    let issue: Uint128 = supply / total_value * value;
+    let issue = value_to_shares(value, supply, total_value)?;
 
+    // the deployed funds belong to share holders from now on
     total_value += value;
-    supply += issue;
-
-    TOTAL_SUPPLY.save(deps.storage, &supply)?;
     TOTAL_VALUE.save(deps.storage, &total_value)?;
+    release_value(deps.storage, deposit.swapped.unwrap_or(value))?;
 
-    // call into cw20-base to mint the token, call as self as no one else is allowed
+    let (entry_fee, entry_fee_res) =
+        charge_entry_fee(deps.storage, &config, &deposit.depositor, issue)?;
+    let deposit = update_deposit(deps.storage, deposit_id, env.block.time, |deposit| {
+        deposit.shares = Some(issue - entry_fee);
+        deposit.fee = Some(entry_fee);
+        deposit.stage = DepositStage::Completed;
+    })?;
+
+    // mint the shares, call as self as no one else is allowed
     let sub_info = MessageInfo {
         sender: env.contract.address.clone(),
         funds: vec![],
     };
-    execute_mint(deps, env, sub_info, sender, issue)?;
-
-    Ok(Response::default())
+    let res = execute_mint(
+        deps,
+        env,
+        sub_info,
+        deposit.depositor.to_string(),
+        issue - entry_fee,
+    )?;
+    Ok(res
+        .add_submessages(fees.messages)
+        .add_events(fees.events)
+        .add_submessages(entry_fee_res.messages)
+        .add_events(entry_fee_res.events))
 }
 
 // contract swaps user provided liquidity to USDC
 pub fn execute_ic_swap(
-    mut deps: DepsMut<NeutronQuery>,
+    deps: &mut DepsMut<NeutronQuery>,
     env: Env,
-    info: MessageInfo,
+    deposit_id: u64,
+    coin: Coin,
     timeout: Option<u64>,
-) -> Result<Response<NeutronMsg>, NeutronError> {
-    let coin = info.funds.get(0);
-    if let Some(coin) = coin {
-        let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee);
-        let (ica, connection_id) = get_ica(deps.as_ref(), &env, OSMOSIS_IC_ACCOUNT_ID)?;
-        let swap_msg = SwapExactAmountIn {
-            sender: ica,
-            token_in: Some(osmosis_std::types::cosmos::base::v1beta1::Coin {
-                denom: coin.denom.clone(),
-                amount: coin.amount.to_string(),
-            }),
-            token_out_denom: "uusdc".to_string(),
-            token_out_min_amount: (coin.amount / Uint128::from(100u128) * Uint128::from(95u128))
-                .to_string(),
-            swap_fee: "10".to_string(),
-        };
-        let mut buf = Vec::new();
-        buf.reserve(swap_msg.encoded_len());
+) -> ContractResult<Response<NeutronMsg>> {
+    let deposit = DEPOSITS.load(deps.storage, deposit_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let home = HOST_CHAINS.load(deps.storage, HOME_CHAIN_ID)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &home.interchain_account_id)?;
+    let min_out = coin.amount * (Decimal::one() - config.max_slippage);
+    let token_in = Coin::new(coin.amount.u128(), host_denom(&home, &coin.denom)?);
+    let any_msg = swap_msg(ica, token_in, &home.remote_denom, min_out)?;
 
-        if let Err(e) = swap_msg.encode(&mut buf) {
-            return Err(NeutronError::Std(StdError::generic_err(format!(
-                "Encode error: {}",
-                e
-            ))));
-        }
+    let cosmos_msg = NeutronMsg::submit_tx(
+        connection_id,
+        home.interchain_account_id.clone(),
+        vec![any_msg],
+        "".to_string(),
+        timeout.unwrap_or(DEFAULT_TIMEOUT_SECONDS),
+        fee,
+    );
 
-        let any_msg = ProtobufAny {
-            type_url: "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn".to_string(),
-            value: Binary::from(buf),
-        };
+    // We use a submessage here because we need the process message reply to save
+    // the outgoing IBC packet identifier for later.
+    let submsg = msg_with_sudo_callback(
+        deps,
+        cosmos_msg,
+        SudoPayload {
+            port_id: get_port_id(env.contract.address.as_str(), &home.interchain_account_id),
+            sender: deposit.depositor.to_string(),
+            operation: SudoOperation::SwapDeposit {
+                deposit_id,
+                min_out,
+            },
+        },
+    )?;
 
-        let cosmos_msg = NeutronMsg::submit_tx(
-            connection_id,
-            OSMOSIS_IC_ACCOUNT_ID.clone().to_string(),
-            vec![any_msg],
-            "".to_string(),
-            timeout.unwrap_or(DEFAULT_TIMEOUT_SECONDS),
-            fee,
-        );
+    Ok(Response::default().add_submessages(vec![submsg]))
+}
 
-        // We use a submessage here because we need the process message reply to save
-        // the outgoing IBC packet identifier for later.
-        let submsg = msg_with_sudo_callback(
-            &mut deps,
-            cosmos_msg,
-            SudoPayload {
-                port_id: get_port_id(env.contract.address.as_str(), OSMOSIS_IC_ACCOUNT_ID),
-                message: "message".to_string(),
-                sender: info.sender.to_string(),
-                executor: "swap_osmosis".to_string(),
-                amount: None,
-            },
-        )?;
+// swap of `token_in` held by the interchain account on the home chain to `token_out_denom`
+fn swap_msg(
+    ica: String,
+    token_in: Coin,
+    token_out_denom: &str,
+    min_out: Uint128,
+) -> ContractResult<ProtobufAny> {
+    let swap_msg = SwapExactAmountIn {
+        sender: ica,
+        token_in: Some(osmosis_std::types::cosmos::base::v1beta1::Coin {
+            denom: token_in.denom,
+            amount: token_in.amount.to_string(),
+        }),
+        token_out_denom: token_out_denom.to_string(),
+        token_out_min_amount: min_out.to_string(),
+        swap_fee: "10".to_string(),
+    };
+    let mut buf = Vec::with_capacity(swap_msg.encoded_len());
 
-        Ok(Response::default().add_submessages(vec![submsg]))
-    } else {
-        Err(NeutronError::Std(StdError::generic_err(
-            "No funds sent to contract",
-        )))
+    if let Err(e) = swap_msg.encode(&mut buf) {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Encode error: {}",
+            e
+        ))));
     }
+
+    Ok(ProtobufAny {
+        type_url: "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn".to_string(),
+        value: Binary::from(buf),
+    })
 }
 
-// user starts to claim money for their LP tokens
-pub fn execute_claim(
+// keeper claims the Red Bank incentives of the interchain account and swaps the rewards it holds
+// to the target denom in one interchain tx, the swap output is supplied to the strategies once
+// acknowledged. The claim acknowledgement carries no amount, so the swapped amount is the reward
+// balance proven by the reward query. Rewards claimed here are swapped by the next harvest once
+// the query proved them.
+pub fn execute_harvest(
     deps: &mut DepsMut<NeutronQuery>,
     env: Env,
-    info: MessageInfo,
-    amount: Uint128,
-) -> Result<Response<NeutronMsg>, NeutronError> {
-    let balance = BALANCES.load(deps.storage, info.sender.clone())?;
-    if balance < amount {
-        return Err(NeutronError::Std(StdError::generic_err(
-            "Cannot claim more than you have",
-        )));
-    }
-
-    let total_supply = TOTAL_SUPPLY.load(deps.storage)?;
-    let total_value = TOTAL_VALUE.load(deps.storage)?;
-    let value = total_supply / total_value * amount;
+    min_out: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(deps.storage)?;
+    let (incentives_addr, reward_denom) = match (&config.incentives_addr, &config.reward_denom) {
+        (Some(incentives_addr), Some(reward_denom)) => {
+            (incentives_addr.clone(), reward_denom.clone())
+        }
+        _ => {
+            return Err(ContractError::Std(StdError::generic_err(
+                "Harvesting is not configured",
+            )))
+        }
+    };
 
-    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee);
-    let (ica, connection_id) = get_ica(deps.as_ref(), &env, OSMOSIS_IC_ACCOUNT_ID)?;
+    let home = HOST_CHAINS.load(deps.storage, HOME_CHAIN_ID)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &home.interchain_account_id)?;
 
-    // we start withdrawing the claimable amount from redbank
     let claim_msg = MsgExecuteContract {
-        sender: ica,
-        contract: REDBANK_OSMOSIS_ADDR.to_string(),
-        msg: "{\"withdraw\":{}}".to_string().into(),
-        funds: vec![osmosis_std::types::cosmos::base::v1beta1::Coin {
-            denom: "uusdc".to_string(),
-            amount: value.to_string(),
-        }],
+        sender: ica.clone(),
+        contract: incentives_addr,
+        msg: "{\"claim_rewards\":{}}".to_string().into(),
+        funds: vec![],
     };
-
-    let mut buf = Vec::new();
-    buf.reserve(claim_msg.encoded_len());
+    let mut buf = Vec::with_capacity(claim_msg.encoded_len());
 
     if let Err(e) = claim_msg.encode(&mut buf) {
-        return Err(NeutronError::Std(StdError::generic_err(format!(
+        return Err(ContractError::Std(StdError::generic_err(format!(
             "Encode error: {}",
             e
         ))));
     }
 
-    let any_msg = ProtobufAny {
+    let mut any_msgs = vec![ProtobufAny {
         type_url: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
         value: Binary::from(buf),
-    };
+    }];
+    // a proven balance is swapped once, a result proven before an earlier swap fails the tx
+    // on the host and the rewards are taken by a later harvest
+    let mut nav = NAV
+        .may_load(deps.storage, HOME_CHAIN_ID)?
+        .unwrap_or_default();
+    let reward_amount = nav.reward_balance.take().unwrap_or_default();
+    if !reward_amount.is_zero() {
+        any_msgs.push(swap_msg(
+            ica,
+            Coin::new(reward_amount.u128(), reward_denom),
+            &home.remote_denom,
+            min_out,
+        )?);
+        NAV.save(deps.storage, HOME_CHAIN_ID, &nav)?;
+    }
 
     let cosmos_msg = NeutronMsg::submit_tx(
         connection_id,
-        OSMOSIS_IC_ACCOUNT_ID.to_string(),
-        vec![any_msg],
+        home.interchain_account_id.clone(),
+        any_msgs,
         "".to_string(),
         DEFAULT_TIMEOUT_SECONDS,
         fee,
@@ -450,163 +699,1731 @@ pub fn execute_claim(
         deps,
         cosmos_msg,
         SudoPayload {
-            port_id: get_port_id(env.contract.address.as_str(), OSMOSIS_IC_ACCOUNT_ID),
-            message: "message".to_string(),
-            sender: info.sender.to_string(),
-            executor: "execute_claim".to_string(),
-            amount: Some(value),
+            port_id: get_port_id(env.contract.address.as_str(), &home.interchain_account_id),
+            sender: env.contract.address.to_string(),
+            operation: SudoOperation::Harvest {
+                reward_amount,
+                min_out,
+            },
         },
     )?;
 
-    Ok(Response::default().add_submessages(vec![submsg]))
+    Ok(Response::default()
+        .add_submessages(vec![submsg])
+        .add_attribute("action", "harvest")
+        .add_attribute("reward_amount", reward_amount))
 }
 
-#[entry_point]
-pub fn execute(
-    deps: DepsMut<NeutronQuery>,
+// user locks shares into a withdrawal ticket of the current epoch
+pub fn execute_withdraw(
+    deps: &mut DepsMut<NeutronQuery>,
     env: Env,
     info: MessageInfo,
-    msg: ExecuteMsg,
-) -> NeutronResult<Response<NeutronMsg>> {
-    deps.api
-        .debug(format!("WASMDEBUG: execute: received msg: {:?}", msg).as_str());
-    match msg {
-        ExecuteMsg::Register {
-            connection_id,
-            interchain_account_id,
-        } => execute_register_ica(deps.storage, env, connection_id, interchain_account_id),
-        ExecuteMsg::Fund {} => execute_fund(deps, env, info),
+    shares: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    if shares.is_zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Cannot withdraw zero shares",
+        )));
     }
-}
-
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps<NeutronQuery>, env: Env, msg: QueryMsg) -> NeutronResult<Binary> {
-    match msg {
-        QueryMsg::InterchainAccountAddress {
-            interchain_account_id,
-            connection_id,
-        } => query_interchain_address(deps, env, interchain_account_id, connection_id),
-        QueryMsg::InterchainAccountAddressFromContract {
-            interchain_account_id,
-        } => query_interchain_address_contract(deps, env, interchain_account_id),
-        QueryMsg::AcknowledgementResult {
-            interchain_account_id,
-            sequence_id,
-        } => query_acknowledgement_result(deps, env, interchain_account_id, sequence_id),
-        QueryMsg::ErrorsQueue {} => query_errors_queue(deps),
-        QueryMsg::Supply {} => query_supply(deps),
-        QueryMsg::Balance { addr } => query_balance(deps, addr),
+    ensure_redeemable(deps.storage, &info, shares)?;
+    let fees = accrue_fees(deps.storage, env.block.time)?;
+
+    // served right away if the buffer on Neutron covers it and the price is fresh,
+    // otherwise the shares are priced when the epoch is processed
+    let config = CONFIG.load(deps.storage)?;
+    let fresh = ensure_nav_fresh(deps.storage, env.block.time, config.nav_max_age).is_ok();
+    let total_supply = TOKEN_INFO.load(deps.storage)?.total_supply;
+    let total_value = TOTAL_VALUE.load(deps.storage)?;
+    let value = shares_to_value(shares, total_supply, total_value)?;
+    let buffer = BUFFER.load(deps.storage)?;
+    if fresh && !value.is_zero() && value <= buffer {
+        let burn_msgs = burn_shares(deps.storage, &info.sender, shares)?;
+        TOTAL_VALUE.save(deps.storage, &(total_value - value))?;
+        BUFFER.save(deps.storage, &(buffer - value))?;
+
+        let (exit_fee, exit_fee_res) = charge_exit_fee(&config, &info.sender, value);
+        return Ok(Response::new()
+            .add_submessages(fees.messages)
+            .add_events(fees.events)
+            .add_messages(burn_msgs)
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: vec![Coin::new((value - exit_fee).u128(), config.local_denom)],
+            }))
+            .add_submessages(exit_fee_res.messages)
+            .add_events(exit_fee_res.events)
+            .add_attribute("action", "withdraw")
+            .add_attribute("instant", "true")
+            .add_attribute("shares", shares)
+            .add_attribute("value", value)
+            .add_attribute("exit_fee", exit_fee));
     }
-}
 
-// returns ICA address from Neutron ICA SDK module
-pub fn query_interchain_address(
-    deps: Deps<NeutronQuery>,
-    env: Env,
-    interchain_account_id: String,
-    connection_id: String,
-) -> NeutronResult<Binary> {
-    let query = NeutronQuery::InterchainAccountAddress {
-        owner_address: env.contract.address.to_string(),
-        interchain_account_id,
-        connection_id,
-    };
+    lock_shares(deps.storage, &info.sender, &env.contract.address, shares)?;
 
-    let res: QueryInterchainAccountAddressResponse = deps.querier.query(&query.into())?;
-    Ok(to_binary(&res)?)
-}
+    let ticket = create_ticket(deps.storage, info.sender, shares, env.block.time)?;
 
-// returns ICA address from the contract storage. The address was saved in sudo_open_ack method
-pub fn query_interchain_address_contract(
-    deps: Deps<NeutronQuery>,
-    env: Env,
-    interchain_account_id: String,
-) -> NeutronResult<Binary> {
-    Ok(to_binary(&get_ica(deps, &env, &interchain_account_id)?)?)
+    Ok(Response::new()
+        .add_submessages(fees.messages)
+        .add_events(fees.events)
+        .add_attribute("action", "withdraw")
+        .add_attribute("instant", "false")
+        .add_attribute("ticket_id", ticket.id.to_string())
+        .add_attribute("epoch", ticket.epoch.to_string())
+        .add_attribute("shares", shares))
 }
 
-// returns the result
-pub fn query_acknowledgement_result(
-    deps: Deps<NeutronQuery>,
+// keeper closes the current epoch: its shares are burnt at the current price and the
+// value is withdrawn from the strategies in one go
+pub fn execute_process_withdrawals(
+    deps: &mut DepsMut<NeutronQuery>,
     env: Env,
-    interchain_account_id: String,
-    sequence_id: u64,
-) -> NeutronResult<Binary> {
-    let port_id = get_port_id(env.contract.address.as_str(), &interchain_account_id);
-    let res = ACKNOWLEDGEMENT_RESULTS.may_load(deps.storage, (port_id, sequence_id))?;
-    Ok(to_binary(&res)?)
-}
-
-pub fn query_errors_queue(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
-    let res = read_errors_from_queue(deps.storage)?;
-    Ok(to_binary(&res)?)
-}
+) -> ContractResult<Response<NeutronMsg>> {
+    let mut epoch = EPOCHS.load(deps.storage, CURRENT_EPOCH.load(deps.storage)?)?;
+    if epoch.shares.is_zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "No withdrawals to process",
+        )));
+    }
 
-pub fn query_supply(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
-    let supply = TOTAL_SUPPLY.load(deps.storage)?;
-    let value = TOTAL_VALUE.load(deps.storage)?;
-    let res = to_binary(&[&supply, &value])?;
-    Ok(res)
-}
+    let nav_max_age = CONFIG.load(deps.storage)?.nav_max_age;
+    ensure_nav_fresh(deps.storage, env.block.time, nav_max_age)?;
+    let fees = accrue_fees(deps.storage, env.block.time)?;
 
-pub fn query_balance(deps: Deps<NeutronQuery>, addr: String) -> NeutronResult<Binary> {
-    let res = BALANCES.load(deps.storage, Addr::unchecked(addr))?;
-    Ok(to_binary(&res)?)
-}
+    let total_supply = TOKEN_INFO.load(deps.storage)?.total_supply;
+    let total_value = TOTAL_VALUE.load(deps.storage)?;
+    let value = shares_to_value(epoch.shares, total_supply, total_value)?;
 
-// saves payload to process later to the storage and returns a SubmitTX Cosmos SubMsg with necessary reply id
-fn msg_with_sudo_callback<C: Into<CosmosMsg<T>>, T>(
-    deps: &mut DepsMut<NeutronQuery>,
-    msg: C,
-    payload: SudoPayload,
-) -> StdResult<SubMsg<T>> {
-    save_reply_payload(deps.storage, payload)?;
-    Ok(SubMsg::reply_on_success(msg, SUDO_PAYLOAD_REPLY_ID))
-}
+    // the value stays on the host chain until it is sent back but belongs to the tickets
+    let burn_msgs = burn_shares(deps.storage, &env.contract.address, epoch.shares)?;
+    TOTAL_VALUE.save(deps.storage, &(total_value - value))?;
+    reserve_value(deps.storage, value)?;
 
-// register ICAs
-fn execute_register_ica(
-    storage: &mut dyn Storage,
-    env: Env,
-    connection_id: String,
-    interchain_account_id: String,
-) -> NeutronResult<Response<NeutronMsg>> {
-    let register =
-        NeutronMsg::register_interchain_account(connection_id, interchain_account_id.clone());
-    let key = get_port_id(env.contract.address.as_str(), &interchain_account_id);
+    epoch.value = value;
+    epoch.stage = if value.is_zero() {
+        EpochStage::Claimable
+    } else {
+        EpochStage::Processing
+    };
+    epoch.updated_at = env.block.time;
+    EPOCHS.save(deps.storage, epoch.id, &epoch)?;
+    open_epoch(deps.storage, epoch.id + 1, env.block.time)?;
+
+    let resp = Response::new()
+        .add_submessages(fees.messages)
+        .add_events(fees.events)
+        .add_messages(burn_msgs)
+        .add_attribute("action", "process_withdrawals")
+        .add_attribute("epoch", epoch.id.to_string())
+        .add_attribute("shares", epoch.shares)
+        .add_attribute("value", value);
+    if value.is_zero() {
+        return Ok(resp);
+    }
 
-    // we are saving empty data here because we handle response of registering ICA in sudo_open_ack method
-    INTERCHAIN_ACCOUNTS.save(storage, key, &None)?;
-    Ok(Response::new().add_message(register))
+    let res = execute_withdraw_epoch(deps, env, epoch.id)?;
+    Ok(resp.add_submessages(res.messages))
 }
 
-// contract recalls USDC from osmosis to Neutron
-fn execute_return_funds(
+// interchain account withdraws the value of an epoch from the strategies and sends it back,
+// one host chain at a time until the whole value was returned
+fn execute_withdraw_epoch(
     deps: &mut DepsMut<NeutronQuery>,
     env: Env,
-    sender: String,
-    amount: Uint128,
-) -> NeutronResult<Response<NeutronMsg>> {
-    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee);
-    let (ica, connection_id) = get_ica(deps.as_ref(), &env, OSMOSIS_IC_ACCOUNT_ID)?;
+    epoch_id: u64,
+) -> ContractResult<Response<NeutronMsg>> {
+    let mut epoch = EPOCHS.load(deps.storage, epoch_id)?;
+    if epoch.stage != EpochStage::Processing {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Epoch {} is not processing",
+            epoch_id
+        ))));
+    }
 
-    let deploy_msg: MsgTransfer = MsgTransfer {
-        sender: ica,
-        receiver: env.contract.address.to_string(),
-        token: Some(cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
-            denom: "uusdc".to_string(),
-            amount: amount.to_string(),
-        }),
-        source_channel: "channel-3515".to_string(),
-        source_port: "transfer".to_string(),
+    let config = CONFIG.load(deps.storage)?;
+    let (chain_id, amount) = withdrawal_chain(deps.storage, epoch.value - epoch.returned)?;
+    let chain = HOST_CHAINS.load(deps.storage, &chain_id)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+
+    let (any_msgs, allocations) =
+        withdraw_and_return_msgs(deps.storage, &chain_id, &chain, &env, ica, amount)?;
+
+    let cosmos_msg = NeutronMsg::submit_tx(
+        connection_id,
+        chain.interchain_account_id.clone(),
+        any_msgs,
+        "".to_string(),
+        DEFAULT_TIMEOUT_SECONDS,
+        fee,
+    );
+
+    // We use a submessage here because we need the process message reply to save
+    // the outgoing IBC packet identifier for later.
+    let submsg = msg_with_sudo_callback(
+        deps,
+        cosmos_msg,
+        SudoPayload {
+            port_id: get_port_id(env.contract.address.as_str(), &chain.interchain_account_id),
+            sender: env.contract.address.to_string(),
+            operation: SudoOperation::WithdrawEpoch {
+                epoch_id,
+                chain: chain_id,
+                amount,
+                allocations,
+            },
+        },
+    )?;
+
+    epoch.stage = EpochStage::Withdrawing;
+    epoch.updated_at = env.block.time;
+    EPOCHS.save(deps.storage, epoch_id, &epoch)?;
+
+    Ok(Response::default().add_submessages(vec![submsg]))
+}
+
+// withdrawal of `amount` from the strategies on a host chain and its transfer back to the
+// contract, sent in one interchain tx
+fn withdraw_and_return_msgs(
+    storage: &dyn Storage,
+    chain_id: &str,
+    chain: &HostChain,
+    env: &Env,
+    ica: String,
+    amount: Uint128,
+) -> ContractResult<(Vec<ProtobufAny>, Vec<Allocation>)> {
+    let allocations = allocate_withdrawal(storage, chain_id, amount)?;
+    let mut msgs = allocation_msgs(storage, &ica, &allocations)?;
+    let return_msg: MsgTransfer = MsgTransfer {
+        sender: ica,
+        receiver: env.contract.address.to_string(),
+        token: Some(cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
+            denom: chain.remote_denom.clone(),
+            amount: amount.to_string(),
+        }),
+        source_channel: chain.return_channel.clone(),
+        source_port: "transfer".to_string(),
+        timeout_height: None,
+        timeout_timestamp: env.block.time.plus_seconds(DEFAULT_TIMEOUT_SECONDS).nanos(),
+    };
+
+    let mut return_buf = Vec::with_capacity(return_msg.encoded_len());
+    if let Err(e) = return_msg.encode(&mut return_buf) {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Encode error: {}",
+            e
+        ))));
+    }
+
+    msgs.push(ProtobufAny {
+        type_url: "/ibc.applications.transfer.v1.MsgTransfer".to_string(),
+        value: Binary::from(return_buf),
+    });
+    Ok((msgs, allocations))
+}
+
+// share of the total value the buffer on Neutron should hold
+fn buffer_target(storage: &dyn Storage) -> StdResult<Uint128> {
+    let buffer_ratio = CONFIG.load(storage)?.buffer_ratio;
+    Ok(TOTAL_VALUE.load(storage)? * buffer_ratio)
+}
+
+// keeper moves funds between the strategies and the buffer until it reaches its target
+pub fn execute_rebalance_buffer(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+) -> ContractResult<Response<NeutronMsg>> {
+    let buffer = BUFFER.load(deps.storage)?;
+    let in_transit = buffer_in_transit(deps.storage)?;
+    let target = buffer_target(deps.storage)?;
+
+    let resp = Response::new()
+        .add_attribute("action", "rebalance_buffer")
+        .add_attribute("buffer", buffer)
+        .add_attribute("in_transit", in_transit)
+        .add_attribute("target", target);
+    // top-ups on their way are not sent again
+    if buffer + in_transit < target {
+        let res = execute_top_up_buffer(deps, env, target - buffer - in_transit)?;
+        Ok(resp.add_submessages(res.messages))
+    } else if buffer > target {
+        let res = execute_sweep_buffer(deps, env, buffer - target)?;
+        Ok(resp.add_submessages(res.messages))
+    } else {
+        Err(ContractError::Std(StdError::generic_err(
+            "Buffer is at its target",
+        )))
+    }
+}
+
+// interchain account of the host chain holding the most withdraws from its strategies and sends
+// the funds to the buffer, the buffer is increased once the funds arrived
+fn execute_top_up_buffer(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    amount: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(deps.storage)?;
+    let (chain_id, amount) = withdrawal_chain(deps.storage, amount)?;
+    let chain = HOST_CHAINS.load(deps.storage, &chain_id)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+    let (any_msgs, allocations) =
+        withdraw_and_return_msgs(deps.storage, &chain_id, &chain, &env, ica, amount)?;
+
+    let cosmos_msg = NeutronMsg::submit_tx(
+        connection_id,
+        chain.interchain_account_id.clone(),
+        any_msgs,
+        "".to_string(),
+        DEFAULT_TIMEOUT_SECONDS,
+        fee,
+    );
+
+    // We use a submessage here because we need the process message reply to save
+    // the outgoing IBC packet identifier for later.
+    let submsg = msg_with_sudo_callback(
+        deps,
+        cosmos_msg,
+        SudoPayload {
+            port_id: get_port_id(env.contract.address.as_str(), &chain.interchain_account_id),
+            sender: env.contract.address.to_string(),
+            operation: SudoOperation::TopUpBuffer {
+                chain: chain_id,
+                amount,
+                allocations,
+            },
+        },
+    )?;
+
+    Ok(Response::default().add_submessages(vec![submsg]))
+}
+
+// contract sends the excess of the buffer to the interchain account of the host chain furthest
+// below its weight to be deployed again
+fn execute_sweep_buffer(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    amount: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(deps.storage)?;
+    let chain_id = deposit_chain(deps.storage, amount)?;
+    let chain = HOST_CHAINS.load(deps.storage, &chain_id)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, _) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+
+    BUFFER.update(deps.storage, |buffer| -> StdResult<_> {
+        Ok(buffer.checked_sub(amount)?)
+    })?;
+
+    let transfer_msg = NeutronMsg::IbcTransfer {
+        source_port: "transfer".to_string(),
+        source_channel: chain.transfer_channel.clone(),
+        sender: env.contract.address.to_string(),
+        receiver: ica,
+        token: Coin::new(amount.u128(), config.local_denom.clone()),
+        timeout_height: RequestPacketTimeoutHeight {
+            revision_number: None,
+            revision_height: None,
+        },
+        timeout_timestamp: env.block.time.plus_seconds(DEFAULT_TIMEOUT_SECONDS).nanos(),
+        memo: "".to_string(),
+        fee,
+    };
+
+    // the transfer reply carries the sequence and channel like an interchain tx does
+    let submsg = msg_with_sudo_callback(
+        deps,
+        transfer_msg,
+        SudoPayload {
+            port_id: "transfer".to_string(),
+            sender: env.contract.address.to_string(),
+            operation: SudoOperation::SweepBuffer {
+                chain: chain_id,
+                amount,
+            },
+        },
+    )?;
+
+    Ok(Response::default().add_submessages(vec![submsg]))
+}
+
+// interchain account of a host chain supplies funds it holds to the strategies on the chain,
+// for the swept buffer and harvested rewards. `operation` describes the packet by its allocations.
+fn execute_strategy_supply(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    chain_id: &str,
+    amount: Uint128,
+    operation: impl FnOnce(Vec<Allocation>) -> SudoOperation,
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(deps.storage)?;
+    let chain = HOST_CHAINS.load(deps.storage, chain_id)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+    let allocations = allocate_deposit(deps.storage, chain_id, amount)?;
+    let any_msgs = allocation_msgs(deps.storage, &ica, &allocations)?;
+
+    let cosmos_msg = NeutronMsg::submit_tx(
+        connection_id,
+        chain.interchain_account_id.clone(),
+        any_msgs,
+        "".to_string(),
+        DEFAULT_TIMEOUT_SECONDS,
+        fee,
+    );
+
+    // We use a submessage here because we need the process message reply to save
+    // the outgoing IBC packet identifier for later.
+    let submsg = msg_with_sudo_callback(
+        deps,
+        cosmos_msg,
+        SudoPayload {
+            port_id: get_port_id(env.contract.address.as_str(), &chain.interchain_account_id),
+            sender: env.contract.address.to_string(),
+            operation: operation(allocations),
+        },
+    )?;
+
+    Ok(Response::default().add_submessages(vec![submsg]))
+}
+
+// keeper moves funds from the strategies above their target to the ones below in one
+// interchain tx, on the first host chain with strategies off their target. Only one rebalance
+// is in flight at a time, deposits and withdrawals sent meanwhile are allocated against the
+// rebalanced values.
+pub fn execute_rebalance(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+) -> ContractResult<Response<NeutronMsg>> {
+    if REBALANCE.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::OperationInFlight {
+            operation: "rebalance".to_string(),
+        });
+    }
+    let config = CONFIG.load(deps.storage)?;
+    // the strategy values come from the position query
+    ensure_nav_fresh(deps.storage, env.block.time, config.nav_max_age)?;
+
+    let mut planned = None;
+    for chain_id in strategy_chains(deps.storage)? {
+        if let Some(allocations) =
+            plan_rebalance(deps.storage, &chain_id, config.rebalance_threshold)?
+        {
+            planned = Some((chain_id, allocations));
+            break;
+        }
+    }
+    let (chain_id, allocations) = planned
+        .ok_or_else(|| StdError::generic_err("Strategies are within the rebalance threshold"))?;
+    let chain = HOST_CHAINS.load(deps.storage, &chain_id)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+    let any_msgs = allocation_msgs(deps.storage, &ica, &allocations)?;
+
+    let cosmos_msg = NeutronMsg::submit_tx(
+        connection_id,
+        chain.interchain_account_id.clone(),
+        any_msgs,
+        "".to_string(),
+        DEFAULT_TIMEOUT_SECONDS,
+        fee,
+    );
+
+    // We use a submessage here because we need the process message reply to save
+    // the outgoing IBC packet identifier for later.
+    let submsg = msg_with_sudo_callback(
+        deps,
+        cosmos_msg,
+        SudoPayload {
+            port_id: get_port_id(env.contract.address.as_str(), &chain.interchain_account_id),
+            sender: env.contract.address.to_string(),
+            operation: SudoOperation::Rebalance {
+                chain: chain_id.clone(),
+                allocations: allocations.clone(),
+            },
+        },
+    )?;
+    REBALANCE.save(deps.storage, &allocations)?;
+
+    let mut resp = Response::new()
+        .add_submessage(submsg)
+        .add_attribute("action", "rebalance")
+        .add_attribute("chain", chain_id);
+    for allocation in allocations {
+        let direction = if allocation.withdraw { "from" } else { "to" };
+        resp = resp.add_attribute(
+            format!("{}_{}", direction, allocation.strategy),
+            allocation.amount,
+        );
+    }
+    Ok(resp)
+}
+
+// user claims the value of their tickets once the funds of the epoch arrived
+pub fn execute_claim_withdrawal(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    ticket_ids: Vec<u64>,
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(deps.storage)?;
+    confirm_returns(
+        deps.storage,
+        &deps.querier,
+        &env.contract.address,
+        &config.local_denom,
+        env.block.time,
+    )?;
+
+    let mut amount = Uint128::zero();
+    for ticket_id in ticket_ids.iter() {
+        let ticket = TICKETS.load(deps.storage, *ticket_id)?;
+        if ticket.owner != info.sender {
+            return Err(ContractError::Unauthorized);
+        }
+        let epoch = EPOCHS.load(deps.storage, ticket.epoch)?;
+        if epoch.stage != EpochStage::Claimable {
+            return Err(ContractError::Std(StdError::generic_err(format!(
+                "Ticket {} is not claimable yet",
+                ticket_id
+            ))));
+        }
+
+        amount += ticket_value(&ticket, &epoch);
+        TICKETS.remove(deps.storage, *ticket_id);
+        USER_TICKETS.remove(deps.storage, (&ticket.owner, *ticket_id));
+    }
+    pay_epoch_funds(deps.storage, amount)?;
+
+    let (exit_fee, exit_fee_res) = charge_exit_fee(&config, &info.sender, amount);
+    let mut resp = Response::new()
+        .add_attribute("action", "claim_withdrawal")
+        .add_attribute("amount", amount)
+        .add_attribute("exit_fee", exit_fee);
+    if !(amount - exit_fee).is_zero() {
+        resp = resp.add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![Coin::new((amount - exit_fee).u128(), config.local_denom)],
+        }));
+    }
+    Ok(resp
+        .add_submessages(exit_fee_res.messages)
+        .add_events(exit_fee_res.events))
+}
+
+// credits the funds sent back from the host chains that arrived since their acknowledgement
+pub fn execute_confirm_returns(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+) -> ContractResult<Response<NeutronMsg>> {
+    let local_denom = CONFIG.load(deps.storage)?.local_denom;
+    let confirmed = confirm_returns(
+        deps.storage,
+        &deps.querier,
+        &env.contract.address,
+        &local_denom,
+        env.block.time,
+    )?;
+    Ok(Response::new()
+        .add_attribute("action", "confirm_returns")
+        .add_attribute("confirmed", confirmed.to_string()))
+}
+
+// pro-rata part of the epoch value a ticket can claim, rounding down
+fn ticket_value(ticket: &WithdrawalTicket, epoch: &WithdrawalEpoch) -> Uint128 {
+    epoch.value.multiply_ratio(ticket.shares, epoch.shares)
+}
+
+#[entry_point]
+pub fn execute(
+    mut deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> ContractResult<Response<NeutronMsg>> {
+    deps.api
+        .debug(format!("WASMDEBUG: execute: received msg: {:?}", msg).as_str());
+    match msg {
+        ExecuteMsg::Register {
+            connection_id,
+            interchain_account_id,
+        } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_register_ica(deps.storage, env, connection_id, interchain_account_id)
+        }
+        ExecuteMsg::Fund {} => execute_fund(&mut deps, env, info),
+        ExecuteMsg::UpdateConfig {
+            max_slippage,
+            fee_denom,
+            buffer_ratio,
+            rebalance_threshold,
+            nav_max_age,
+            performance_fee,
+            management_fee,
+            fee_recipient,
+            entry_fee_bps,
+            exit_fee_bps,
+            fee_splits,
+            ack_retention,
+        } => execute_update_config(
+            deps,
+            env,
+            info,
+            max_slippage,
+            fee_denom,
+            buffer_ratio,
+            rebalance_threshold,
+            nav_max_age,
+            performance_fee,
+            management_fee,
+            fee_recipient,
+            entry_fee_bps,
+            exit_fee_bps,
+            fee_splits,
+            ack_retention,
+        ),
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            execute_transfer_ownership(deps, info, new_owner)
+        }
+        ExecuteMsg::AcceptOwnership {} => execute_accept_ownership(deps, info),
+        ExecuteMsg::UpdateRoles { keeper, guardian } => {
+            execute_update_roles(deps, info, keeper, guardian)
+        }
+        ExecuteMsg::Pause {} => execute_set_paused(deps, info, true),
+        ExecuteMsg::Unpause {} => execute_set_paused(deps, info, false),
+        ExecuteMsg::RefundDeposit { deposit_id } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            let deposit = DEPOSITS.load(deps.storage, deposit_id)?;
+            match deposit.stage {
+                DepositStage::Refunding | DepositStage::RefundInFlight => {
+                    execute_refund_deposit(&mut deps, env, deposit_id)
+                }
+                _ => Err(ContractError::Std(StdError::generic_err(format!(
+                    "Deposit {} is not refunding",
+                    deposit_id
+                )))),
+            }
+        }
+        ExecuteMsg::Withdraw { shares } => execute_withdraw(&mut deps, env, info, shares),
+        ExecuteMsg::ProcessWithdrawals {} => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_process_withdrawals(&mut deps, env)
+        }
+        ExecuteMsg::RetryWithdrawals { epoch_id } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_withdraw_epoch(&mut deps, env, epoch_id)
+        }
+        ExecuteMsg::ClaimWithdrawal { ticket_ids } => {
+            execute_claim_withdrawal(&mut deps, env, info, ticket_ids)
+        }
+        ExecuteMsg::ConfirmReturns {} => execute_confirm_returns(&mut deps, env),
+        ExecuteMsg::RebalanceBuffer {} => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_rebalance_buffer(&mut deps, env)
+        }
+        ExecuteMsg::Harvest { min_out } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_harvest(&mut deps, env, min_out)
+        }
+        ExecuteMsg::RegisterNavQueries { chain } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_register_nav_queries(deps, env, chain)
+        }
+        ExecuteMsg::AccrueFees {} => {
+            let res = accrue_fees(deps.storage, env.block.time)?;
+            Ok(res.add_attribute("action", "accrue_fees"))
+        }
+        ExecuteMsg::CompleteDeposit { deposit_id } => {
+            execute_complete_deposit(&mut deps, env, deposit_id)
+        }
+        ExecuteMsg::SetHostChain { id, chain } => {
+            execute_set_host_chain(deps, env, info, id, chain)
+        }
+        ExecuteMsg::ReopenIca { chain } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_reopen_ica(deps.storage, env, chain)
+        }
+        ExecuteMsg::SetStrategy {
+            id,
+            chain,
+            kind,
+            weight,
+            cap,
+        } => execute_set_strategy(deps, env, info, id, chain, kind, weight, cap),
+        ExecuteMsg::Rebalance {} => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_rebalance(&mut deps, env)
+        }
+        ExecuteMsg::PruneState { limit } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_prune_state(deps, limit)
+        }
+        ExecuteMsg::ClearError { id } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_clear_error(deps, id)
+        }
+        ExecuteMsg::RetryError { id } => {
+            ensure_keeper(deps.storage, &info.sender)?;
+            execute_retry_error(&mut deps, env, id)
+        }
+        ExecuteMsg::Transfer { recipient, amount } => {
+            execute_transfer(deps, info, recipient, amount)
+        }
+        ExecuteMsg::Burn { amount } => execute_burn(deps, info, amount),
+        ExecuteMsg::Send {
+            contract,
+            amount,
+            msg,
+        } => execute_send(deps, info, contract, amount, msg),
+        ExecuteMsg::IncreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => execute_increase_allowance(deps, env, info, spender, amount, expires),
+        ExecuteMsg::DecreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => execute_decrease_allowance(deps, env, info, spender, amount, expires),
+        ExecuteMsg::TransferFrom {
+            owner,
+            recipient,
+            amount,
+        } => execute_transfer_from(deps, env, info, owner, recipient, amount),
+        ExecuteMsg::SendFrom {
+            owner,
+            contract,
+            amount,
+            msg,
+        } => execute_send_from(deps, env, info, owner, contract, amount, msg),
+        ExecuteMsg::BurnFrom { owner, amount } => execute_burn_from(deps, env, info, owner, amount),
+    }
+}
+
+// first step of the ownership transfer, the new owner has to accept it
+pub fn execute_transfer_ownership(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+    new_owner: String,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_owner(deps.storage, &info.sender)?;
+
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    let mut roles = ROLES.load(deps.storage)?;
+    roles.pending_owner = Some(new_owner.clone());
+    ROLES.save(deps.storage, &roles)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_ownership")
+        .add_attribute("pending_owner", new_owner))
+}
+
+pub fn execute_accept_ownership(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+) -> ContractResult<Response<NeutronMsg>> {
+    let mut roles = ROLES.load(deps.storage)?;
+    if roles.pending_owner.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized);
+    }
+    roles.owner = info.sender.clone();
+    roles.pending_owner = None;
+    ROLES.save(deps.storage, &roles)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "accept_ownership")
+        .add_attribute("owner", info.sender))
+}
+
+pub fn execute_update_roles(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+    keeper: Option<String>,
+    guardian: Option<String>,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_owner(deps.storage, &info.sender)?;
+
+    let mut roles = ROLES.load(deps.storage)?;
+    if let Some(keeper) = keeper {
+        roles.keeper = deps.api.addr_validate(&keeper)?;
+    }
+    if let Some(guardian) = guardian {
+        roles.guardian = deps.api.addr_validate(&guardian)?;
+    }
+    ROLES.save(deps.storage, &roles)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_roles")
+        .add_attribute("keeper", roles.keeper)
+        .add_attribute("guardian", roles.guardian))
+}
+
+// the guardian can only pause, resuming is left to the owner
+pub fn execute_set_paused(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+    paused: bool,
+) -> ContractResult<Response<NeutronMsg>> {
+    if paused {
+        ensure_guardian(deps.storage, &info.sender)?;
+    } else {
+        ensure_owner(deps.storage, &info.sender)?;
+    }
+    PAUSED.save(deps.storage, &paused)?;
+
+    Ok(Response::new().add_attribute("action", if paused { "pause" } else { "unpause" }))
+}
+
+// owner adds a host chain or updates its connection and channels. The interchain account and
+// the target asset of a chain are fixed once added, its strategies depend on them.
+pub fn execute_set_host_chain(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    chain: HostChain,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_owner(deps.storage, &info.sender)?;
+    validate_host_chain(&id, &chain)?;
+
+    let added = match HOST_CHAINS.may_load(deps.storage, &id)? {
+        Some(existing) => {
+            if existing.interchain_account_id != chain.interchain_account_id
+                || existing.remote_denom != chain.remote_denom
+                || existing.remote_bech32_prefix != chain.remote_bech32_prefix
+            {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "Host chain {} can only change its connection and channels",
+                    id
+                ))));
+            }
+            // the account lives on the connection it was registered on, the chain can not move
+            let key = get_port_id(env.contract.address.as_str(), &chain.interchain_account_id);
+            if existing.connection_id != chain.connection_id
+                && (INTERCHAIN_ACCOUNTS.has(deps.storage, key.clone())
+                    || ICA_STATUS.has(deps.storage, key))
+            {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "Host chain {} can not change its connection once its interchain account is registered",
+                    id
+                ))));
+            }
+            false
+        }
+        None => {
+            let chains = HOST_CHAINS
+                .keys(deps.storage, None, None, Order::Ascending)
+                .count();
+            if chains >= MAX_HOST_CHAINS {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "at most {} host chains are allowed",
+                    MAX_HOST_CHAINS
+                ))));
+            }
+            true
+        }
+    };
+    HOST_CHAINS.save(deps.storage, &id, &chain)?;
+
+    let mut resp = Response::new()
+        .add_attribute("action", "set_host_chain")
+        .add_attribute("id", id)
+        .add_attribute("connection_id", chain.connection_id.clone());
+    // the interchain account of a new chain is opened right away
+    if added {
+        let register = execute_register_ica(
+            deps.storage,
+            env,
+            chain.connection_id,
+            chain.interchain_account_id,
+        )?;
+        resp = resp.add_submessages(register.messages);
+    }
+    Ok(resp)
+}
+
+// owner registers a strategy on a host chain or changes its weight and cap. Strategies are never
+// removed so that in-flight acknowledgements always find theirs, a weight of 0 stops new
+// deposits to it.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_set_strategy(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    chain_id: String,
+    kind: StrategyKind,
+    weight: u32,
+    cap: Option<Uint128>,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_owner(deps.storage, &info.sender)?;
+    let chain = HOST_CHAINS
+        .may_load(deps.storage, &chain_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Unknown host chain {}", chain_id)))?;
+    validate_strategy(&id, &kind, &chain.remote_bech32_prefix)?;
+
+    let mut resp = Response::new()
+        .add_attribute("action", "set_strategy")
+        .add_attribute("id", id.clone())
+        .add_attribute("chain", chain_id.clone())
+        .add_attribute("weight", weight.to_string());
+    // the position query of the chain has to cover new and moved strategies
+    let keys_changed = match STRATEGIES.may_load(deps.storage, &id)? {
+        Some(mut strategy) => {
+            if strategy.chain != chain_id
+                || std::mem::discriminant(&strategy.kind) != std::mem::discriminant(&kind)
+            {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "Strategy {} is already registered with a different kind or chain",
+                    id
+                ))));
+            }
+            // a strategy only moves to a new address while it holds nothing
+            let moved = strategy.kind != kind;
+            if moved && !strategy.value.is_zero() {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "Strategy {} can only change its address while it holds no value",
+                    id
+                ))));
+            }
+            strategy.kind = kind;
+            strategy.weight = weight;
+            strategy.cap = cap;
+            STRATEGIES.save(deps.storage, &id, &strategy)?;
+            moved
+        }
+        None => {
+            if load_strategies(deps.storage)?.len() >= MAX_STRATEGIES {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "at most {} strategies are allowed",
+                    MAX_STRATEGIES
+                ))));
+            }
+            STRATEGIES.save(
+                deps.storage,
+                &id,
+                &StrategyInfo {
+                    chain: chain_id.clone(),
+                    kind,
+                    weight,
+                    value: Uint128::zero(),
+                    cap,
+                },
+            )?;
+            true
+        }
+    };
+    let queries = NAV_QUERIES
+        .may_load(deps.storage, &chain_id)?
+        .unwrap_or_default();
+    if let (true, Some(query_id)) = (keys_changed, queries.position_query_id) {
+        let (ica, _) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+        let keys = position_keys(deps.storage, &chain_id, &ica)?;
+        resp = resp.add_message(NeutronMsg::update_interchain_query(
+            query_id,
+            Some(keys),
+            None,
+            None,
+        )?);
+    }
+    // deposits are deployed on the home chain
+    if chain_strategies(deps.storage, HOME_CHAIN_ID)?
+        .iter()
+        .all(|(_, strategy)| strategy.weight == 0)
+    {
+        return Err(ContractError::Std(StdError::generic_err(
+            "at least one strategy on the home chain needs a positive weight",
+        )));
+    }
+    Ok(resp)
+}
+
+fn fee_recipients(
+    deps: Deps<NeutronQuery>,
+    fee_splits: &[FeeSplit],
+) -> StdResult<Vec<FeeRecipient>> {
+    fee_splits
+        .iter()
+        .map(|split| {
+            Ok(FeeRecipient {
+                address: deps.api.addr_validate(&split.recipient)?,
+                weight: split.weight,
+            })
+        })
+        .collect()
+}
+
+// owner rotates deployment parameters without a code migration
+#[allow(clippy::too_many_arguments)]
+pub fn execute_update_config(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    max_slippage: Option<Decimal>,
+    fee_denom: Option<String>,
+    buffer_ratio: Option<Decimal>,
+    rebalance_threshold: Option<Decimal>,
+    nav_max_age: Option<u64>,
+    performance_fee: Option<Decimal>,
+    management_fee: Option<Decimal>,
+    fee_recipient: Option<String>,
+    entry_fee_bps: Option<u16>,
+    exit_fee_bps: Option<u16>,
+    fee_splits: Option<Vec<FeeSplit>>,
+    ack_retention: Option<u64>,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_owner(deps.storage, &info.sender)?;
+
+    // fees up to now are charged with the old parameters
+    let fees = accrue_fees(deps.storage, env.block.time)?;
+
+    let mut config = CONFIG.load(deps.storage)?;
+    let mut changed = vec![];
+
+    if let Some(max_slippage) = max_slippage {
+        validate_slippage(max_slippage)?;
+        config.max_slippage = max_slippage;
+        changed.push("max_slippage");
+    }
+    if let Some(fee_denom) = fee_denom {
+        if fee_denom.is_empty() {
+            return Err(ContractError::Std(StdError::generic_err(
+                "fee_denom must not be empty",
+            )));
+        }
+        config.fee_denom = fee_denom;
+        changed.push("fee_denom");
+    }
+    if let Some(buffer_ratio) = buffer_ratio {
+        validate_buffer_ratio(buffer_ratio)?;
+        config.buffer_ratio = buffer_ratio;
+        changed.push("buffer_ratio");
+    }
+    if let Some(rebalance_threshold) = rebalance_threshold {
+        validate_rebalance_threshold(rebalance_threshold)?;
+        config.rebalance_threshold = rebalance_threshold;
+        changed.push("rebalance_threshold");
+    }
+    if let Some(nav_max_age) = nav_max_age {
+        validate_nav_max_age(nav_max_age)?;
+        config.nav_max_age = nav_max_age;
+        changed.push("nav_max_age");
+    }
+    if let Some(performance_fee) = performance_fee {
+        config.performance_fee = performance_fee;
+        changed.push("performance_fee");
+    }
+    if let Some(management_fee) = management_fee {
+        config.management_fee = management_fee;
+        changed.push("management_fee");
+    }
+    validate_fees(config.performance_fee, config.management_fee)?;
+    if let Some(fee_recipient) = fee_recipient {
+        config.fee_recipient = deps.api.addr_validate(&fee_recipient)?;
+        changed.push("fee_recipient");
+    }
+    if let Some(entry_fee_bps) = entry_fee_bps {
+        config.entry_fee_bps = entry_fee_bps;
+        changed.push("entry_fee_bps");
+    }
+    if let Some(exit_fee_bps) = exit_fee_bps {
+        config.exit_fee_bps = exit_fee_bps;
+        changed.push("exit_fee_bps");
+    }
+    if let Some(fee_splits) = fee_splits {
+        config.fee_recipients = fee_recipients(deps.as_ref(), &fee_splits)?;
+        changed.push("fee_splits");
+    }
+    if let Some(ack_retention) = ack_retention {
+        validate_ack_retention(ack_retention)?;
+        config.ack_retention = ack_retention;
+        changed.push("ack_retention");
+    }
+    validate_transaction_fees(
+        config.entry_fee_bps,
+        config.exit_fee_bps,
+        &config
+            .fee_recipients
+            .iter()
+            .map(|r| r.weight)
+            .collect::<Vec<_>>(),
+    )?;
+
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_submessages(fees.messages)
+        .add_events(fees.events)
+        .add_attribute("action", "update_config")
+        .add_event(Event::new("update_config").add_attribute("changed", changed.join(","))))
+}
+
+// removes up to `limit` entries of state that is no longer needed: payloads of packets handled
+// before they were removed with their acknowledgement and results above the retention, in that
+// order
+pub fn execute_prune_state(
+    deps: DepsMut<NeutronQuery>,
+    limit: u32,
+) -> ContractResult<Response<NeutronMsg>> {
+    if limit == 0 || limit > MAX_PRUNE_LIMIT {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "limit must be between 1 and {}",
+            MAX_PRUNE_LIMIT
+        ))));
+    }
+
+    // a payload is pending until a result is stored for its packet. the payloads are scanned
+    // from where the last call stopped and pending ones count against the limit too, so a
+    // call never walks more than `limit` entries
+    let cursor = PRUNE_CURSOR.may_load(deps.storage)?;
+    let scanned: Vec<((String, u64), Vec<u8>)> = SUDO_PAYLOAD
+        .range(
+            deps.storage,
+            cursor.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit as usize)
+        .collect::<StdResult<_>>()?;
+    let mut payloads = 0;
+    for (key, data) in &scanned {
+        let payload: SudoPayload = from_binary(&Binary(data.clone()))?;
+        if ACKNOWLEDGEMENT_RESULTS.has(deps.storage, (payload.port_id, key.1)) {
+            SUDO_PAYLOAD.remove(deps.storage, key.clone());
+            payloads += 1;
+        }
+    }
+    // the scan starts over once it reached the last payload
+    match scanned.last() {
+        Some((key, _)) if scanned.len() == limit as usize => {
+            PRUNE_CURSOR.save(deps.storage, key)?
+        }
+        _ => PRUNE_CURSOR.remove(deps.storage),
+    }
+    let results = evict_ack_results(deps.storage, limit - scanned.len() as u32)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "prune_state")
+        .add_attribute("payloads_scanned", scanned.len().to_string())
+        .add_attribute("payloads", payloads.to_string())
+        .add_attribute("ack_results", results.to_string()))
+}
+
+// drops an error from the queue once it was dealt with
+pub fn execute_clear_error(
+    deps: DepsMut<NeutronQuery>,
+    id: u64,
+) -> ContractResult<Response<NeutronMsg>> {
+    ERRORS_QUEUE.load(deps.storage, id)?;
+    ERRORS_QUEUE.remove(deps.storage, id);
+    Ok(Response::new()
+        .add_attribute("action", "clear_error")
+        .add_attribute("id", id.to_string()))
+}
+
+// submits the failed step of an error again and drops the error once that succeeded
+pub fn execute_retry_error(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    id: u64,
+) -> ContractResult<Response<NeutronMsg>> {
+    let entry = ERRORS_QUEUE.load(deps.storage, id)?;
+    let res = match &entry.kind {
+        ErrorKind::RefundDeposit { deposit_id } => execute_refund_deposit(deps, env, *deposit_id)?,
+        ErrorKind::WithdrawEpoch { epoch_id } => execute_withdraw_epoch(deps, env, *epoch_id)?,
+        ErrorKind::DeployHarvest { amount } => {
+            let amount = *amount;
+            reserve_value(deps.storage, amount)?;
+            execute_strategy_supply(deps, env, HOME_CHAIN_ID, amount, |allocations| {
+                SudoOperation::DeployHarvest {
+                    amount,
+                    allocations,
+                }
+            })?
+        }
+        ErrorKind::DeployBuffer { chain, amount } => {
+            execute_strategy_supply(deps, env, chain, *amount, |allocations| {
+                SudoOperation::DeployBuffer {
+                    chain: chain.clone(),
+                    amount: *amount,
+                    allocations,
+                }
+            })?
+        }
+        // the packet is handled again as a whole, a failure leaves the error queued
+        ErrorKind::Response { .. } | ErrorKind::Error { .. } | ErrorKind::Timeout => {
+            match &entry.payload {
+                Some(payload) => handle_packet(
+                    deps.branch(),
+                    env,
+                    &entry.channel,
+                    entry.sequence,
+                    payload,
+                    &entry.kind,
+                )?,
+                None => return Err(ContractError::NotRetryable { id }),
+            }
+        }
+        ErrorKind::MissingPayload => return Err(ContractError::NotRetryable { id }),
+    };
+    ERRORS_QUEUE.remove(deps.storage, id);
+    Ok(res
+        .add_attribute("action", "retry_error")
+        .add_attribute("id", id.to_string()))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps<NeutronQuery>, env: Env, msg: QueryMsg) -> NeutronResult<Binary> {
+    match msg {
+        QueryMsg::InterchainAccountAddress {
+            interchain_account_id,
+            connection_id,
+        } => query_interchain_address(deps, env, interchain_account_id, connection_id),
+        QueryMsg::InterchainAccountAddressFromContract {
+            interchain_account_id,
+        } => query_interchain_address_contract(deps, env, interchain_account_id),
+        QueryMsg::AcknowledgementResult {
+            interchain_account_id,
+            sequence_id,
+        } => query_acknowledgement_result(deps, env, interchain_account_id, sequence_id),
+        QueryMsg::SudoPayload {
+            channel_id,
+            sequence_id,
+        } => query_sudo_payload(deps, channel_id, sequence_id),
+        QueryMsg::ErrorsQueue { start_after, limit } => {
+            query_errors_queue(deps, start_after, limit)
+        }
+        QueryMsg::Supply {} => query_supply(deps),
+        QueryMsg::Balance { addr } => query_balance(deps, addr),
+        QueryMsg::TokenInfo {} => query_token_info(deps),
+        QueryMsg::Allowance { owner, spender } => query_allowance(deps, owner, spender),
+        QueryMsg::AllAccounts { start_after, limit } => {
+            query_all_accounts(deps, start_after, limit)
+        }
+        QueryMsg::AllAllowances {
+            owner,
+            start_after,
+            limit,
+        } => query_all_allowances(deps, owner, start_after, limit),
+        QueryMsg::MarketingInfo {} => query_marketing_info(deps),
+        QueryMsg::Config {} => query_config(deps),
+        QueryMsg::Roles {} => query_roles(deps),
+        QueryMsg::Deposit { id } => query_deposit(deps, id),
+        QueryMsg::DepositsByUser {
+            addr,
+            start_after,
+            limit,
+        } => query_deposits_by_user(deps, addr, start_after, limit),
+        QueryMsg::WithdrawalEpoch { id } => query_withdrawal_epoch(deps, id),
+        QueryMsg::Buffer {} => query_buffer(deps),
+        QueryMsg::PendingWithdrawals {
+            addr,
+            start_after,
+            limit,
+        } => query_pending_withdrawals(deps, addr, start_after, limit),
+        QueryMsg::ClaimableWithdrawals {
+            addr,
+            start_after,
+            limit,
+        } => query_claimable_withdrawals(deps, addr, start_after, limit),
+        QueryMsg::Nav {} => query_nav(deps, env),
+        QueryMsg::FeeState {} => query_fee_state(deps),
+        QueryMsg::Strategies {} => query_strategies(deps),
+        QueryMsg::HostChains {} => query_host_chains(deps, env),
+    }
+}
+
+// returns ICA address from Neutron ICA SDK module
+pub fn query_interchain_address(
+    deps: Deps<NeutronQuery>,
+    env: Env,
+    interchain_account_id: String,
+    connection_id: String,
+) -> NeutronResult<Binary> {
+    let query = NeutronQuery::InterchainAccountAddress {
+        owner_address: env.contract.address.to_string(),
+        interchain_account_id,
+        connection_id,
+    };
+
+    let res: QueryInterchainAccountAddressResponse = deps.querier.query(&query.into())?;
+    Ok(to_binary(&res)?)
+}
+
+// returns ICA address from the contract storage. The address was saved in sudo_open_ack method
+pub fn query_interchain_address_contract(
+    deps: Deps<NeutronQuery>,
+    env: Env,
+    interchain_account_id: String,
+) -> NeutronResult<Binary> {
+    Ok(to_binary(&get_ica(deps, &env, &interchain_account_id)?)?)
+}
+
+// returns the result
+pub fn query_acknowledgement_result(
+    deps: Deps<NeutronQuery>,
+    env: Env,
+    interchain_account_id: String,
+    sequence_id: u64,
+) -> NeutronResult<Binary> {
+    let port_id = get_port_id(env.contract.address.as_str(), &interchain_account_id);
+    let res = ACKNOWLEDGEMENT_RESULTS.may_load(deps.storage, (port_id, sequence_id))?;
+    Ok(to_binary(&res)?)
+}
+
+// returns the payload saved for a packet, None for unknown packets
+pub fn query_sudo_payload(
+    deps: Deps<NeutronQuery>,
+    channel_id: String,
+    sequence_id: u64,
+) -> NeutronResult<Binary> {
+    let payload = read_sudo_payload(deps.storage, channel_id, sequence_id).ok();
+    Ok(to_binary(&payload)?)
+}
+
+// errors of handled packets, oldest first
+pub fn query_errors_queue(
+    deps: Deps<NeutronQuery>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> NeutronResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let res = read_errors_from_queue(deps.storage, start_after, limit)?;
+    Ok(to_binary(&res)?)
+}
+
+pub fn query_supply(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let supply = query_share_supply(deps)?;
+    let value = TOTAL_VALUE.load(deps.storage)?;
+    let res = to_binary(&[&supply, &value])?;
+    Ok(res)
+}
+
+pub fn query_config(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(to_binary(&config)?)
+}
+
+pub fn query_roles(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let roles = ROLES.load(deps.storage)?;
+    Ok(to_binary(&roles)?)
+}
+
+pub fn query_deposit(deps: Deps<NeutronQuery>, id: u64) -> NeutronResult<Binary> {
+    let deposit = DEPOSITS.load(deps.storage, id)?;
+    Ok(to_binary(&deposit)?)
+}
+
+pub fn query_deposits_by_user(
+    deps: Deps<NeutronQuery>,
+    addr: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> NeutronResult<Binary> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let deposits = USER_DEPOSITS
+        .prefix(&addr)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|id| DEPOSITS.load(deps.storage, id?))
+        .collect::<StdResult<Vec<Deposit>>>()?;
+    Ok(to_binary(&deposits)?)
+}
+
+pub fn query_buffer(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    Ok(to_binary(&BufferResponse {
+        amount: BUFFER.load(deps.storage)?,
+        in_transit: buffer_in_transit(deps.storage)?,
+        target: buffer_target(deps.storage)?,
+    })?)
+}
+
+pub fn query_withdrawal_epoch(deps: Deps<NeutronQuery>, id: u64) -> NeutronResult<Binary> {
+    let epoch = EPOCHS.load(deps.storage, id)?;
+    Ok(to_binary(&epoch)?)
+}
+
+// tickets of the user with the epoch they belong to, oldest first
+fn user_tickets<'a>(
+    deps: Deps<'a, NeutronQuery>,
+    addr: &Addr,
+    start_after: Option<u64>,
+) -> impl Iterator<Item = StdResult<(WithdrawalTicket, WithdrawalEpoch)>> + 'a {
+    let start = start_after.map(Bound::exclusive);
+    USER_TICKETS
+        .prefix(addr)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .map(move |id| {
+            let ticket = TICKETS.load(deps.storage, id?)?;
+            let epoch = EPOCHS.load(deps.storage, ticket.epoch)?;
+            Ok((ticket, epoch))
+        })
+}
+
+pub fn query_pending_withdrawals(
+    deps: Deps<NeutronQuery>,
+    addr: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> NeutronResult<Binary> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let tickets = user_tickets(deps, &addr, start_after)
+        .filter(|res| {
+            res.as_ref()
+                .map(|(_, epoch)| epoch.stage != EpochStage::Claimable)
+                .unwrap_or(true)
+        })
+        .take(limit)
+        .map(|res| res.map(|(ticket, _)| ticket))
+        .collect::<StdResult<Vec<WithdrawalTicket>>>()?;
+    Ok(to_binary(&tickets)?)
+}
+
+pub fn query_claimable_withdrawals(
+    deps: Deps<NeutronQuery>,
+    addr: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> NeutronResult<Binary> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let claims = user_tickets(deps, &addr, start_after)
+        .filter(|res| {
+            res.as_ref()
+                .map(|(_, epoch)| epoch.stage == EpochStage::Claimable)
+                .unwrap_or(true)
+        })
+        .take(limit)
+        .map(|res| {
+            res.map(|(ticket, epoch)| ClaimableWithdrawal {
+                ticket_id: ticket.id,
+                epoch: epoch.id,
+                amount: ticket_value(&ticket, &epoch),
+            })
+        })
+        .collect::<StdResult<Vec<ClaimableWithdrawal>>>()?;
+    Ok(to_binary(&claims)?)
+}
+
+pub fn query_nav(deps: Deps<NeutronQuery>, env: Env) -> NeutronResult<Binary> {
+    let chains = HOST_CHAINS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .map(|chain| {
+            let chain = chain?;
+            let queries = NAV_QUERIES
+                .may_load(deps.storage, &chain)?
+                .unwrap_or_default();
+            let nav = NAV.may_load(deps.storage, &chain)?.unwrap_or_default();
+            Ok(ChainNavResponse {
+                chain,
+                balance_query_id: queries.balance_query_id,
+                position_query_id: queries.position_query_id,
+                ica_balance: nav.ica_balance,
+                position: nav.position,
+                reward_query_id: queries.reward_query_id,
+                reward_balance: nav.reward_balance,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let nav_max_age = CONFIG.load(deps.storage)?.nav_max_age;
+    Ok(to_binary(&NavResponse {
+        chains,
+        reserved: RESERVED_VALUE.load(deps.storage)?,
+        total_value: TOTAL_VALUE.load(deps.storage)?,
+        fresh: ensure_nav_fresh(deps.storage, env.block.time, nav_max_age).is_ok(),
+    })?)
+}
+
+pub fn query_fee_state(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let state = FEE_STATE.load(deps.storage)?;
+    Ok(to_binary(&state)?)
+}
+
+pub fn query_strategies(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let strategies = load_strategies(deps.storage)?
+        .into_iter()
+        .map(|(id, info)| StrategyResponse {
+            id,
+            chain: info.chain,
+            kind: info.kind,
+            weight: info.weight,
+            value: info.value,
+            cap: info.cap,
+        })
+        .collect::<Vec<_>>();
+    Ok(to_binary(&strategies)?)
+}
+
+pub fn query_host_chains(deps: Deps<NeutronQuery>, env: Env) -> NeutronResult<Binary> {
+    let chains = HOST_CHAINS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (id, chain) = item?;
+            let key = get_port_id(env.contract.address.as_str(), &chain.interchain_account_id);
+            let interchain_account = INTERCHAIN_ACCOUNTS
+                .may_load(deps.storage, key.clone())?
+                .flatten()
+                .map(|(address, _)| address);
+            Ok(HostChainResponse {
+                id,
+                chain,
+                interchain_account,
+                ica_status: ICA_STATUS.may_load(deps.storage, key)?,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(to_binary(&chains)?)
+}
+
+// saves payload to process later to the storage and returns a SubmitTX Cosmos SubMsg with necessary reply id
+fn msg_with_sudo_callback<C: Into<CosmosMsg<T>>, T>(
+    deps: &mut DepsMut<NeutronQuery>,
+    msg: C,
+    payload: SudoPayload,
+) -> StdResult<SubMsg<T>> {
+    let id = save_reply_payload(deps.storage, payload)?;
+    Ok(SubMsg::reply_on_success(msg, id))
+}
+
+// register ICAs
+fn execute_register_ica(
+    storage: &mut dyn Storage,
+    env: Env,
+    connection_id: String,
+    interchain_account_id: String,
+) -> ContractResult<Response<NeutronMsg>> {
+    let register =
+        NeutronMsg::register_interchain_account(connection_id, interchain_account_id.clone());
+    let key = get_port_id(env.contract.address.as_str(), &interchain_account_id);
+
+    // we are saving empty data here because we handle response of registering ICA in sudo_open_ack method
+    // a registered account keeps its address, registering it again recovers the same one
+    if !INTERCHAIN_ACCOUNTS.has(storage, key.clone()) {
+        INTERCHAIN_ACCOUNTS.save(storage, key.clone(), &None)?;
+    }
+    ICA_STATUS.save(storage, key, &IcaStatus::Registering)?;
+    Ok(Response::new().add_message(register))
+}
+
+// keeper registers the interchain account of a host chain again once a timeout closed its
+// channel, operations on the chain wait for the open ack
+fn execute_reopen_ica(
+    storage: &mut dyn Storage,
+    env: Env,
+    chain_id: String,
+) -> ContractResult<Response<NeutronMsg>> {
+    let chain = HOST_CHAINS
+        .may_load(storage, &chain_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Unknown host chain {}", chain_id)))?;
+    let key = get_port_id(env.contract.address.as_str(), &chain.interchain_account_id);
+    if ICA_STATUS.may_load(storage, key)? != Some(IcaStatus::Closed) {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Interchain account of {} is not closed",
+            chain_id
+        ))));
+    }
+
+    let res = execute_register_ica(
+        storage,
+        env,
+        chain.connection_id,
+        chain.interchain_account_id,
+    )?;
+    Ok(res
+        .add_attribute("action", "reopen_ica")
+        .add_attribute("chain", chain_id))
+}
+
+// registers the interchain queries for the target denom balance of the interchain account of
+// a host chain and its positions in the strategies there, their ids are saved in the reply.
+// On the home chain the reward denom balance is queried as well, it is what harvests swap.
+fn execute_register_nav_queries(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    chain_id: String,
+) -> ContractResult<Response<NeutronMsg>> {
+    let chain = HOST_CHAINS
+        .may_load(deps.storage, &chain_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Unknown host chain {}", chain_id)))?;
+    if NAV_QUERIES.has(deps.storage, &chain_id) {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "NAV queries of {} are already registered",
+            chain_id
+        ))));
+    }
+    if chain_strategies(deps.storage, &chain_id)?.is_empty() {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "No strategies on {}",
+            chain_id
+        ))));
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)?;
+
+    let balance_msg = new_register_balance_query_msg(
+        connection_id.clone(),
+        ica.clone(),
+        chain.remote_denom.clone(),
+        config.nav_update_period,
+    )?;
+    let position_msg = NeutronMsg::register_interchain_query(
+        QueryPayload::KV(position_keys(deps.storage, &chain_id, &ica)?),
+        connection_id.clone(),
+        config.nav_update_period,
+    )?;
+    // the replies save the query ids for this chain
+    NAV_QUERIES.save(deps.storage, &chain_id, &NavQueries::default())?;
+    NAV_QUERY_CHAIN.save(deps.storage, &chain_id)?;
+
+    let mut resp = Response::new()
+        .add_submessage(SubMsg::reply_on_success(
+            balance_msg,
+            REGISTER_BALANCE_QUERY_REPLY_ID,
+        ))
+        .add_submessage(SubMsg::reply_on_success(
+            position_msg,
+            REGISTER_POSITION_QUERY_REPLY_ID,
+        ));
+    if let (HOME_CHAIN_ID, Some(reward_denom)) = (chain_id.as_str(), config.reward_denom) {
+        let reward_msg = new_register_balance_query_msg(
+            connection_id,
+            ica,
+            reward_denom,
+            config.nav_update_period,
+        )?;
+        resp = resp.add_submessage(SubMsg::reply_on_success(
+            reward_msg,
+            REGISTER_REWARD_QUERY_REPLY_ID,
+        ));
+    }
+
+    Ok(resp
+        .add_attribute("action", "register_nav_queries")
+        .add_attribute("chain", chain_id))
+}
+
+// funds of a failed deposit on the home chain and on Neutron, depending on how far it got
+fn deposit_refund_coins(
+    deposit: &Deposit,
+    config: &Config,
+    home: &HostChain,
+) -> StdResult<(Coin, Coin)> {
+    match deposit.swapped {
+        Some(swapped) => Ok((
+            Coin::new(swapped.u128(), home.remote_denom.clone()),
+            Coin::new(swapped.u128(), config.local_denom.clone()),
+        )),
+        None => {
+            let transferred = deposit.transferred.unwrap_or(deposit.input.amount);
+            Ok((
+                Coin::new(transferred.u128(), host_denom(home, &deposit.input.denom)?),
+                Coin::new(transferred.u128(), deposit.input.denom.clone()),
+            ))
+        }
+    }
+}
+
+// denom of a Neutron denom once transferred to `chain`, a voucher of the chain's end of the
+// transfer channel. vouchers of other chains would need their denom trace and are rejected
+fn host_denom(chain: &HostChain, denom: &str) -> StdResult<String> {
+    if denom.starts_with("ibc/") {
+        return Err(StdError::generic_err(format!(
+            "Can not resolve the host denom of {}",
+            denom
+        )));
+    }
+    let hash = Sha256::digest(format!("transfer/{}/{}", chain.return_channel, denom));
+    Ok(format!(
+        "ibc/{}",
+        hash.iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<String>()
+    ))
+}
+
+// refunds a failed deposit depending on the stage it failed at
+fn execute_refund_deposit(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    deposit_id: u64,
+) -> ContractResult<Response<NeutronMsg>> {
+    let deposit = DEPOSITS.load(deps.storage, deposit_id)?;
+    match deposit.stage {
+        // funds never left the contract, send them straight back
+        DepositStage::Transferring => {
+            let refund = deposit.input.clone();
+            update_deposit(deps.storage, deposit_id, env.block.time, |deposit| {
+                deposit.refunded = Some(refund.clone());
+                deposit.stage = DepositStage::Refunded;
+            })?;
+            Ok(Response::new()
+                .add_message(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: deposit.depositor.to_string(),
+                    amount: vec![refund],
+                }))
+                .add_attribute("action", "refund_deposit")
+                .add_attribute("deposit_id", deposit_id.to_string()))
+        }
+        // funds sit on the interchain account, bring them back first
+        DepositStage::Swapping | DepositStage::Deploying | DepositStage::Refunding => {
+            let res = execute_refund_transfer(deps, env.clone(), deposit_id)?;
+            update_deposit(deps.storage, deposit_id, env.block.time, |deposit| {
+                deposit.stage = DepositStage::RefundInFlight;
+            })?;
+            Ok(res)
+        }
+        // the funds are already on their way back, a second transfer would pay them out twice
+        DepositStage::RefundInFlight => Err(ContractError::OperationInFlight {
+            operation: format!("refund of deposit {}", deposit_id),
+        }),
+        // deployed deposits are owed shares, not a refund
+        DepositStage::AwaitingPrice | DepositStage::Completed | DepositStage::Refunded => {
+            Err(ContractError::Std(StdError::generic_err(format!(
+                "Deposit {} can not be refunded",
+                deposit_id
+            ))))
+        }
+    }
+}
+
+// interchain account sends the funds of a failed deposit back to the contract
+fn execute_refund_transfer(
+    deps: &mut DepsMut<NeutronQuery>,
+    env: Env,
+    deposit_id: u64,
+) -> ContractResult<Response<NeutronMsg>> {
+    let deposit = DEPOSITS.load(deps.storage, deposit_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let home = HOST_CHAINS.load(deps.storage, HOME_CHAIN_ID)?;
+    let fee = min_ntrn_ibc_fee(query_min_ibc_fee(deps.as_ref())?.min_fee, &config.fee_denom);
+    let (ica, connection_id) = get_ica(deps.as_ref(), &env, &home.interchain_account_id)?;
+    let (host_coin, _) = deposit_refund_coins(&deposit, &config, &home)?;
+
+    let refund_msg: MsgTransfer = MsgTransfer {
+        sender: ica,
+        receiver: env.contract.address.to_string(),
+        token: Some(cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
+            denom: host_coin.denom,
+            amount: host_coin.amount.to_string(),
+        }),
+        source_channel: home.return_channel.clone(),
+        source_port: "transfer".to_string(),
         timeout_height: None,
-        timeout_timestamp: 0,
+        timeout_timestamp: env.block.time.plus_seconds(DEFAULT_TIMEOUT_SECONDS).nanos(),
     };
-    let mut buf = Vec::new();
-    buf.reserve(deploy_msg.encoded_len());
+    let mut buf = Vec::with_capacity(refund_msg.encoded_len());
 
-    if let Err(e) = deploy_msg.encode(&mut buf) {
-        return Err(NeutronError::Std(StdError::generic_err(format!(
+    if let Err(e) = refund_msg.encode(&mut buf) {
+        return Err(ContractError::Std(StdError::generic_err(format!(
             "Encode error: {}",
             e
         ))));
@@ -619,7 +2436,7 @@ fn execute_return_funds(
 
     let cosmos_msg = NeutronMsg::submit_tx(
         connection_id,
-        OSMOSIS_IC_ACCOUNT_ID.to_string(),
+        home.interchain_account_id.clone(),
         vec![any_msg],
         "".to_string(),
         DEFAULT_TIMEOUT_SECONDS,
@@ -632,41 +2449,56 @@ fn execute_return_funds(
         deps,
         cosmos_msg,
         SudoPayload {
-            port_id: get_port_id(env.contract.address.as_str(), OSMOSIS_IC_ACCOUNT_ID),
-            message: "message".to_string(),
-            sender: sender.to_string(),
-            executor: "execute_return_funds".to_string(),
-            amount: Some(amount),
+            port_id: get_port_id(env.contract.address.as_str(), &home.interchain_account_id),
+            sender: deposit.depositor.to_string(),
+            operation: SudoOperation::RefundDeposit {
+                deposit_id,
+                amount: host_coin.amount,
+            },
         },
     )?;
 
-    Ok(Response::default().add_submessages(vec![submsg]))
+    Ok(Response::default()
+        .add_submessages(vec![submsg])
+        .add_attribute("action", "refund_deposit")
+        .add_attribute("deposit_id", deposit_id.to_string()))
 }
 
-// contract sends USDC to user as a result of their LP share claim
-fn execute_return_funds_user(
+// contract sends the returned funds of a failed deposit to the depositor
+fn execute_refund_user(
     storage: &mut dyn Storage,
-    _env: Env,
-    sender: String,
-    amount: Uint128,
-) -> StdResult<Response> {
-    BALANCES.update(
-        storage,
-        Addr::unchecked(sender.clone()), // TODO
-        |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() - amount) },
-    )?;
-    let resp = Response::new().add_message(CosmosMsg::Bank(BankMsg::Send {
-        to_address: sender.clone(),
-        amount: vec![cosmwasm_std::Coin {
-            denom: "uusdc".to_string(), // TODO get ibc denom
-            amount,
-        }],
-    }));
-    Ok(resp)
+    env: Env,
+    deposit_id: u64,
+) -> StdResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(storage)?;
+    let home = HOST_CHAINS.load(storage, HOME_CHAIN_ID)?;
+    let deposit = DEPOSITS.load(storage, deposit_id)?;
+    // only the transfer in flight is paid out, an ack for a deposit refunded already is ignored
+    if deposit.stage != DepositStage::RefundInFlight {
+        return Ok(Response::new());
+    }
+    let (_, local_coin) = deposit_refund_coins(&deposit, &config, &home)?;
+
+    update_deposit(storage, deposit_id, env.block.time, |deposit| {
+        deposit.refunded = Some(local_coin.clone());
+        deposit.stage = DepositStage::Refunded;
+    })?;
+    // swapped funds left the interchain account
+    if let Some(swapped) = deposit.swapped {
+        release_value(storage, swapped)?;
+    }
+    Ok(Response::new().add_message(CosmosMsg::Bank(BankMsg::Send {
+        to_address: deposit.depositor.to_string(),
+        amount: vec![local_coin],
+    })))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn sudo(deps: DepsMut<NeutronQuery>, env: Env, msg: SudoMsg) -> StdResult<Response> {
+pub fn sudo(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    msg: SudoMsg,
+) -> StdResult<Response<NeutronMsg>> {
     deps.api
         .debug(format!("WASMDEBUG: sudo: received sudo msg: {:?}", msg).as_str());
 
@@ -675,7 +2507,7 @@ pub fn sudo(deps: DepsMut<NeutronQuery>, env: Env, msg: SudoMsg) -> StdResult<Re
         SudoMsg::Response { request, data } => sudo_response(deps, env, request, data),
 
         // For handling error acknowledgements.
-        SudoMsg::Error { request, details } => sudo_error(deps, request, details),
+        SudoMsg::Error { request, details } => sudo_error(deps, env, request, details),
 
         // For handling error timeouts.
         SudoMsg::Timeout { request } => sudo_timeout(deps, env, request),
@@ -694,13 +2526,27 @@ pub fn sudo(deps: DepsMut<NeutronQuery>, env: Env, msg: SudoMsg) -> StdResult<Re
             counterparty_channel_id,
             counterparty_version,
         ),
+
+        // For handling new results of the NAV interchain queries
+        SudoMsg::KVQueryResult { query_id } => sudo_kv_query_result(deps, env, query_id),
         _ => Ok(Response::default()),
     }
 }
 
+// the first deployments kept the vault parameters in constants and have none of the items
+// the handlers load, they can't be migrated and need a new vault to be instantiated
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
     deps.api.debug("WASMDEBUG: migrate");
+    if !CONFIG.exists(deps.storage)
+        || !ROLES.exists(deps.storage)
+        || !HOST_CHAINS.has(deps.storage, HOME_CHAIN_ID)
+    {
+        return Err(StdError::generic_err(
+            "Vault state predates the stored config, instantiate a new vault instead of migrating",
+        ));
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     Ok(Response::default())
 }
 
@@ -712,7 +2558,7 @@ fn sudo_open_ack(
     _channel_id: String,
     _counterparty_channel_id: String,
     counterparty_version: String,
-) -> StdResult<Response> {
+) -> StdResult<Response<NeutronMsg>> {
     // The version variable contains a JSON value with multiple fields,
     // including the generated account address.
     let parsed_version: Result<OpenAckVersion, _> =
@@ -722,25 +2568,40 @@ fn sudo_open_ack(
     if let Ok(parsed_version) = parsed_version {
         INTERCHAIN_ACCOUNTS.save(
             deps.storage,
-            port_id,
+            port_id.clone(),
             &Some((
                 parsed_version.address,
                 parsed_version.controller_connection_id,
             )),
         )?;
+        ICA_STATUS.save(deps.storage, port_id, &IcaStatus::Open)?;
         return Ok(Response::default());
     }
     Err(StdError::generic_err("Can't parse counterparty_version"))
 }
 
+// Returning an error from sudo closes the channel. This is only done for a packet without
+// sequence or channel, which means a fatal error occurred on the Neutron side and the packet
+// can not be matched to a payload. Everything else is handled by `sudo_packet`, which puts
+// failures into the errors queue.
+fn packet_id(request: &RequestPacket) -> StdResult<(String, u64)> {
+    let seq_id = request
+        .sequence
+        .ok_or_else(|| StdError::generic_err("sequence not found"))?;
+    let channel_id = request
+        .source_channel
+        .clone()
+        .ok_or_else(|| StdError::generic_err("channel_id not found"))?;
+    Ok((channel_id, seq_id))
+}
+
 fn sudo_response(
-    mut deps: DepsMut<NeutronQuery>,
+    deps: DepsMut<NeutronQuery>,
     env: Env,
     request: RequestPacket,
     data: Binary,
-) -> StdResult<Response> {
-    let api = deps.api;
-    api.debug(
+) -> StdResult<Response<NeutronMsg>> {
+    deps.api.debug(
         format!(
             "WASMDEBUG: sudo_response: sudo received: {:?} {:?}",
             request, data
@@ -748,307 +2609,716 @@ fn sudo_response(
         .as_str(),
     );
 
-    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-    // FOR LATER INSPECTION.
-    // In this particular case, we return an error because not having the sequence id
-    // in the request value implies that a fatal error occurred on Neutron side.
-    let seq_id = request
-        .sequence
-        .ok_or_else(|| StdError::generic_err("sequence not found"))?;
+    let (channel_id, seq_id) = packet_id(&request)?;
 
-    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-    // FOR LATER INSPECTION.
-    // In this particular case, we return an error because not having the sequence id
-    // in the request value implies that a fatal error occurred on Neutron side.
-    let channel_id = request
-        .source_channel
-        .ok_or_else(|| StdError::generic_err("channel_id not found"))?;
+    sudo_packet(deps, env, channel_id, seq_id, ErrorKind::Response { data })
+}
 
-    // NOTE: NO ERROR IS RETURNED HERE. THE CHANNEL LIVES ON.
-    // In this particular example, this is a matter of developer's choice. Not being able to read
-    // the payload here means that there was a problem with the contract while submitting an
-    // interchain transaction. You can decide that this is not worth killing the channel,
-    // write an error log and / or save the acknowledgement to an errors queue for later manual
-    // processing. The decision is based purely on your application logic.
-    let payload = read_sudo_payload(deps.storage, channel_id, seq_id).ok();
-    if let Some(payload) = payload {
-        api.debug(format!("WASMDEBUG: sudo_response: sudo payload: {:?}", payload).as_str());
-
-        // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-        // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-        // FOR LATER INSPECTION.
-        // In this particular case, we return an error because not being able to parse this data
-        // that a fatal error occurred on Neutron side, or that the remote chain sent us unexpected data.
-        // Both cases require immediate attention.
-        let parsed_data = decode_acknowledgement_response(data)?;
-
-        let mut item_types = vec![];
-
-        for item in parsed_data {
-            let item_type = item.msg_type.as_str();
-            item_types.push(item_type.to_string());
-            match item_type {
-                "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn" => {
-                    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-                    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-                    // FOR LATER INSPECTION.
-                    // In this particular case, a mismatch between the string message type and the
-                    // serialised data layout looks like a fatal error that has to be investigated.
-                    let out: MsgSwapExactAmountInResponse = decode_message_response(&item.data)?;
-
-                    api.debug(format!("Swapped for uusdc: {:?}", out.token_out_amount).as_str());
-
-                    let value_res = out.token_out_amount.parse::<Uint128>();
-                    if let Ok(value) = value_res {
-                        let res =
-                            execute_deploy(&mut deps, env.clone(), payload.sender.clone(), value);
-                        if res.is_err() {
-                            let error_msg = "WASMDEBUG: Error: Unable to execute_deploy";
-                            api.debug(error_msg);
-
-                            // TODO add error to queue
-                            return Err(StdError::generic_err(error_msg));
-                        }
-                    } else {
-                        let error_msg = "WASMDEBUG: Error: Unable to parse token_out_amount";
-                        api.debug(error_msg);
+fn sudo_timeout(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    request: RequestPacket,
+) -> StdResult<Response<NeutronMsg>> {
+    deps.api
+        .debug(format!("WASMDEBUG: sudo timeout request: {:?}", request).as_str());
 
-                        // TODO add error to queue
-                        return Err(StdError::generic_err(error_msg));
-                    }
-                }
-                // RE deploy to mars
-                "/cosmwasm.wasm.v1.MsgExecuteContract" => match payload.executor.as_str() {
-                    "execute_fund" => {
-                        let out: MsgExecuteContractResponse = decode_message_response(&item.data)?;
-                        api.debug(format!("Deployed to mars: {:?}", out).as_str());
-                        if let Some(amount) = payload.amount {
-                            let res = execute_tokens_to_user(
-                                &mut deps,
-                                env.clone(),
-                                payload.sender.clone(),
-                                amount,
-                            );
-                            if res.is_err() {
-                                let error_msg =
-                                    "WASMDEBUG: Error: Unable to execute_tokens_to_user";
-                                api.debug(error_msg);
-
-                                // TODO add error to queue
-                                return Err(StdError::generic_err(error_msg));
-                            }
-                        } else {
-                            let error_msg = "WASMDEBUG: Error: Unable to execute_tokens_to_user";
-                            api.debug(error_msg);
-
-                            // TODO add error to queue
-                            return Err(StdError::generic_err(error_msg));
-                        }
-                    }
-                    "execute_tokens_to_user" => {
-                        let out: MsgExecuteContractResponse = decode_message_response(&item.data)?;
-                        api.debug(format!("Tokens to user: {:?}", out).as_str());
-                        return Ok(Response::default());
-                    }
-                    "execute_claim" => {
-                        let out: MsgExecuteContractResponse = decode_message_response(&item.data)?;
-                        api.debug(format!("Claiming tokens: {:?}", out).as_str());
-
-                        if let Some(amount) = payload.amount {
-                            let res = execute_return_funds(
-                                &mut deps,
-                                env.clone(),
-                                payload.sender.clone(),
-                                amount,
-                            );
-
-                            if res.is_err() {
-                                let error_msg = "WASMDEBUG: Error: Unable to execute_return_funds";
-                                api.debug(error_msg);
-
-                                // TODO add error to queue
-                                return Err(StdError::generic_err(error_msg));
-                            }
-
-                            return Ok(Response::default());
-                        }
-                        return Err(StdError::generic_err("amount not found"));
-                    }
-                    "execute_return_funds" => {
-                        let out: MsgExecuteContractResponse = decode_message_response(&item.data)?;
-                        api.debug(format!("Claiming tokens: {:?}", out).as_str());
+    let (channel_id, seq_id) = packet_id(&request)?;
 
-                        if let Some(amount) = payload.amount {
-                            let res = execute_return_funds_user(
-                                deps.storage,
-                                env.clone(),
-                                payload.sender.clone(),
-                                amount,
-                            );
+    sudo_packet(deps, env, channel_id, seq_id, ErrorKind::Timeout)
+}
+
+fn sudo_error(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    request: RequestPacket,
+    details: String,
+) -> StdResult<Response<NeutronMsg>> {
+    deps.api
+        .debug(format!("WASMDEBUG: sudo error: {}", details).as_str());
+    deps.api
+        .debug(format!("WASMDEBUG: request packet: {:?}", request).as_str());
 
-                            if res.is_err() {
-                                let error_msg =
-                                    "WASMDEBUG: Error: Unable to execute_return_funds_user";
-                                api.debug(error_msg);
+    let (channel_id, seq_id) = packet_id(&request)?;
 
-                                // TODO add error to queue
-                                return Err(StdError::generic_err(error_msg));
-                            }
+    sudo_packet(deps, env, channel_id, seq_id, ErrorKind::Error { details })
+}
 
-                            return Ok(Response::default());
-                        }
-                        return Err(StdError::generic_err("amount not found"));
-                    }
-                    _ => {
-                        let error_msg = "WASMDEBUG: Error: Unknown executor";
-                        api.debug(error_msg);
-                        return Err(StdError::generic_err(error_msg));
+// NOTE: NO ERROR IS RETURNED HERE. THE CHANNEL LIVES ON.
+// A missing payload or a failure while handling the packet is put into the errors queue with
+// the packet outcome instead, so the keeper can inspect and retry it. The handling runs on a branch of the storage that is only
+// committed if it succeeds as a whole, a failure leaves no half updated state behind.
+fn sudo_packet(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    channel_id: String,
+    seq_id: u64,
+    outcome: ErrorKind,
+) -> StdResult<Response<NeutronMsg>> {
+    let payload = match take_sudo_payload(deps.storage, channel_id.clone(), seq_id) {
+        Ok(payload) => payload,
+        Err(_) => {
+            let error_msg = "WASMDEBUG: Error: Unable to read sudo payload";
+            deps.api.debug(error_msg);
+            add_error_to_queue(
+                deps.storage,
+                env.block.time,
+                ErrorKind::MissingPayload,
+                channel_id,
+                seq_id,
+                None,
+                error_msg.to_string(),
+            )?;
+            return Ok(Response::default());
+        }
+    };
+    deps.api
+        .debug(format!("WASMDEBUG: sudo payload: {:?}", payload).as_str());
+
+    // the ordered channel of an interchain account is closed by the timeout
+    if outcome == ErrorKind::Timeout {
+        close_ica(deps.storage, payload.port_id.clone())?;
+    }
+
+    let mut branch = StorageBranch::new(deps.storage);
+    let res = handle_packet(
+        DepsMut {
+            storage: &mut branch,
+            api: deps.api,
+            querier: deps.querier,
+        },
+        env.clone(),
+        &channel_id,
+        seq_id,
+        &payload,
+        &outcome,
+    );
+    match res {
+        Ok(res) => {
+            branch.commit();
+            Ok(res)
+        }
+        Err(e) => {
+            drop(branch);
+            let error_msg = format!("WASMDEBUG: Error: {}", e);
+            deps.api.debug(&error_msg);
+            let id = add_error_to_queue(
+                deps.storage,
+                env.block.time,
+                outcome,
+                channel_id,
+                seq_id,
+                Some(payload),
+                error_msg,
+            )?;
+            Ok(Response::new().add_attribute("error_id", id.to_string()))
+        }
+    }
+}
+
+// handles the outcome of a packet, also used to retry a queued one
+fn handle_packet(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    channel_id: &str,
+    seq_id: u64,
+    payload: &SudoPayload,
+    outcome: &ErrorKind,
+) -> ContractResult<Response<NeutronMsg>> {
+    match outcome {
+        ErrorKind::Response { data } => {
+            handle_response(deps, env, channel_id, seq_id, payload, data.clone())
+        }
+        ErrorKind::Error { details } => {
+            handle_error(deps, env, channel_id, seq_id, payload, details.clone())
+        }
+        ErrorKind::Timeout => handle_timeout(deps, env, channel_id, seq_id, payload),
+        _ => Err(ContractError::Std(StdError::generic_err(
+            "Not a packet outcome",
+        ))),
+    }
+}
+
+fn handle_response(
+    mut deps: DepsMut<NeutronQuery>,
+    env: Env,
+    channel_id: &str,
+    seq_id: u64,
+    payload: &SudoPayload,
+    data: Binary,
+) -> ContractResult<Response<NeutronMsg>> {
+    let api = deps.api;
+    let mut resp = Response::new();
+
+    // The buffer is swept with a plain IBC transfer, its acknowledgement is not an
+    // interchain tx response.
+    let (parsed_data, item_types) = match payload.operation {
+        SudoOperation::SweepBuffer { .. } => (
+            vec![],
+            vec!["/ibc.applications.transfer.v1.MsgTransfer".to_string()],
+        ),
+        _ => {
+            let parsed_data = decode_acknowledgement_response(data)?;
+            let item_types: Vec<String> = parsed_data
+                .iter()
+                .map(|item| item.msg_type.clone())
+                .collect();
+            (parsed_data, item_types)
+        }
+    };
+
+    // the responses to the strategy messages come first, each strategy decodes its own
+    let mut items = parsed_data.iter();
+    apply_allocations(deps.storage, payload.operation.allocations(), &mut items)?;
+    match &payload.operation {
+        SudoOperation::TransferDeposit { deposit_id, .. } => {
+            let item = next_response(&mut items, "/ibc.applications.transfer.v1.MsgTransfer")?;
+            let out: MsgTransferResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Transferred to osmosis: {:?}", out).as_str());
+
+            let deposit = update_deposit(deps.storage, *deposit_id, env.block.time, |deposit| {
+                deposit.transferred = Some(deposit.input.amount);
+                deposit.stage = DepositStage::Swapping;
+            })?;
+            let res = execute_ic_swap(&mut deps, env.clone(), *deposit_id, deposit.input, None);
+            match res {
+                Ok(res) => resp = resp.add_submessages(res.messages),
+                Err(e) => {
+                    let error_msg = format!("WASMDEBUG: Error: Unable to execute_ic_swap: {}", e);
+                    api.debug(&error_msg);
+                    return Err(ContractError::Std(StdError::generic_err(error_msg)));
+                }
+            }
+        }
+        SudoOperation::SwapDeposit {
+            deposit_id,
+            min_out,
+        } => {
+            let item = next_response(
+                &mut items,
+                "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn",
+            )?;
+            let out: MsgSwapExactAmountInResponse = decode_message_response(&item.data)?;
+
+            api.debug(format!("Swapped for uusdc: {:?}", out.token_out_amount).as_str());
+
+            let value_res = out.token_out_amount.parse::<Uint128>();
+            if let Ok(value) = value_res {
+                ensure_min_out(value, *min_out)?;
+                update_deposit(deps.storage, *deposit_id, env.block.time, |deposit| {
+                    deposit.swapped = Some(value);
+                    deposit.stage = DepositStage::Deploying;
+                })?;
+                // the swapped funds are on the interchain account but have no shares yet
+                reserve_value(deps.storage, value)?;
+                let res = execute_deploy(&mut deps, env.clone(), *deposit_id, value);
+                match res {
+                    Ok(res) => resp = resp.add_submessages(res.messages),
+                    Err(e) => {
+                        let error_msg =
+                            format!("WASMDEBUG: Error: Unable to execute_deploy: {}", e);
+                        api.debug(&error_msg);
+                        return Err(ContractError::Std(StdError::generic_err(error_msg)));
                     }
+                }
+            } else {
+                let error_msg = "WASMDEBUG: Error: Unable to parse token_out_amount";
+                api.debug(error_msg);
+                return Err(ContractError::Std(StdError::generic_err(error_msg)));
+            }
+        }
+        SudoOperation::DeployDeposit {
+            deposit_id, amount, ..
+        } => {
+            api.debug("Deployed to strategies");
+            let res = execute_tokens_to_user(&mut deps, env.clone(), *deposit_id, *amount);
+            match res {
+                Ok(res) => resp = resp.add_submessages(res.messages),
+                Err(e) => {
+                    let error_msg =
+                        format!("WASMDEBUG: Error: Unable to execute_tokens_to_user: {}", e);
+                    api.debug(&error_msg);
+                    return Err(ContractError::Std(StdError::generic_err(error_msg)));
+                }
+            }
+        }
+        SudoOperation::RefundDeposit { deposit_id, .. } => {
+            let item = next_response(&mut items, "/ibc.applications.transfer.v1.MsgTransfer")?;
+            let out: MsgTransferResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Refund returned: {:?}", out).as_str());
+
+            let res = execute_refund_user(deps.storage, env.clone(), *deposit_id);
+            match res {
+                Ok(res) => resp = resp.add_submessages(res.messages),
+                Err(e) => {
+                    let error_msg =
+                        format!("WASMDEBUG: Error: Unable to execute_refund_user: {}", e);
+                    api.debug(&error_msg);
+                    return Err(ContractError::Std(StdError::generic_err(error_msg)));
+                }
+            }
+        }
+        // nothing proven to swap yet, the claimed rewards are swapped by the next harvest
+        SudoOperation::Harvest { reward_amount, .. } if reward_amount.is_zero() => {
+            let item = next_response(&mut items, "/cosmwasm.wasm.v1.MsgExecuteContract")?;
+            let out: MsgExecuteContractResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Claimed rewards from mars: {:?}", out).as_str());
+        }
+        SudoOperation::Harvest { min_out, .. } => {
+            let item = next_response(&mut items, "/cosmwasm.wasm.v1.MsgExecuteContract")?;
+            let out: MsgExecuteContractResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Claimed rewards from mars: {:?}", out).as_str());
+
+            let item = next_response(
+                &mut items,
+                "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn",
+            )?;
+            let out: MsgSwapExactAmountInResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Harvested for uusdc: {:?}", out.token_out_amount).as_str());
+
+            let value = out.token_out_amount.parse::<Uint128>().map_err(|_| {
+                StdError::generic_err("WASMDEBUG: Error: Unable to parse token_out_amount")
+            })?;
+            ensure_min_out(value, *min_out)?;
+            // the rewards are counted once supplied to the strategies
+            reserve_value(deps.storage, value)?;
+            match execute_strategy_supply(
+                &mut deps,
+                env.clone(),
+                HOME_CHAIN_ID,
+                value,
+                |allocations| SudoOperation::DeployHarvest {
+                    amount: value,
+                    allocations,
                 },
-                "/ibc.applications.transfer.v1.MsgTransfer" => match payload.executor.as_str() {
-                    "execute_fund_to_osmosis" => {
-                        let out: MsgTransferResponse = decode_message_response(&item.data)?;
-                        api.debug(format!("Transferred to osmosis: {:?}", out).as_str());
-                        execute_ic_swap(deps, env, info, None);
-                        return Ok(Response::default());
-                    }
+            ) {
+                Ok(res) => resp = resp.add_submessages(res.messages),
+                Err(e) => {
+                    add_error_to_queue(
+                        deps.storage,
+                        env.block.time,
+                        ErrorKind::DeployHarvest { amount: value },
+                        channel_id.to_string(),
+                        seq_id,
+                        Some(payload.clone()),
+                        e.to_string(),
+                    )?;
+                    release_value(deps.storage, value)?;
+                }
+            }
+        }
+        SudoOperation::DeployHarvest { amount, .. } => {
+            api.debug("Deployed rewards to strategies");
+
+            // the yield belongs to the share holders now
+            TOTAL_VALUE.update(deps.storage, |total_value| -> StdResult<_> {
+                Ok(total_value + *amount)
+            })?;
+            release_value(deps.storage, *amount)?;
+            resp = resp.add_attribute("harvested", *amount);
+        }
+        SudoOperation::WithdrawEpoch {
+            epoch_id,
+            chain,
+            amount,
+            ..
+        } => {
+            let item = next_response(&mut items, "/ibc.applications.transfer.v1.MsgTransfer")?;
+            let out: MsgTransferResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Returned withdrawals: {:?}", out).as_str());
+
+            let epoch = EPOCHS.update(deps.storage, *epoch_id, |epoch| -> StdResult<_> {
+                let mut epoch = epoch.ok_or_else(|| StdError::generic_err("epoch not found"))?;
+                epoch.returned += *amount;
+                epoch.stage = if epoch.returned >= epoch.value {
+                    EpochStage::Returning
+                } else {
+                    EpochStage::Processing
+                };
+                epoch.updated_at = env.block.time;
+                Ok(epoch)
+            })?;
+            release_after_nav(deps.storage, chain, *amount)?;
+            // the tickets are paid once the funds arrived, not when they left the host chain
+            create_return(
+                deps.storage,
+                *amount,
+                ReturnPurpose::WithdrawEpoch {
+                    epoch_id: *epoch_id,
                 },
-                _ => {
-                    let error_msg = "WASMDEBUG: Error: Unknown message type";
-                    api.debug(error_msg);
-                    return Err(StdError::generic_err(error_msg));
+                env.block.time,
+            )?;
+            let local_denom = CONFIG.load(deps.storage)?.local_denom;
+            confirm_returns(
+                deps.storage,
+                &deps.querier,
+                &env.contract.address,
+                &local_denom,
+                env.block.time,
+            )?;
+            // the rest of the epoch is withdrawn from the next host chain
+            if epoch.stage == EpochStage::Processing {
+                match execute_withdraw_epoch(&mut deps, env.clone(), *epoch_id) {
+                    Ok(res) => resp = resp.add_submessages(res.messages),
+                    Err(e) => {
+                        add_error_to_queue(
+                            deps.storage,
+                            env.block.time,
+                            ErrorKind::WithdrawEpoch {
+                                epoch_id: *epoch_id,
+                            },
+                            channel_id.to_string(),
+                            seq_id,
+                            Some(payload.clone()),
+                            e.to_string(),
+                        )?;
+                    }
                 }
             }
         }
-
-        // update but also check that we don't update same seq_id twice
-        ACKNOWLEDGEMENT_RESULTS.update(
-            deps.storage,
-            (payload.port_id, seq_id),
-            |maybe_ack| -> StdResult<AcknowledgementResult> {
-                match maybe_ack {
-                    Some(_ack) => Err(StdError::generic_err("trying to update same seq_id")),
-                    None => Ok(AcknowledgementResult::Success(item_types)),
+        SudoOperation::TopUpBuffer { chain, amount, .. } => {
+            let item = next_response(&mut items, "/ibc.applications.transfer.v1.MsgTransfer")?;
+            let out: MsgTransferResponse = decode_message_response(&item.data)?;
+            api.debug(format!("Topped up buffer: {:?}", out).as_str());
+
+            // the buffer is credited once the funds arrived, until then they are counted in
+            // transit and reserved while the remote state still holds them
+            reserve_value(deps.storage, *amount)?;
+            release_after_nav(deps.storage, chain, *amount)?;
+            create_return(
+                deps.storage,
+                *amount,
+                ReturnPurpose::TopUpBuffer,
+                env.block.time,
+            )?;
+            let local_denom = CONFIG.load(deps.storage)?.local_denom;
+            confirm_returns(
+                deps.storage,
+                &deps.querier,
+                &env.contract.address,
+                &local_denom,
+                env.block.time,
+            )?;
+        }
+        SudoOperation::SweepBuffer { chain, amount } => {
+            match execute_strategy_supply(&mut deps, env.clone(), chain, *amount, |allocations| {
+                SudoOperation::DeployBuffer {
+                    chain: chain.clone(),
+                    amount: *amount,
+                    allocations,
                 }
-            },
-        )?;
-
-        Ok(Response::default())
-    } else {
-        let error_msg = "WASMDEBUG: Error: Unable to read sudo payload";
-        api.debug(error_msg);
-        add_error_to_queue(deps.storage, error_msg.to_string());
-        Ok(Response::default())
+            }) {
+                Ok(res) => resp = resp.add_submessages(res.messages),
+                Err(e) => {
+                    add_error_to_queue(
+                        deps.storage,
+                        env.block.time,
+                        ErrorKind::DeployBuffer {
+                            chain: chain.clone(),
+                            amount: *amount,
+                        },
+                        channel_id.to_string(),
+                        seq_id,
+                        Some(payload.clone()),
+                        e.to_string(),
+                    )?;
+                }
+            }
+        }
+        SudoOperation::DeployBuffer { .. } => {
+            api.debug("Deployed buffer to strategies");
+        }
+        SudoOperation::Rebalance { .. } => {
+            api.debug("Rebalanced strategies");
+            REBALANCE.remove(deps.storage);
+        }
     }
-}
 
-fn sudo_timeout(
-    deps: DepsMut<NeutronQuery>,
-    _env: Env,
-    request: RequestPacket,
-) -> StdResult<Response> {
-    deps.api
-        .debug(format!("WASMDEBUG: sudo timeout request: {:?}", request).as_str());
+    save_ack_result(
+        deps.storage,
+        payload.port_id.clone(),
+        seq_id,
+        AcknowledgementResult::Success(item_types),
+    )?;
 
-    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-    // FOR LATER INSPECTION.
-    // In this particular case, we return an error because not having the sequence id
-    // in the request value implies that a fatal error occurred on Neutron side.
-    let seq_id = request
-        .sequence
-        .ok_or_else(|| StdError::generic_err("sequence not found"))?;
+    Ok(resp)
+}
 
-    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-    // FOR LATER INSPECTION.
-    // In this particular case, we return an error because not having the sequence id
-    // in the request value implies that a fatal error occurred on Neutron side.
-    let channel_id = request
-        .source_channel
-        .ok_or_else(|| StdError::generic_err("channel_id not found"))?;
+// next response of `msg_type` in the acknowledgement, responses before it need no handling
+fn next_response<'a>(
+    items: &mut impl Iterator<Item = &'a MsgData>,
+    msg_type: &str,
+) -> StdResult<&'a MsgData> {
+    items
+        .find(|item| item.msg_type == msg_type)
+        .ok_or_else(|| StdError::generic_err(format!("WASMDEBUG: Error: No {} response", msg_type)))
+}
 
-    // update but also check that we don't update same seq_id twice
-    // NOTE: NO ERROR IS RETURNED HERE. THE CHANNEL LIVES ON.
-    // In this particular example, this is a matter of developer's choice. Not being able to read
-    // the payload here means that there was a problem with the contract while submitting an
-    // interchain transaction. You can decide that this is not worth killing the channel,
-    // write an error log and / or save the acknowledgement to an errors queue for later manual
-    // processing. The decision is based purely on your application logic.
-    // Please be careful because it may lead to an unexpected state changes because state might
-    // has been changed before this call and will not be reverted because of supressed error.
-    let payload = read_sudo_payload(deps.storage, channel_id, seq_id).ok();
-    if let Some(payload) = payload {
-        // update but also check that we don't update same seq_id twice
-        ACKNOWLEDGEMENT_RESULTS.update(
-            deps.storage,
-            (payload.port_id, seq_id),
-            |maybe_ack| -> StdResult<AcknowledgementResult> {
-                match maybe_ack {
-                    Some(_ack) => Err(StdError::generic_err("trying to update same seq_id")),
-                    None => Ok(AcknowledgementResult::Timeout(payload.message)),
+fn handle_timeout(
+    mut deps: DepsMut<NeutronQuery>,
+    env: Env,
+    channel_id: &str,
+    seq_id: u64,
+    payload: &SudoPayload,
+) -> ContractResult<Response<NeutronMsg>> {
+    let mut resp = Response::new();
+    save_ack_result(
+        deps.storage,
+        payload.port_id.clone(),
+        seq_id,
+        AcknowledgementResult::Timeout(payload.operation.name().to_string()),
+    )?;
+
+    match &payload.operation {
+        SudoOperation::TransferDeposit { deposit_id, .. }
+        | SudoOperation::SwapDeposit { deposit_id, .. }
+        | SudoOperation::DeployDeposit { deposit_id, .. }
+        | SudoOperation::RefundDeposit { deposit_id, .. } => {
+            let deposit = DEPOSITS.load(deps.storage, *deposit_id)?;
+            match deposit.stage {
+                DepositStage::Transferring => {
+                    match execute_refund_deposit(&mut deps, env.clone(), *deposit_id) {
+                        Ok(res) => resp = resp.add_submessages(res.messages),
+                        Err(e) => {
+                            add_error_to_queue(
+                                deps.storage,
+                                env.block.time,
+                                ErrorKind::RefundDeposit {
+                                    deposit_id: *deposit_id,
+                                },
+                                channel_id.to_string(),
+                                seq_id,
+                                Some(payload.clone()),
+                                e.to_string(),
+                            )?;
+                        }
+                    }
                 }
-            },
-        )?;
-    } else {
-        let error_msg = "WASMDEBUG: Error: Unable to read sudo payload";
-        deps.api.debug(error_msg);
-        add_error_to_queue(deps.storage, error_msg.to_string());
+                // a timeout closes the interchain account channel, so funds on the host chain
+                // are sent back by the keeper once the account is reopened
+                DepositStage::Swapping | DepositStage::Deploying | DepositStage::RefundInFlight => {
+                    update_deposit(deps.storage, *deposit_id, env.block.time, |deposit| {
+                        deposit.stage = DepositStage::Refunding;
+                    })?;
+                }
+                _ => {}
+            }
+        }
+        SudoOperation::WithdrawEpoch { epoch_id, .. } => {
+            reset_withdrawing_epoch(deps.storage, *epoch_id, env.block.time)?;
+        }
+        SudoOperation::SweepBuffer { amount, .. } => {
+            restore_swept_buffer(deps.storage, *amount)?;
+        }
+        // harvested funds stay on the interchain account until the next harvest
+        SudoOperation::DeployHarvest { amount, .. } => {
+            release_value(deps.storage, *amount)?;
+        }
+        // nothing moved, the keeper can rebalance again
+        SudoOperation::Rebalance { .. } => REBALANCE.remove(deps.storage),
+        SudoOperation::Harvest { .. }
+        | SudoOperation::TopUpBuffer { .. }
+        | SudoOperation::DeployBuffer { .. } => {}
     }
 
-    Ok(Response::default())
+    Ok(resp)
 }
 
-fn sudo_error(
-    deps: DepsMut<NeutronQuery>,
-    request: RequestPacket,
+fn handle_error(
+    mut deps: DepsMut<NeutronQuery>,
+    env: Env,
+    channel_id: &str,
+    seq_id: u64,
+    payload: &SudoPayload,
     details: String,
-) -> StdResult<Response> {
-    deps.api
-        .debug(format!("WASMDEBUG: sudo error: {}", details).as_str());
-    deps.api
-        .debug(format!("WASMDEBUG: request packet: {:?}", request).as_str());
+) -> ContractResult<Response<NeutronMsg>> {
+    let mut resp = Response::new();
+    save_ack_result(
+        deps.storage,
+        payload.port_id.clone(),
+        seq_id,
+        AcknowledgementResult::Error((payload.operation.name().to_string(), details)),
+    )?;
 
-    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-    // FOR LATER INSPECTION.
-    // In this particular case, we return an error because not having the sequence id
-    // in the request value implies that a fatal error occurred on Neutron side.
-    let seq_id = request
-        .sequence
-        .ok_or_else(|| StdError::generic_err("sequence not found"))?;
+    match &payload.operation {
+        // a failed refund is left for the keeper to retry, anything else is refunded right away
+        SudoOperation::TransferDeposit { deposit_id, .. }
+        | SudoOperation::SwapDeposit { deposit_id, .. }
+        | SudoOperation::DeployDeposit { deposit_id, .. } => {
+            match execute_refund_deposit(&mut deps, env.clone(), *deposit_id) {
+                Ok(res) => resp = resp.add_submessages(res.messages),
+                Err(e) => {
+                    add_error_to_queue(
+                        deps.storage,
+                        env.block.time,
+                        ErrorKind::RefundDeposit {
+                            deposit_id: *deposit_id,
+                        },
+                        channel_id.to_string(),
+                        seq_id,
+                        Some(payload.clone()),
+                        e.to_string(),
+                    )?;
+                }
+            }
+        }
+        SudoOperation::WithdrawEpoch { epoch_id, .. } => {
+            reset_withdrawing_epoch(deps.storage, *epoch_id, env.block.time)?;
+        }
+        SudoOperation::SweepBuffer { amount, .. } => {
+            restore_swept_buffer(deps.storage, *amount)?;
+        }
+        // harvested funds stay on the interchain account until the next harvest
+        SudoOperation::DeployHarvest { amount, .. } => {
+            release_value(deps.storage, *amount)?;
+        }
+        // nothing moved, the keeper can rebalance again
+        SudoOperation::Rebalance { .. } => REBALANCE.remove(deps.storage),
+        // the funds stayed on the interchain account
+        SudoOperation::RefundDeposit { deposit_id, .. } => {
+            reset_refund_in_flight(deps.storage, *deposit_id, env.block.time)?;
+        }
+        SudoOperation::Harvest { .. }
+        | SudoOperation::TopUpBuffer { .. }
+        | SudoOperation::DeployBuffer { .. } => {}
+    }
 
-    // WARNING: RETURNING THIS ERROR CLOSES THE CHANNEL.
-    // AN ALTERNATIVE IS TO MAINTAIN AN ERRORS QUEUE AND PUT THE FAILED REQUEST THERE
-    // FOR LATER INSPECTION.
-    // In this particular case, we return an error because not having the sequence id
-    // in the request value implies that a fatal error occurred on Neutron side.
-    let channel_id = request
-        .source_channel
-        .ok_or_else(|| StdError::generic_err("channel_id not found"))?;
-    let payload = read_sudo_payload(deps.storage, channel_id, seq_id).ok();
+    Ok(resp)
+}
 
-    if let Some(payload) = payload {
-        // update but also check that we don't update same seq_id twice
-        ACKNOWLEDGEMENT_RESULTS.update(
+// updates the NAV from a new interchain query result and recomputes the total value
+fn sudo_kv_query_result(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    query_id: u64,
+) -> StdResult<Response<NeutronMsg>> {
+    let (chain_id, queries) = NAV_QUERIES
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+        .into_iter()
+        .find(|(_, queries)| {
+            queries.balance_query_id == Some(query_id)
+                || queries.position_query_id == Some(query_id)
+                || queries.reward_query_id == Some(query_id)
+        })
+        .ok_or_else(|| StdError::generic_err(format!("Unknown interchain query {}", query_id)))?;
+    let chain = HOST_CHAINS.load(deps.storage, &chain_id)?;
+    let result = get_raw_interchain_query_result(deps.as_ref(), query_id)
+        .map_err(|e| StdError::generic_err(e.to_string()))?
+        .result;
+
+    let mut nav = NAV.may_load(deps.storage, &chain_id)?.unwrap_or_default();
+    if queries.balance_query_id == Some(query_id) {
+        // results can be submitted out of order, only newer remote state is taken
+        if result.height < nav.balance_height {
+            return Ok(Response::default());
+        }
+        let balances = Balances::reconstruct(&result.kv_results)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let balance = balances
+            .coins
+            .into_iter()
+            .find(|coin| coin.denom == chain.remote_denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+        nav.ica_balance = Some(balance);
+        nav.balance_height = result.height;
+        nav.balance_updated_at = Some(env.block.time);
+    } else if queries.position_query_id == Some(query_id) {
+        if result.height < nav.position_height {
+            return Ok(Response::default());
+        }
+        let (ica, _) = get_ica(deps.as_ref(), &env, &chain.interchain_account_id)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let position = update_positions(
             deps.storage,
-            (payload.port_id, seq_id),
-            |maybe_ack| -> StdResult<AcknowledgementResult> {
-                match maybe_ack {
-                    Some(_ack) => Err(StdError::generic_err("trying to update same seq_id")),
-                    None => Ok(AcknowledgementResult::Error((payload.message, details))),
-                }
-            },
+            &chain_id,
+            &ica,
+            &result.kv_results,
+            env.block.time,
         )?;
+        // a result queried before the strategies changed is skipped
+        match position {
+            Some(position) => nav.position = Some(position),
+            None => return Ok(Response::default()),
+        }
+        nav.position_height = result.height;
+        nav.position_updated_at = Some(env.block.time);
     } else {
-        let error_msg = "WASMDEBUG: Error: Unable to read sudo payload";
-        deps.api.debug(error_msg);
-        add_error_to_queue(deps.storage, error_msg.to_string());
+        // claimed rewards are not part of the vault value until they are swapped
+        if result.height < nav.reward_height {
+            return Ok(Response::default());
+        }
+        let reward_denom = CONFIG.load(deps.storage)?.reward_denom;
+        let balances = Balances::reconstruct(&result.kv_results)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let balance = balances
+            .coins
+            .into_iter()
+            .find(|coin| Some(&coin.denom) == reward_denom.as_ref())
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+        nav.reward_balance = Some(balance);
+        nav.reward_height = result.height;
+        NAV.save(deps.storage, &chain_id, &nav)?;
+        return Ok(Response::new()
+            .add_attribute("action", "update_rewards")
+            .add_attribute("chain", chain_id)
+            .add_attribute("reward_balance", balance));
     }
+    NAV.save(deps.storage, &chain_id, &nav)?;
+    release_proven(deps.storage, &chain_id)?;
+
+    let mut resp = Response::new()
+        .add_attribute("action", "update_nav")
+        .add_attribute("chain", chain_id)
+        .add_attribute("query_id", query_id.to_string());
+    if let Some(total_value) = recompute_total_value(deps.storage)? {
+        resp = resp.add_attribute("total_value", total_value);
+    }
+    Ok(resp)
+}
 
-    Ok(Response::default())
+// marks the interchain account on `port_id` as closed, transfer ports have no account
+fn close_ica(storage: &mut dyn Storage, port_id: String) -> StdResult<()> {
+    if INTERCHAIN_ACCOUNTS.has(storage, port_id.clone()) {
+        ICA_STATUS.save(storage, port_id, &IcaStatus::Closed)?;
+    }
+    Ok(())
+}
+
+// a failed IBC transfer refunds the swept funds to the contract
+fn restore_swept_buffer(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    BUFFER.update(storage, |buffer| -> StdResult<_> { Ok(buffer + amount) })?;
+    Ok(())
+}
+
+// a failed epoch withdrawal can be resent by the keeper, its shares are burnt already
+fn reset_withdrawing_epoch(
+    storage: &mut dyn Storage,
+    epoch_id: u64,
+    now: Timestamp,
+) -> StdResult<()> {
+    EPOCHS.update(storage, epoch_id, |epoch| -> StdResult<_> {
+        let mut epoch = epoch.ok_or_else(|| StdError::generic_err("epoch not found"))?;
+        epoch.stage = EpochStage::Processing;
+        epoch.updated_at = now;
+        Ok(epoch)
+    })?;
+    Ok(())
+}
+
+// hands a refund that did not leave the interchain account back to the keeper
+fn reset_refund_in_flight(
+    storage: &mut dyn Storage,
+    deposit_id: u64,
+    now: Timestamp,
+) -> StdResult<()> {
+    if DEPOSITS.load(storage, deposit_id)?.stage == DepositStage::RefundInFlight {
+        update_deposit(storage, deposit_id, now, |deposit| {
+            deposit.stage = DepositStage::Refunding;
+        })?;
+    }
+    Ok(())
 }
 
 // prepare_sudo_payload is called from reply handler
@@ -1056,7 +3326,7 @@ fn sudo_error(
 // Such flow msg_with_sudo_callback() -> reply() -> prepare_sudo_payload() -> sudo() allows you "attach" some payload to your SubmitTx message
 // and process this payload when an acknowledgement for the SubmitTx message is received in Sudo handler
 fn prepare_sudo_payload(mut deps: DepsMut, _env: Env, msg: Reply) -> StdResult<Response> {
-    let payload = read_reply_payload(deps.storage)?;
+    let payload = read_reply_payload(deps.storage, msg.id)?;
     let resp: MsgSubmitTxResponse = serde_json_wasm::from_slice(
         msg.result
             .into_result()
@@ -1071,19 +3341,62 @@ fn prepare_sudo_payload(mut deps: DepsMut, _env: Env, msg: Reply) -> StdResult<R
     let seq_id = resp.sequence_id;
     let channel_id = resp.channel;
     save_sudo_payload(deps.branch().storage, channel_id, seq_id, payload)?;
+    remove_reply_payload(deps.storage, msg.id);
     Ok(Response::new())
 }
 
+// saves the id of a registered NAV query, see execute_register_nav_queries
+fn save_nav_query_id(deps: DepsMut, msg: Reply) -> StdResult<Response> {
+    let resp: MsgRegisterInterchainQueryResponse = serde_json_wasm::from_slice(
+        msg.result
+            .into_result()
+            .map_err(StdError::generic_err)?
+            .data
+            .ok_or_else(|| StdError::generic_err("no result"))?
+            .as_slice(),
+    )
+    .map_err(|e| StdError::generic_err(format!("failed to parse response: {:?}", e)))?;
+
+    let chain_id = NAV_QUERY_CHAIN.load(deps.storage)?;
+    NAV_QUERIES.update(deps.storage, &chain_id, |queries| -> StdResult<_> {
+        let mut queries = queries.unwrap_or_default();
+        match msg.id {
+            REGISTER_BALANCE_QUERY_REPLY_ID => queries.balance_query_id = Some(resp.id),
+            REGISTER_POSITION_QUERY_REPLY_ID => queries.position_query_id = Some(resp.id),
+            _ => queries.reward_query_id = Some(resp.id),
+        }
+        Ok(queries)
+    })?;
+    Ok(Response::new().add_attribute("nav_query_id", resp.id.to_string()))
+}
+
+// the host enforces `min_out` on swaps, an acknowledged output below it is not accounted
+fn ensure_min_out(amount: Uint128, min_out: Uint128) -> ContractResult<()> {
+    if amount < min_out {
+        return Err(ContractError::SlippageExceeded { amount, min_out });
+    }
+    Ok(())
+}
+
 fn get_ica(
     deps: Deps<impl CustomQuery>,
     env: &Env,
     interchain_account_id: &str,
-) -> Result<(String, String), StdError> {
+) -> ContractResult<(String, String)> {
     let key = get_port_id(env.contract.address.as_str(), interchain_account_id);
+    let not_ready = || ContractError::IcaNotReady {
+        interchain_account_id: interchain_account_id.to_string(),
+    };
 
+    // a closed or registering account takes no transactions
+    if let Some(status) = ICA_STATUS.may_load(deps.storage, key.clone())? {
+        if status != IcaStatus::Open {
+            return Err(not_ready());
+        }
+    }
     INTERCHAIN_ACCOUNTS
         .load(deps.storage, key)?
-        .ok_or_else(|| StdError::generic_err("Interchain account is not created yet"))
+        .ok_or_else(not_ready)
 }
 
 #[entry_point]
@@ -1091,7 +3404,10 @@ pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> StdResult<Response> {
     deps.api
         .debug(format!("WASMDEBUG: reply msg: {:?}", msg).as_str());
     match msg.id {
-        SUDO_PAYLOAD_REPLY_ID => prepare_sudo_payload(deps, env, msg),
+        REGISTER_BALANCE_QUERY_REPLY_ID
+        | REGISTER_POSITION_QUERY_REPLY_ID
+        | REGISTER_REWARD_QUERY_REPLY_ID => save_nav_query_id(deps, msg),
+        id if id >= SUDO_PAYLOAD_REPLY_ID_START => prepare_sudo_payload(deps, env, msg),
         _ => Err(StdError::generic_err(format!(
             "unsupported reply message id {}",
             msg.id
@@ -1099,18 +3415,18 @@ pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> StdResult<Response> {
     }
 }
 
-fn min_ntrn_ibc_fee(fee: IbcFee) -> IbcFee {
+fn min_ntrn_ibc_fee(fee: IbcFee, fee_denom: &str) -> IbcFee {
     IbcFee {
         recv_fee: fee.recv_fee,
         ack_fee: fee
             .ack_fee
             .into_iter()
-            .filter(|a| a.denom == FEE_DENOM)
+            .filter(|a| a.denom == fee_denom)
             .collect(),
         timeout_fee: fee
             .timeout_fee
             .into_iter()
-            .filter(|a| a.denom == FEE_DENOM)
+            .filter(|a| a.denom == fee_denom)
             .collect(),
     }
 }
@@ -0,0 +1,65 @@
+use cosmwasm_std::{StdError, Uint128};
+use neutron_sdk::NeutronError;
+use thiserror::Error;
+
+pub type ContractResult<T> = Result<T, ContractError>;
+
+/// errors of the vault, clients can match on the variant instead of the message
+#[derive(Error, Debug)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("{0}")]
+    Neutron(NeutronError),
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("Vault is paused")]
+    Paused,
+
+    #[error("No funds sent to contract")]
+    NoFunds,
+
+    #[error("Expected {expected} to be sent")]
+    InvalidFunds { expected: String },
+
+    #[error("Cannot claim more than you have")]
+    InsufficientShares,
+
+    #[error("Cannot Exceed Cap")]
+    CapExceeded,
+
+    #[error("Swap returned {amount}, less than the minimum of {min_out}")]
+    SlippageExceeded { amount: Uint128, min_out: Uint128 },
+
+    #[error("Interchain account {interchain_account_id} is not ready")]
+    IcaNotReady { interchain_account_id: String },
+
+    #[error("A {operation} is already in flight")]
+    OperationInFlight { operation: String },
+
+    #[error("Error {id} can not be retried")]
+    NotRetryable { id: u64 },
+}
+
+// std errors wrapped by the sdk are kept as std errors
+impl From<NeutronError> for ContractError {
+    fn from(err: NeutronError) -> Self {
+        match err {
+            NeutronError::Std(err) => ContractError::Std(err),
+            err => ContractError::Neutron(err),
+        }
+    }
+}
+
+impl From<ContractError> for NeutronError {
+    fn from(err: ContractError) -> Self {
+        match err {
+            ContractError::Std(err) => NeutronError::Std(err),
+            ContractError::Neutron(err) => err,
+            err => NeutronError::Std(StdError::generic_err(err.to_string())),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use cosmwasm_std::{
+    Addr, BankMsg, Coin, Decimal, Decimal256, Event, Response, StdError, Storage, Timestamp,
+    Uint128, Uint256,
+};
+use neutron_sdk::bindings::msg::NeutronMsg;
+
+use crate::{
+    error::ContractResult,
+    nav::ensure_nav_fresh,
+    shares::{share_price, value_to_shares},
+    storage::{Config, FeeRecipient, FeeState, CONFIG, FEE_STATE, TOKEN_INFO, TOTAL_VALUE},
+    token::mint_shares,
+};
+
+// Vault fees are paid by minting shares to the fee recipient, diluting the share holders by
+// the value of the fee instead of moving funds out of the strategy.
+// The management fee accrues on the total value over time. The performance fee is taken on
+// the increase of the share price above the high-water mark, so a loss has to be recovered
+// before yield is charged again.
+// Entry and exit fees are taken in basis points of each deposit and redemption and split
+// between the fee recipients by weight: entry fees as shares, exit fees in the local denom.
+
+const SECONDS_PER_YEAR: u64 = 31_536_000;
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// fee state of a new vault, the first share is priced at the empty vault price
+pub fn initial_fee_state(now: Timestamp) -> FeeState {
+    FeeState {
+        high_water_mark: share_price(Uint128::zero(), Uint128::zero()),
+        last_accrual: now,
+        management_fees: Uint128::zero(),
+        performance_fees: Uint128::zero(),
+    }
+}
+
+/// mints the fees accrued since the last accrual, has to run before shares are priced
+pub fn accrue_fees(
+    store: &mut dyn Storage,
+    now: Timestamp,
+) -> ContractResult<Response<NeutronMsg>> {
+    let config = CONFIG.load(store)?;
+    // fees are only charged against a fresh NAV, the next accrual picks them up otherwise
+    if ensure_nav_fresh(store, now, config.nav_max_age).is_err() {
+        return Ok(Response::new());
+    }
+    let mut state = FEE_STATE.load(store)?;
+    let total_supply = TOKEN_INFO.load(store)?.total_supply;
+    let total_value = TOTAL_VALUE.load(store)?;
+    let price = share_price(total_supply, total_value);
+
+    // nobody to charge, fees start with the next deposit
+    if total_supply.is_zero() {
+        state.last_accrual = now;
+        state.high_water_mark = state.high_water_mark.max(price);
+        FEE_STATE.save(store, &state)?;
+        return Ok(Response::new());
+    }
+
+    let elapsed = now.seconds().saturating_sub(state.last_accrual.seconds());
+    let management_value =
+        total_value * (config.management_fee * Decimal::from_ratio(elapsed, SECONDS_PER_YEAR));
+    let performance_value = if price > state.high_water_mark {
+        let gain = Uint256::from(total_supply) * (price - state.high_water_mark);
+        Uint128::try_from(gain * Decimal256::from(config.performance_fee))
+            .map_err(StdError::from)?
+    } else {
+        Uint128::zero()
+    };
+
+    let fee_value = (management_value + performance_value).min(total_value);
+    let shares = value_to_shares(fee_value, total_supply, total_value - fee_value)?;
+    // dust fees are carried to the next accrual, unless the fee is disabled
+    if shares.is_zero() {
+        if config.management_fee.is_zero() {
+            state.last_accrual = now;
+        }
+        if config.performance_fee.is_zero() {
+            state.high_water_mark = state.high_water_mark.max(price);
+        }
+        FEE_STATE.save(store, &state)?;
+        return Ok(Response::new());
+    }
+
+    let performance_shares = shares.multiply_ratio(performance_value, fee_value);
+    let management_shares = shares - performance_shares;
+    let msgs = mint_shares(store, &config.fee_recipient, shares)?;
+
+    state.management_fees += management_shares;
+    state.performance_fees += performance_shares;
+    state.last_accrual = now;
+    state.high_water_mark = state
+        .high_water_mark
+        .max(share_price(total_supply + shares, total_value));
+    FEE_STATE.save(store, &state)?;
+
+    Ok(Response::new().add_messages(msgs).add_event(
+        Event::new("accrue_fees")
+            .add_attribute("recipient", config.fee_recipient)
+            .add_attribute("management_shares", management_shares)
+            .add_attribute("performance_shares", performance_shares),
+    ))
+}
+
+/// `bps` basis points of `amount`, rounding down
+pub fn bps_fee(amount: Uint128, bps: u16) -> Uint128 {
+    amount.multiply_ratio(bps, BPS_DENOMINATOR)
+}
+
+/// splits `amount` between the recipients by weight, the rounding remainder goes to the first
+pub fn split_fee(recipients: &[FeeRecipient], amount: Uint128) -> Vec<(Addr, Uint128)> {
+    let total_weight: u64 = recipients.iter().map(|r| u64::from(r.weight)).sum();
+    if amount.is_zero() || total_weight == 0 {
+        return vec![];
+    }
+    let mut parts: Vec<(Addr, Uint128)> = recipients
+        .iter()
+        .map(|r| {
+            (
+                r.address.clone(),
+                amount.multiply_ratio(r.weight, total_weight),
+            )
+        })
+        .collect();
+    let paid: Uint128 = parts.iter().map(|(_, part)| *part).sum();
+    parts[0].1 += amount - paid;
+    parts
+}
+
+// one event per recipient so revenue can be attributed by the indexer
+fn fee_event(kind: &str, payer: &Addr, recipient: &Addr, amount: Uint128, asset: &str) -> Event {
+    Event::new("vault_fee")
+        .add_attribute("kind", kind)
+        .add_attribute("payer", payer)
+        .add_attribute("recipient", recipient)
+        .add_attribute("amount", amount)
+        .add_attribute("asset", asset)
+}
+
+/// takes the entry fee from the `shares` issued to `depositor` by minting it to the
+/// fee recipients, returns the fee shares
+pub fn charge_entry_fee(
+    store: &mut dyn Storage,
+    config: &Config,
+    depositor: &Addr,
+    shares: Uint128,
+) -> ContractResult<(Uint128, Response<NeutronMsg>)> {
+    let fee = bps_fee(shares, config.entry_fee_bps);
+    let mut resp = Response::new();
+    for (recipient, amount) in split_fee(&config.fee_recipients, fee) {
+        if amount.is_zero() {
+            continue;
+        }
+        resp = resp
+            .add_messages(mint_shares(store, &recipient, amount)?)
+            .add_event(fee_event("entry", depositor, &recipient, amount, "shares"));
+    }
+    Ok((fee, resp))
+}
+
+/// takes the exit fee from `amount` of the local denom paid out to `redeemer` by sending it
+/// to the fee recipients, returns the fee
+pub fn charge_exit_fee(
+    config: &Config,
+    redeemer: &Addr,
+    amount: Uint128,
+) -> (Uint128, Response<NeutronMsg>) {
+    let fee = bps_fee(amount, config.exit_fee_bps);
+    let mut resp = Response::new();
+    for (recipient, part) in split_fee(&config.fee_recipients, fee) {
+        if part.is_zero() {
+            continue;
+        }
+        resp = resp
+            .add_message(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin::new(part.u128(), config.local_denom.clone())],
+            })
+            .add_event(fee_event(
+                "exit",
+                redeemer,
+                &recipient,
+                part,
+                &config.local_denom,
+            ));
+    }
+    (fee, resp)
+}
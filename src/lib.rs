@@ -3,9 +3,19 @@
 extern crate core;
 
 pub mod contract;
+pub mod error;
 pub mod msg;
 
+mod access;
+mod branch;
+mod fees;
+mod nav;
+mod returns;
+mod shares;
 mod storage;
+mod strategy;
+mod token;
+mod validation;
 
 #[allow(clippy::unwrap_used)]
 #[cfg(test)]
@@ -1,3 +1,6 @@
+use cosmwasm_std::{Binary, Decimal, Uint128};
+use cw20::Expiration;
+use cw20_base::msg::InstantiateMarketingInfo;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -18,26 +21,419 @@ pub enum QueryMsg {
         interchain_account_id: String,
         sequence_id: u64,
     },
-    // this query returns non-critical errors list
-    ErrorsQueue {},
+    // this query returns the operation of a packet waiting for its acknowledgement
+    SudoPayload {
+        channel_id: String,
+        sequence_id: u64,
+    },
+    // this query returns the errors of handled packets, oldest first
+    ErrorsQueue {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
     Supply {},
+    // returns the share balance of the address as cw20 BalanceResponse
     Balance {
         addr: String,
     },
+    // cw20 queries of the vault shares
+    TokenInfo {},
+    Allowance {
+        owner: String,
+        spender: String,
+    },
+    AllAccounts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    AllAllowances {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    MarketingInfo {},
+    // this query returns the vault configuration set at instantiate
+    Config {},
+    // this query returns the owner, pending owner, keeper and guardian
+    Roles {},
+    // this query returns a single deposit and the stage it is in
+    Deposit {
+        id: u64,
+    },
+    // this query returns the deposits of a user, oldest first
+    DepositsByUser {
+        addr: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // this query returns the buffer held on Neutron and its target
+    Buffer {},
+    // this query returns a withdrawal epoch and the stage it is in
+    WithdrawalEpoch {
+        id: u64,
+    },
+    // this query returns the withdrawal tickets of a user that can not be claimed yet
+    PendingWithdrawals {
+        addr: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // this query returns the withdrawal tickets of a user that can be claimed and their value
+    ClaimableWithdrawals {
+        addr: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // this query returns the remote state the total value is computed from
+    Nav {},
+    // this query returns the fees minted so far, the last accrual and the high-water mark
+    FeeState {},
+    // this query returns the registered strategies, their weights and values
+    Strategies {},
+    // this query returns the host chains and their interchain accounts
+    HostChains {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct BufferResponse {
+    /// funds held on Neutron for instant withdrawals
+    pub amount: Uint128,
+    /// top-ups that left the host chains and are credited to the buffer once they arrived
+    pub in_transit: Uint128,
+    /// amount the keeper rebalances the buffer towards
+    pub target: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ClaimableWithdrawal {
+    pub ticket_id: u64,
+    pub epoch: u64,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct NavResponse {
+    /// remote state of each host chain
+    pub chains: Vec<ChainNavResponse>,
+    /// value held remotely that does not belong to share holders yet
+    pub reserved: Uint128,
+    pub total_value: Uint128,
+    /// whether the total value is recent enough to price shares
+    pub fresh: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ChainNavResponse {
+    pub chain: String,
+    pub balance_query_id: Option<u64>,
+    pub position_query_id: Option<u64>,
+    /// target denom held by the interchain account
+    pub ica_balance: Option<Uint128>,
+    /// value of the positions in the strategies on the chain
+    pub position: Option<Uint128>,
+    pub reward_query_id: Option<u64>,
+    /// claimed rewards the next harvest swaps
+    pub reward_balance: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StrategyResponse {
+    pub id: String,
+    pub chain: String,
+    pub kind: StrategyKind,
+    pub weight: u32,
+    pub value: Uint128,
+    pub cap: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct HostChainResponse {
+    pub id: String,
+    pub chain: HostChain,
+    /// address of the interchain account once it was opened
+    pub interchain_account: Option<String>,
+    pub ica_status: Option<IcaStatus>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct MigrateMsg {}
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    /// connection to the home chain, the host chain deposits are swapped on
+    pub connection_id: String,
+    /// id of the interchain account on the home chain
+    pub interchain_account_id: String,
+    /// Red Bank contract on the home chain, registered as the first strategy
+    pub redbank_addr: String,
+    /// Mars incentives contract on the home chain, required to harvest rewards
+    pub incentives_addr: Option<String>,
+    /// denom Red Bank incentives are paid in on the home chain, required to harvest rewards
+    pub reward_denom: Option<String>,
+    /// transfer channel from Neutron to the home chain
+    pub transfer_channel: String,
+    /// transfer channel from the home chain back to Neutron
+    pub return_channel: String,
+    /// denom on the home chain deposits are swapped to and lent out in
+    pub target_denom: String,
+    /// denom of the target asset on Neutron once returned from a host chain
+    pub local_denom: String,
+    /// denom IBC fees are paid in
+    pub fee_denom: String,
+    /// maximum accepted slippage when swapping deposits into the target denom
+    pub max_slippage: Decimal,
+    /// share of the total value kept on Neutron to serve withdrawals instantly
+    #[serde(default)]
+    pub buffer_ratio: Decimal,
+    /// smallest deviation from its target weight, as share of the deployed value, a strategy
+    /// is rebalanced for, defaults to 1%
+    pub rebalance_threshold: Option<Decimal>,
+    /// blocks between updates of the interchain queries backing the NAV, defaults to 100
+    pub nav_update_period: Option<u64>,
+    /// seconds after which the NAV is too old to price shares, defaults to one hour
+    pub nav_max_age: Option<u64>,
+    /// share of the yield above the high-water mark taken as fee, at most 30%
+    #[serde(default)]
+    pub performance_fee: Decimal,
+    /// yearly share of the total value taken as fee, at most 5%
+    #[serde(default)]
+    pub management_fee: Decimal,
+    /// receives the shares minted for fees, defaults to the owner
+    pub fee_recipient: Option<String>,
+    /// fee on deposits in basis points, at most 500
+    #[serde(default)]
+    pub entry_fee_bps: u16,
+    /// fee on redemptions in basis points, at most 500
+    #[serde(default)]
+    pub exit_fee_bps: u16,
+    /// recipients the entry and exit fees are split between by weight
+    #[serde(default)]
+    pub fee_splits: Vec<FeeSplit>,
+    /// number of acknowledgement results kept for queries, defaults to 1000 and at most 100000
+    pub ack_retention: Option<u64>,
+    /// bech32 prefix of addresses on the home chain, e.g. `osmo`
+    pub remote_bech32_prefix: String,
+    /// owner of the vault, defaults to the instantiator
+    pub owner: Option<String>,
+    /// keeper allowed to run operational messages, defaults to the owner
+    pub keeper: Option<String>,
+    /// guardian allowed to pause the vault, defaults to the owner
+    pub guardian: Option<String>,
+    /// name of the share token
+    pub name: String,
+    /// symbol of the share token
+    pub symbol: String,
+    /// decimals of the share token, should match the target denom
+    pub decimals: u8,
+    pub marketing: Option<InstantiateMarketingInfo>,
+    /// ledger the shares are kept in, defaults to the internal cw20 balances
+    #[serde(default)]
+    pub share_backend: ShareBackend,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeSplit {
+    /// e.g. the treasury, a referrer or the insurance fund
+    pub recipient: String,
+    pub weight: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareBackend {
+    /// shares are cw20 balances kept by the contract
+    #[default]
+    Cw20,
+    /// shares are the native TokenFactory denom `factory/{contract}/babu`
+    TokenFactory,
+}
+
+/// IcaStatus tells whether an interchain account takes transactions. Its channel is ordered,
+/// so a timed out packet closes it until the account is registered again.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
-pub struct InstantiateMsg {}
+#[serde(rename_all = "snake_case")]
+pub enum IcaStatus {
+    /// registration was sent, waiting for the channel to open
+    Registering,
+    Open,
+    /// channel was closed by a timeout, the account has to be reopened
+    Closed,
+}
+
+/// HostChain is a chain the vault deploys to through its own interchain account. The target
+/// asset is `remote_denom` on the chain and the local denom once sent back to Neutron.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct HostChain {
+    pub connection_id: String,
+    pub interchain_account_id: String,
+    /// transfer channel from Neutron to the chain
+    pub transfer_channel: String,
+    /// transfer channel from the chain back to Neutron
+    pub return_channel: String,
+    pub remote_denom: String,
+    /// bech32 prefix of addresses on the chain, e.g. `umee`
+    pub remote_bech32_prefix: String,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    /// lends the target denom on the Mars Red Bank contract at `addr`
+    RedBank { addr: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Register {
         connection_id: String,
         interchain_account_id: String,
     },
     Fund {},
+    /// updates the vault config, only callable by the owner
+    UpdateConfig {
+        max_slippage: Option<Decimal>,
+        fee_denom: Option<String>,
+        buffer_ratio: Option<Decimal>,
+        rebalance_threshold: Option<Decimal>,
+        nav_max_age: Option<u64>,
+        performance_fee: Option<Decimal>,
+        management_fee: Option<Decimal>,
+        fee_recipient: Option<String>,
+        entry_fee_bps: Option<u16>,
+        exit_fee_bps: Option<u16>,
+        fee_splits: Option<Vec<FeeSplit>>,
+        ack_retention: Option<u64>,
+    },
+    /// proposes a new owner, who has to accept the ownership to complete the transfer
+    TransferOwnership {
+        new_owner: String,
+    },
+    /// accepts a pending ownership transfer, only callable by the proposed owner
+    AcceptOwnership {},
+    /// updates the keeper and guardian roles, only callable by the owner
+    UpdateRoles {
+        keeper: Option<String>,
+        guardian: Option<String>,
+    },
+    /// stops deposits, callable by the guardian or the owner
+    Pause {},
+    /// resumes deposits, only callable by the owner
+    Unpause {},
+    /// sends a deposit stuck in refunding back to its depositor, callable by the keeper and
+    /// rejected while its refund is in flight
+    RefundDeposit {
+        deposit_id: u64,
+    },
+    /// locks shares into a withdrawal ticket of the current epoch
+    Withdraw {
+        shares: Uint128,
+    },
+    /// closes the current epoch and withdraws its value from the host chain, callable by the keeper
+    ProcessWithdrawals {},
+    /// resends the withdrawal of an epoch that failed or timed out, callable by the keeper
+    RetryWithdrawals {
+        epoch_id: u64,
+    },
+    /// pays out withdrawal tickets of epochs whose funds arrived
+    ClaimWithdrawal {
+        ticket_ids: Vec<u64>,
+    },
+    /// credits the funds sent back from the host chains once they arrived on Neutron, tickets
+    /// of an epoch become claimable when its whole value arrived
+    ConfirmReturns {},
+    /// moves funds between the strategies and the buffer towards its target, callable by the keeper
+    RebalanceBuffer {},
+    /// claims Red Bank incentives, swaps the claimed rewards proven by the reward balance query
+    /// to the target denom and supplies the result to the strategies, callable by the keeper.
+    /// Rewards claimed by this harvest are swapped by the next one.
+    Harvest {
+        min_out: Uint128,
+    },
+    /// registers the interchain queries backing the NAV on a host chain, callable by the keeper.
+    /// The query deposits required by Neutron are paid from the contract balance.
+    RegisterNavQueries {
+        chain: String,
+    },
+    /// mints the fees accrued since the last accrual to the fee recipient
+    AccrueFees {},
+    /// issues the shares of a deployed deposit once the NAV is fresh again
+    CompleteDeposit {
+        deposit_id: u64,
+    },
+    /// registers the interchain account of a host chain again after its channel was closed,
+    /// callable by the keeper
+    ReopenIca {
+        chain: String,
+    },
+    /// adds a host chain or updates its channels, only callable by the owner. The connection can
+    /// only change until the interchain account of the chain is registered.
+    SetHostChain {
+        id: String,
+        chain: HostChain,
+    },
+    /// registers a strategy on a host chain or changes its weight and cap, only callable by
+    /// the owner. The address of a strategy can be changed while it holds no value.
+    SetStrategy {
+        id: String,
+        chain: String,
+        kind: StrategyKind,
+        weight: u32,
+        cap: Option<Uint128>,
+    },
+    /// moves funds between the strategies of a host chain towards their target weights,
+    /// callable by the keeper
+    Rebalance {},
+    /// scans up to `limit` sudo payloads, continuing after the last call, and removes the
+    /// handled ones, the rest of the limit removes acknowledgement results that are no longer
+    /// kept, callable by the keeper
+    PruneState {
+        limit: u32,
+    },
+    /// drops an error from the errors queue, callable by the keeper
+    ClearError {
+        id: u64,
+    },
+    /// submits the failed step of a queued error again, callable by the keeper
+    RetryError {
+        id: u64,
+    },
+    /// cw20 messages of the vault shares
+    Transfer {
+        recipient: String,
+        amount: Uint128,
+    },
+    Burn {
+        amount: Uint128,
+    },
+    Send {
+        contract: String,
+        amount: Uint128,
+        msg: Binary,
+    },
+    IncreaseAllowance {
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    DecreaseAllowance {
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    TransferFrom {
+        owner: String,
+        recipient: String,
+        amount: Uint128,
+    },
+    SendFrom {
+        owner: String,
+        contract: String,
+        amount: Uint128,
+        msg: Binary,
+    },
+    BurnFrom {
+        owner: String,
+        amount: Uint128,
+    },
 }
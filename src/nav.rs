@@ -0,0 +1,111 @@
+use cosmwasm_std::{Order, StdError, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::Bound;
+
+use crate::{
+    returns::buffer_in_transit,
+    storage::{BUFFER, NAV, NAV_QUERIES, PENDING_RELEASES, RESERVED_VALUE, TOTAL_VALUE},
+    strategy::strategy_chains,
+};
+
+// Net asset value of the vault from verified remote state.
+// On every host chain with strategies, interchain queries prove the interchain account's
+// balance of the target denom and its positions in the strategies. The vault value is that
+// remote value plus the buffer on Neutron and the top-ups on their way to it, minus value that
+// is held remotely but does not belong to share holders yet (deposits waiting for their shares,
+// withdrawals waiting to be sent back).
+
+pub const DEFAULT_NAV_UPDATE_PERIOD: u64 = 100;
+pub const DEFAULT_NAV_MAX_AGE: u64 = 60 * 60;
+
+/// sets the total value from the latest remote state once both queries of every chain with
+/// strategies delivered a result
+pub fn recompute_total_value(store: &mut dyn Storage) -> StdResult<Option<Uint128>> {
+    let mut remote = Uint128::zero();
+    for chain in strategy_chains(store)? {
+        let nav = NAV.may_load(store, &chain)?.unwrap_or_default();
+        match (nav.ica_balance, nav.position) {
+            (Some(ica_balance), Some(position)) => remote += ica_balance + position,
+            _ => return Ok(None),
+        }
+    }
+    let total_value = (remote + BUFFER.load(store)? + buffer_in_transit(store)?)
+        .saturating_sub(RESERVED_VALUE.load(store)?);
+    TOTAL_VALUE.save(store, &total_value)?;
+    Ok(Some(total_value))
+}
+
+/// checks that the total value was computed from recent remote state of every chain with
+/// strategies, before it is used to price shares. Without registered queries the vault keeps
+/// its own bookkeeping.
+pub fn ensure_nav_fresh(store: &dyn Storage, now: Timestamp, max_age: u64) -> StdResult<()> {
+    if NAV_QUERIES
+        .keys(store, None, None, Order::Ascending)
+        .next()
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    let fresh = |updated_at: Option<Timestamp>| {
+        updated_at
+            .map(|updated_at| updated_at.plus_seconds(max_age) >= now)
+            .unwrap_or(false)
+    };
+    for chain in strategy_chains(store)? {
+        let nav = NAV.may_load(store, &chain)?.unwrap_or_default();
+        if !fresh(nav.balance_updated_at) || !fresh(nav.position_updated_at) {
+            return Err(StdError::generic_err(format!("NAV of {} is stale", chain)));
+        }
+    }
+    Ok(())
+}
+
+// value held remotely for deposits and withdrawals in flight
+pub fn reserve_value(store: &mut dyn Storage, value: Uint128) -> StdResult<()> {
+    RESERVED_VALUE.update(store, |reserved| -> StdResult<_> { Ok(reserved + value) })?;
+    Ok(())
+}
+
+pub fn release_value(store: &mut dyn Storage, value: Uint128) -> StdResult<()> {
+    RESERVED_VALUE.update(store, |reserved| -> StdResult<_> {
+        Ok(reserved.saturating_sub(value))
+    })?;
+    Ok(())
+}
+
+/// releases value that left the interchain account of `chain` once its balance and positions
+/// were proven at a later host height, until then the remote state still holds it. The host
+/// height the transfer was executed at is not known on Neutron, the newest height proven before
+/// its acknowledgement stands in for it. Without queries on the chain nothing is proven and the
+/// value is released right away.
+pub fn release_after_nav(store: &mut dyn Storage, chain: &str, value: Uint128) -> StdResult<()> {
+    if !NAV_QUERIES.has(store, chain) {
+        return release_value(store, value);
+    }
+    let nav = NAV.may_load(store, chain)?.unwrap_or_default();
+    let height = nav.balance_height.max(nav.position_height);
+    PENDING_RELEASES.update(store, (chain, height), |pending| -> StdResult<_> {
+        Ok(pending.unwrap_or_default() + value)
+    })?;
+    Ok(())
+}
+
+/// releases the pending value of `chain` that its latest balance and position results prove gone
+pub fn release_proven(store: &mut dyn Storage, chain: &str) -> StdResult<()> {
+    let nav = NAV.may_load(store, chain)?.unwrap_or_default();
+    let proven = nav.balance_height.min(nav.position_height);
+    let released = PENDING_RELEASES
+        .prefix(chain)
+        .range(
+            store,
+            None,
+            Some(Bound::exclusive(proven)),
+            Order::Ascending,
+        )
+        .collect::<StdResult<Vec<_>>>()?;
+    for (height, value) in released {
+        PENDING_RELEASES.remove(store, (chain, height));
+        release_value(store, value)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use cosmwasm_std::{Addr, Order, QuerierWrapper, StdError, StdResult, Storage, Timestamp, Uint128};
+use neutron_sdk::bindings::query::NeutronQuery;
+
+use crate::storage::{EpochStage, ReturnPurpose, BUFFER, EPOCHS, EPOCH_FUNDS, RETURNS};
+
+// Funds sent back from the host chains.
+// The acknowledgement of the interchain tx only proves that the transfer left the interchain
+// account, the transfer to Neutron can still time out and put the funds back on the host chain.
+// A return is credited once the balance of the contract covers it on top of the funds that are
+// credited already. Nothing ties the funds that arrived to a transfer, returns are matched by
+// amount in the order they were sent.
+
+/// credits the returns whose funds arrived on Neutron and returns how many were confirmed
+pub fn confirm_returns(
+    store: &mut dyn Storage,
+    querier: &QuerierWrapper<NeutronQuery>,
+    contract: &Addr,
+    denom: &str,
+    now: Timestamp,
+) -> StdResult<u32> {
+    let pending = RETURNS
+        .range(store, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let balance = querier.query_balance(contract, denom)?.amount;
+    let mut held = BUFFER.load(store)? + EPOCH_FUNDS.may_load(store)?.unwrap_or_default();
+    let mut confirmed = 0;
+    for (id, transfer) in pending {
+        if balance < held + transfer.amount {
+            continue;
+        }
+        held += transfer.amount;
+        match transfer.purpose {
+            ReturnPurpose::WithdrawEpoch { epoch_id } => {
+                land_epoch_funds(store, epoch_id, transfer.amount, now)?
+            }
+            ReturnPurpose::TopUpBuffer => {
+                BUFFER.update(store, |buffer| -> StdResult<_> {
+                    Ok(buffer + transfer.amount)
+                })?;
+            }
+        }
+        RETURNS.remove(store, id);
+        confirmed += 1;
+    }
+    Ok(confirmed)
+}
+
+/// funds on their way to the buffer, they left the host chain but did not arrive yet
+pub fn buffer_in_transit(store: &dyn Storage) -> StdResult<Uint128> {
+    RETURNS.range(store, None, None, Order::Ascending).try_fold(
+        Uint128::zero(),
+        |total, transfer| {
+            let (_, transfer) = transfer?;
+            Ok(match transfer.purpose {
+                ReturnPurpose::TopUpBuffer => total + transfer.amount,
+                ReturnPurpose::WithdrawEpoch { .. } => total,
+            })
+        },
+    )
+}
+
+// tickets can be claimed once the whole value of the epoch arrived
+fn land_epoch_funds(
+    store: &mut dyn Storage,
+    epoch_id: u64,
+    amount: Uint128,
+    now: Timestamp,
+) -> StdResult<()> {
+    let funds = EPOCH_FUNDS.may_load(store)?.unwrap_or_default();
+    EPOCH_FUNDS.save(store, &(funds + amount))?;
+    EPOCHS.update(store, epoch_id, |epoch| -> StdResult<_> {
+        let mut epoch = epoch.ok_or_else(|| StdError::generic_err("epoch not found"))?;
+        epoch.landed += amount;
+        if epoch.stage == EpochStage::Returning && epoch.landed >= epoch.value {
+            epoch.stage = EpochStage::Claimable;
+        }
+        epoch.updated_at = now;
+        Ok(epoch)
+    })?;
+    Ok(())
+}
+
+/// takes claimed funds out of the epoch funds
+pub fn pay_epoch_funds(store: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    let funds = EPOCH_FUNDS.may_load(store)?.unwrap_or_default();
+    EPOCH_FUNDS.save(store, &funds.saturating_sub(amount))
+}
@@ -0,0 +1,57 @@
+use cosmwasm_std::{Decimal256, StdError, StdResult, Uint128, Uint256};
+
+// Conversion between deposited value and vault shares.
+// All divisions happen last and round down, so rounding always favours the vault:
+// a depositor never gets more shares than their deposit is worth and a redeemer never
+// gets more value than their shares are worth.
+//
+// Both sides of the share price carry a virtual offset. Without it the first depositor
+// can mint a single share, inflate the vault value and have every later deposit round
+// down to zero shares. With the offset the virtual shares own most of any such donation,
+// so the attacker loses far more than the rounding can ever take from other depositors.
+
+/// shares that exist in the price calculation without belonging to anyone
+pub const VIRTUAL_SHARES: u128 = 1_000_000;
+/// value that exists in the price calculation without being deposited,
+/// equal to VIRTUAL_SHARES so the first deposit is priced 1:1
+pub const VIRTUAL_VALUE: u128 = 1_000_000;
+
+/// shares to mint for a deposit of `value`
+pub fn value_to_shares(
+    value: Uint128,
+    total_supply: Uint128,
+    total_value: Uint128,
+) -> StdResult<Uint128> {
+    let shares = Uint256::from(value).multiply_ratio(
+        Uint256::from(total_supply) + Uint256::from(VIRTUAL_SHARES),
+        Uint256::from(total_value) + Uint256::from(VIRTUAL_VALUE),
+    );
+    Ok(Uint128::try_from(shares)?)
+}
+
+/// value redeemable for `shares`, never more than the vault actually holds
+/// (the virtual value can only be claimed when the vault trades below 1:1)
+pub fn shares_to_value(
+    shares: Uint128,
+    total_supply: Uint128,
+    total_value: Uint128,
+) -> StdResult<Uint128> {
+    if shares > total_supply {
+        return Err(StdError::generic_err(
+            "Cannot redeem more shares than the total supply",
+        ));
+    }
+    let value = Uint256::from(shares).multiply_ratio(
+        Uint256::from(total_value) + Uint256::from(VIRTUAL_VALUE),
+        Uint256::from(total_supply) + Uint256::from(VIRTUAL_SHARES),
+    );
+    Ok(Uint128::try_from(value)?.min(total_value))
+}
+
+/// value of a single share, 1 for an empty vault
+pub fn share_price(total_supply: Uint128, total_value: Uint128) -> Decimal256 {
+    Decimal256::from_ratio(
+        Uint256::from(total_value) + Uint256::from(VIRTUAL_VALUE),
+        Uint256::from(total_supply) + Uint256::from(VIRTUAL_SHARES),
+    )
+}
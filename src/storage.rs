@@ -1,20 +1,235 @@
-use cosmwasm_std::{from_binary, to_vec, Addr, Binary, Order, StdResult, Storage, Uint128};
+use cosmwasm_std::{
+    from_binary, to_vec, Addr, Binary, Coin, Decimal, Decimal256, Order, StdError, StdResult,
+    Storage, Timestamp, Uint128,
+};
+use cw20::{AllowanceResponse, MarketingInfoResponse};
 // use cw20_base::state::TokenInfo;
-use cw_storage_plus::{Item, Map};
+use cw_storage_plus::{Bound, Item, Map};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::msg::{HostChain, IcaStatus, ShareBackend, StrategyKind};
+
 /// SudoPayload is a type that stores information about a transaction that we try to execute
 /// on the host chain. This is a type introduced for our convenience.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct SudoPayload {
-    pub message: String,
     pub port_id: String,
     pub sender: String,
-    // added some more data to the payload
-    pub executor: String,
-    pub amount: Option<Uint128>,
+    /// operation the packet belongs to, advanced by its acknowledgement
+    pub operation: SudoOperation,
+}
+
+/// SudoOperation is a step of a vault flow sent as one packet, with the data its
+/// acknowledgement, error or timeout needs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoOperation {
+    /// deposit sent from Neutron to the interchain account of the home chain
+    TransferDeposit { deposit_id: u64, amount: Uint128 },
+    /// deposit swapped to the target denom on the home chain
+    SwapDeposit { deposit_id: u64, min_out: Uint128 },
+    /// swapped deposit supplied to the strategies of the home chain
+    DeployDeposit {
+        deposit_id: u64,
+        amount: Uint128,
+        allocations: Vec<Allocation>,
+    },
+    /// funds of a failed deposit sent back from the home chain
+    RefundDeposit { deposit_id: u64, amount: Uint128 },
+    /// rewards claimed on the home chain and the proven reward balance swapped to the target denom
+    Harvest {
+        reward_amount: Uint128,
+        min_out: Uint128,
+    },
+    /// swapped rewards supplied to the strategies of the home chain
+    DeployHarvest {
+        amount: Uint128,
+        allocations: Vec<Allocation>,
+    },
+    /// part of the value of a withdrawal epoch withdrawn from a host chain and sent back
+    WithdrawEpoch {
+        epoch_id: u64,
+        chain: String,
+        amount: Uint128,
+        allocations: Vec<Allocation>,
+    },
+    /// funds withdrawn from the strategies of a host chain and sent to the buffer
+    TopUpBuffer {
+        chain: String,
+        amount: Uint128,
+        allocations: Vec<Allocation>,
+    },
+    /// excess of the buffer sent to the interchain account of a host chain
+    SweepBuffer { chain: String, amount: Uint128 },
+    /// swept buffer supplied to the strategies of a host chain
+    DeployBuffer {
+        chain: String,
+        amount: Uint128,
+        allocations: Vec<Allocation>,
+    },
+    /// funds moved between the strategies of a host chain
+    Rebalance {
+        chain: String,
+        allocations: Vec<Allocation>,
+    },
+}
+
+impl SudoOperation {
+    /// name of the operation in acknowledgement results
+    pub fn name(&self) -> &'static str {
+        match self {
+            SudoOperation::TransferDeposit { .. } => "transfer_deposit",
+            SudoOperation::SwapDeposit { .. } => "swap_deposit",
+            SudoOperation::DeployDeposit { .. } => "deploy_deposit",
+            SudoOperation::RefundDeposit { .. } => "refund_deposit",
+            SudoOperation::Harvest { .. } => "harvest",
+            SudoOperation::DeployHarvest { .. } => "deploy_harvest",
+            SudoOperation::WithdrawEpoch { .. } => "withdraw_epoch",
+            SudoOperation::TopUpBuffer { .. } => "top_up_buffer",
+            SudoOperation::SweepBuffer { .. } => "sweep_buffer",
+            SudoOperation::DeployBuffer { .. } => "deploy_buffer",
+            SudoOperation::Rebalance { .. } => "rebalance",
+        }
+    }
+
+    /// strategies the interchain messages were built for, in the order they were sent
+    pub fn allocations(&self) -> &[Allocation] {
+        match self {
+            SudoOperation::DeployDeposit { allocations, .. }
+            | SudoOperation::DeployHarvest { allocations, .. }
+            | SudoOperation::WithdrawEpoch { allocations, .. }
+            | SudoOperation::TopUpBuffer { allocations, .. }
+            | SudoOperation::DeployBuffer { allocations, .. }
+            | SudoOperation::Rebalance { allocations, .. } => allocations,
+            SudoOperation::TransferDeposit { .. }
+            | SudoOperation::SwapDeposit { .. }
+            | SudoOperation::RefundDeposit { .. }
+            | SudoOperation::Harvest { .. }
+            | SudoOperation::SweepBuffer { .. } => &[],
+        }
+    }
+}
+
+/// ErrorKind is the step that failed while handling a packet. Everything but a missing payload
+/// can be retried by the keeper: the handling of the packet outcome or the follow-up it submits.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// no payload was stored for the packet
+    MissingPayload,
+    /// handling the acknowledgement of the packet
+    Response { data: Binary },
+    /// handling the error acknowledgement of the packet
+    Error { details: String },
+    /// handling the timeout of the packet
+    Timeout,
+    /// sending a deposit back to its depositor
+    RefundDeposit { deposit_id: u64 },
+    /// withdrawing the rest of an epoch from the next host chain
+    WithdrawEpoch { epoch_id: u64 },
+    /// supplying harvested rewards to the strategies
+    DeployHarvest { amount: Uint128 },
+    /// supplying the swept buffer to the strategies of a host chain
+    DeployBuffer { chain: String, amount: Uint128 },
+}
+
+/// ErrorEntry is a failure while handling a packet that was not worth closing the channel for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ErrorEntry {
+    pub id: u64,
+    pub timestamp: Timestamp,
+    pub kind: ErrorKind,
+    pub channel: String,
+    pub sequence: u64,
+    /// payload of the packet, if it was found
+    pub payload: Option<SudoPayload>,
+    pub details: String,
+}
+
+/// Config holds the deployment specific parameters of the vault, set at instantiate.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Config {
+    pub incentives_addr: Option<String>,
+    pub reward_denom: Option<String>,
+    pub local_denom: String,
+    pub fee_denom: String,
+    pub max_slippage: Decimal,
+    pub buffer_ratio: Decimal,
+    /// smallest deviation from its target, as share of the deployed value, a strategy is
+    /// rebalanced for
+    pub rebalance_threshold: Decimal,
+    /// blocks between updates of the interchain queries backing the NAV
+    pub nav_update_period: u64,
+    /// seconds after which the NAV is too old to price shares
+    pub nav_max_age: u64,
+    /// share of the yield above the high-water mark taken as fee
+    pub performance_fee: Decimal,
+    /// yearly share of the total value taken as fee
+    pub management_fee: Decimal,
+    /// receives the shares minted for fees
+    pub fee_recipient: Addr,
+    /// fee on deposits in basis points, taken from the issued shares
+    pub entry_fee_bps: u16,
+    /// fee on redemptions in basis points, taken from the paid out value
+    pub exit_fee_bps: u16,
+    /// recipients the entry and exit fees are split between
+    pub fee_recipients: Vec<FeeRecipient>,
+    pub share_backend: ShareBackend,
+    /// number of acknowledgement results kept for queries, older ones are evicted
+    #[serde(default = "default_ack_retention")]
+    pub ack_retention: u64,
+}
+
+pub const DEFAULT_ACK_RETENTION: u64 = 1_000;
+
+fn default_ack_retention() -> u64 {
+    DEFAULT_ACK_RETENTION
+}
+
+/// FeeRecipient receives a share of the entry and exit fees proportional to its weight.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeRecipient {
+    pub address: Addr,
+    pub weight: u32,
+}
+
+/// StrategyInfo is a strategy in the registry and its target share of the deployed funds.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct StrategyInfo {
+    /// host chain the strategy deploys on
+    #[serde(default = "home_chain_id")]
+    pub chain: String,
+    pub kind: StrategyKind,
+    /// new deposits are split between the strategies by weight, 0 retires the strategy
+    pub weight: u32,
+    /// value of the position from the last interchain query result and the
+    /// acknowledged deposits and withdrawals since
+    pub value: Uint128,
+    /// most value rebalancing moves into the strategy
+    #[serde(default)]
+    pub cap: Option<Uint128>,
+}
+
+/// Allocation is the part of a deposit or withdrawal that goes to one strategy.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Allocation {
+    pub strategy: String,
+    pub amount: Uint128,
+    /// whether the amount is taken out of the strategy
+    #[serde(default)]
+    pub withdraw: bool,
+}
+
+/// Roles holds the addresses allowed to administrate and operate the vault.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Roles {
+    pub owner: Addr,
+    /// owner proposed by the current owner, who has not accepted yet
+    pub pending_owner: Option<Addr>,
+    pub keeper: Addr,
+    pub guardian: Addr,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
@@ -39,24 +254,244 @@ pub struct MinterData {
     pub cap: Option<Uint128>,
 }
 
-pub const SUDO_PAYLOAD_REPLY_ID: u64 = 1;
+/// DepositStage is the step of the deposit flow a deposit is currently waiting on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositStage {
+    /// funds are on their way from Neutron to the interchain account
+    Transferring,
+    /// funds arrived on the host chain and are swapped to the target denom
+    Swapping,
+    /// swapped funds are supplied to Red Bank
+    Deploying,
+    /// funds are deployed, shares are issued once the NAV is fresh
+    AwaitingPrice,
+    /// shares were issued to the depositor
+    Completed,
+    /// a step failed and the funds wait on the interchain account to be sent back
+    Refunding,
+    /// the funds are on their way back from the interchain account
+    RefundInFlight,
+    /// the funds were sent back to the depositor
+    Refunded,
+}
+
+/// Deposit tracks a single user deposit through the interchain deposit flow.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Deposit {
+    pub id: u64,
+    pub depositor: Addr,
+    pub input: Coin,
+    pub stage: DepositStage,
+    /// amount that arrived on the host chain
+    pub transferred: Option<Uint128>,
+    /// amount of target denom received from the swap
+    pub swapped: Option<Uint128>,
+    /// amount supplied to the strategies
+    pub deployed: Option<Uint128>,
+    /// shares minted to the depositor
+    pub shares: Option<Uint128>,
+    /// shares minted to the fee recipients as entry fee
+    pub fee: Option<Uint128>,
+    /// funds sent back to the depositor after a failure
+    pub refunded: Option<Coin>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+/// EpochStage is the step of the withdrawal flow an epoch is in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EpochStage {
+    /// collecting withdrawal tickets
+    Open,
+    /// shares are burnt and the value is fixed, waiting to be sent from the host chain
+    Processing,
+    /// withdrawal from Red Bank and the transfer back are in flight
+    Withdrawing,
+    /// the whole value left the host chains, waiting for the funds to arrive on Neutron
+    Returning,
+    /// funds arrived, tickets can be claimed
+    Claimable,
+}
+
+/// WithdrawalEpoch batches the withdrawal tickets processed together.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct WithdrawalEpoch {
+    pub id: u64,
+    pub stage: EpochStage,
+    /// shares locked by the tickets of this epoch
+    pub shares: Uint128,
+    /// value the shares were redeemed for when the epoch was processed
+    pub value: Uint128,
+    /// value sent back so far, the value is withdrawn from one host chain at a time
+    #[serde(default)]
+    pub returned: Uint128,
+    /// value that arrived on Neutron so far
+    #[serde(default)]
+    pub landed: Uint128,
+    pub updated_at: Timestamp,
+}
+
+/// ReturnPurpose is what funds sent back to Neutron are credited to once they arrived.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnPurpose {
+    /// value of a withdrawal epoch sent back from one host chain
+    WithdrawEpoch { epoch_id: u64 },
+    /// funds withdrawn from the strategies to top up the buffer
+    TopUpBuffer,
+}
+
+/// ReturnTransfer is a transfer to the contract that left the host chain but is not known to
+/// have arrived yet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ReturnTransfer {
+    pub id: u64,
+    pub amount: Uint128,
+    pub purpose: ReturnPurpose,
+    pub created_at: Timestamp,
+}
+
+/// WithdrawalTicket is a claim on a pro-rata part of the value of its epoch.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct WithdrawalTicket {
+    pub id: u64,
+    pub owner: Addr,
+    pub epoch: u64,
+    pub shares: Uint128,
+    pub created_at: Timestamp,
+}
+
+/// NavQueries holds the ids of the interchain queries backing the NAV on a host chain.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct NavQueries {
+    /// balance of the target denom held by the interchain account
+    pub balance_query_id: Option<u64>,
+    /// positions of the interchain account in the strategies
+    pub position_query_id: Option<u64>,
+    /// balance of the reward denom held by the interchain account, home chain only
+    pub reward_query_id: Option<u64>,
+}
 
-pub const REPLY_ID_STORAGE: Item<Vec<u8>> = Item::new("reply_queue_id");
+/// NavState is the latest remote state delivered by the interchain queries.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct NavState {
+    pub ica_balance: Option<Uint128>,
+    /// value of the positions in the strategies on the chain
+    pub position: Option<Uint128>,
+    /// host chain heights the results were proven at
+    pub balance_height: u64,
+    pub position_height: u64,
+    pub balance_updated_at: Option<Timestamp>,
+    pub position_updated_at: Option<Timestamp>,
+    /// claimed rewards held by the interchain account, taken by the next harvest
+    pub reward_balance: Option<Uint128>,
+    pub reward_height: u64,
+}
+
+/// FeeState tracks the fees minted to the fee recipient.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeState {
+    /// highest share price fees were accrued at, performance fees are only taken above it
+    pub high_water_mark: Decimal256,
+    pub last_accrual: Timestamp,
+    /// shares minted for management fees so far
+    pub management_fees: Uint128,
+    /// shares minted for performance fees so far
+    pub performance_fees: Uint128,
+}
+
+pub const REGISTER_BALANCE_QUERY_REPLY_ID: u64 = 2;
+pub const REGISTER_POSITION_QUERY_REPLY_ID: u64 = 3;
+pub const REGISTER_REWARD_QUERY_REPLY_ID: u64 = 4;
+/// reply ids from here on are allocated to interchain txs, one per submessage
+pub const SUDO_PAYLOAD_REPLY_ID_START: u64 = 1_000;
+
+/// id of the host chain set up at instantiate, deposits are swapped and rewards harvested there
+pub const HOME_CHAIN_ID: &str = "osmosis";
+
+fn home_chain_id() -> String {
+    HOME_CHAIN_ID.to_string()
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+pub const ROLES: Item<Roles> = Item::new("roles");
+pub const PAUSED: Item<bool> = Item::new("paused");
+
+// payloads of submitted txs waiting for their reply, by reply id
+pub const REPLY_PAYLOADS: Map<u64, Vec<u8>> = Map::new("reply_payloads");
+pub const NEXT_REPLY_ID: Item<u64> = Item::new("next_reply_id");
 pub const SUDO_PAYLOAD: Map<(String, u64), Vec<u8>> = Map::new("sudo_payload");
+// last payload key scanned by PruneState, the next call continues after it
+pub const PRUNE_CURSOR: Item<(String, u64)> = Item::new("prune_cursor");
 pub const INTERCHAIN_ACCOUNTS: Map<String, Option<(String, String)>> =
     Map::new("interchain_accounts");
+// status of the interchain accounts by port id, accounts without a status are open
+pub const ICA_STATUS: Map<String, IcaStatus> = Map::new("ica_status");
 
 // interchain transaction responses - ack/err/timeout state to query later
 pub const ACKNOWLEDGEMENT_RESULTS: Map<(String, u64), AcknowledgementResult> =
-    Map::new("acknowledgement_results");
+    Map::new("ack_results");
+// keys of the acknowledgement results in the order they were stored, the oldest are evicted
+// once more than the configured retention are kept
+pub const ACK_RESULT_KEYS: Map<u64, (String, u64)> = Map::new("ack_result_keys");
+pub const NEXT_ACK_RESULT: Item<u64> = Item::new("next_ack_result");
 
-pub const ERRORS_QUEUE: Map<u32, String> = Map::new("errors_queue");
+pub const ERRORS_QUEUE: Map<u64, ErrorEntry> = Map::new("error_entries");
+pub const NEXT_ERROR_ID: Item<u64> = Item::new("next_error_id");
 
-pub const TOTAL_SUPPLY: Item<Uint128> = Item::new("total_supply");
 pub const TOTAL_VALUE: Item<Uint128> = Item::new("total_value");
+// part of the total value held on Neutron in the local denom
+pub const BUFFER: Item<Uint128> = Item::new("buffer");
+// value held on the host chain for deposits without shares and withdrawals not sent back yet
+pub const RESERVED_VALUE: Item<Uint128> = Item::new("reserved_value");
+// reserved value that left a host chain, by chain and the host height the NAV has to be proven
+// above before it is released
+pub const PENDING_RELEASES: Map<(&str, u64), Uint128> = Map::new("pending_releases");
+
+// host chains by id
+pub const HOST_CHAINS: Map<&str, HostChain> = Map::new("host_chains");
 
+// NAV queries and their latest results by host chain
+pub const NAV_QUERIES: Map<&str, NavQueries> = Map::new("chain_nav_queries");
+pub const NAV: Map<&str, NavState> = Map::new("chain_nav");
+// host chain of the NAV queries waiting for their registration replies
+pub const NAV_QUERY_CHAIN: Item<String> = Item::new("nav_query_chain");
+
+pub const FEE_STATE: Item<FeeState> = Item::new("fee_state");
+
+// registered strategies by id
+pub const STRATEGIES: Map<&str, StrategyInfo> = Map::new("strategies");
+// allocations of the rebalance waiting for its acknowledgement, if any
+pub const REBALANCE: Item<Vec<Allocation>> = Item::new("rebalance");
+
+// share ledger, the total supply of shares lives in TokenInfo
 pub const TOKEN_INFO: Item<TokenInfo> = Item::new("token_info");
+pub const MARKETING_INFO: Item<MarketingInfoResponse> = Item::new("marketing_info");
+// only set for the TokenFactory share backend
+pub const SHARE_DENOM: Item<String> = Item::new("share_denom");
 pub const BALANCES: Map<Addr, Uint128> = Map::new("balances");
+pub const ALLOWANCES: Map<(&Addr, &Addr), AllowanceResponse> = Map::new("allowances");
+
+pub const DEPOSIT_SEQ: Item<u64> = Item::new("deposit_seq");
+pub const DEPOSITS: Map<u64, Deposit> = Map::new("deposits");
+// index of deposit ids per depositor
+pub const USER_DEPOSITS: Map<(&Addr, u64), ()> = Map::new("user_deposits");
+
+// epoch new withdrawal tickets are added to
+pub const CURRENT_EPOCH: Item<u64> = Item::new("current_epoch");
+pub const EPOCHS: Map<u64, WithdrawalEpoch> = Map::new("withdrawal_epochs");
+pub const TICKET_SEQ: Item<u64> = Item::new("ticket_seq");
+// tickets are removed once claimed
+pub const TICKETS: Map<u64, WithdrawalTicket> = Map::new("withdrawal_tickets");
+pub const USER_TICKETS: Map<(&Addr, u64), ()> = Map::new("user_withdrawal_tickets");
+// funds of withdrawal epochs that arrived on Neutron and were not claimed yet
+pub const EPOCH_FUNDS: Item<Uint128> = Item::new("epoch_funds");
+
+pub const RETURN_SEQ: Item<u64> = Item::new("return_seq");
+// transfers to the contract acknowledged on the host chain, removed once the funds arrived
+pub const RETURNS: Map<u64, ReturnTransfer> = Map::new("returns");
 
 /// Serves for storing acknowledgement calls for interchain transactions
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema, Debug)]
@@ -70,29 +505,70 @@ pub enum AcknowledgementResult {
     Timeout(String),
 }
 
-pub fn save_reply_payload(store: &mut dyn Storage, payload: SudoPayload) -> StdResult<()> {
-    REPLY_ID_STORAGE.save(store, &to_vec(&payload)?)
+/// stores the payload under a fresh reply id, so several txs can be submitted at once
+pub fn save_reply_payload(store: &mut dyn Storage, payload: SudoPayload) -> StdResult<u64> {
+    let id = NEXT_REPLY_ID
+        .may_load(store)?
+        .unwrap_or(SUDO_PAYLOAD_REPLY_ID_START);
+    NEXT_REPLY_ID.save(
+        store,
+        &id.checked_add(1).unwrap_or(SUDO_PAYLOAD_REPLY_ID_START),
+    )?;
+    REPLY_PAYLOADS.save(store, id, &to_vec(&payload)?)?;
+    Ok(id)
 }
 
-pub fn read_reply_payload(store: &dyn Storage) -> StdResult<SudoPayload> {
-    let data = REPLY_ID_STORAGE.load(store)?;
+pub fn read_reply_payload(store: &dyn Storage, id: u64) -> StdResult<SudoPayload> {
+    let data = REPLY_PAYLOADS.load(store, id)?;
     from_binary(&Binary(data))
 }
 
-pub fn add_error_to_queue(store: &mut dyn Storage, error_msg: String) -> Option<()> {
-    let result = ERRORS_QUEUE
-        .keys(store, None, None, Order::Descending)
-        .next()
-        .and_then(|data| data.ok())
-        .map(|c| c + 1)
-        .or(Some(0));
+pub fn remove_reply_payload(store: &mut dyn Storage, id: u64) {
+    REPLY_PAYLOADS.remove(store, id)
+}
 
-    result.and_then(|idx| ERRORS_QUEUE.save(store, idx, &error_msg).ok())
+/// queues an error under a fresh id, ids of cleared errors are not reused
+pub fn add_error_to_queue(
+    store: &mut dyn Storage,
+    timestamp: Timestamp,
+    kind: ErrorKind,
+    channel: String,
+    sequence: u64,
+    payload: Option<SudoPayload>,
+    details: String,
+) -> StdResult<u64> {
+    let id = NEXT_ERROR_ID.may_load(store)?.unwrap_or_default();
+    NEXT_ERROR_ID.save(store, &(id + 1))?;
+    ERRORS_QUEUE.save(
+        store,
+        id,
+        &ErrorEntry {
+            id,
+            timestamp,
+            kind,
+            channel,
+            sequence,
+            payload,
+            details,
+        },
+    )?;
+    Ok(id)
 }
 
-pub fn read_errors_from_queue(store: &dyn Storage) -> StdResult<Vec<(Vec<u8>, String)>> {
+pub fn read_errors_from_queue(
+    store: &dyn Storage,
+    start_after: Option<u64>,
+    limit: usize,
+) -> StdResult<Vec<ErrorEntry>> {
     ERRORS_QUEUE
-        .range_raw(store, None, None, Order::Ascending)
+        .range(
+            store,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|entry| entry.map(|(_, entry)| entry))
         .collect()
 }
 
@@ -105,6 +581,17 @@ pub fn read_sudo_payload(
     from_binary(&Binary(data))
 }
 
+/// reads the payload of a packet and removes it, each packet is handled once
+pub fn take_sudo_payload(
+    store: &mut dyn Storage,
+    channel_id: String,
+    seq_id: u64,
+) -> StdResult<SudoPayload> {
+    let payload = read_sudo_payload(store, channel_id.clone(), seq_id)?;
+    SUDO_PAYLOAD.remove(store, (channel_id, seq_id));
+    Ok(payload)
+}
+
 pub fn save_sudo_payload(
     store: &mut dyn Storage,
     channel_id: String,
@@ -113,3 +600,150 @@ pub fn save_sudo_payload(
 ) -> StdResult<()> {
     SUDO_PAYLOAD.save(store, (channel_id, seq_id), &to_vec(&payload)?)
 }
+
+/// stores the result of a packet, evicting the oldest results above the retention.
+/// a replayed packet is only rejected while its result is kept, eviction ends the
+/// replay protection of the packet
+pub fn save_ack_result(
+    store: &mut dyn Storage,
+    port_id: String,
+    seq_id: u64,
+    result: AcknowledgementResult,
+) -> StdResult<()> {
+    // check that we don't update same seq_id twice
+    if ACKNOWLEDGEMENT_RESULTS.has(store, (port_id.clone(), seq_id)) {
+        return Err(StdError::generic_err("trying to update same seq_id"));
+    }
+    ACKNOWLEDGEMENT_RESULTS.save(store, (port_id.clone(), seq_id), &result)?;
+    let next = NEXT_ACK_RESULT.may_load(store)?.unwrap_or_default();
+    ACK_RESULT_KEYS.save(store, next, &(port_id, seq_id))?;
+    NEXT_ACK_RESULT.save(store, &(next + 1))?;
+    // a lowered retention is caught up with over the next results
+    evict_ack_results(store, 2)?;
+    Ok(())
+}
+
+/// removes up to `limit` of the oldest results above the retention, returns the number removed
+pub fn evict_ack_results(store: &mut dyn Storage, limit: u32) -> StdResult<u32> {
+    let retention = CONFIG.load(store)?.ack_retention;
+    let next = NEXT_ACK_RESULT.may_load(store)?.unwrap_or_default();
+    let oldest: Vec<(u64, (String, u64))> = ACK_RESULT_KEYS
+        .range(store, None, None, Order::Ascending)
+        .take(limit as usize)
+        .collect::<StdResult<_>>()?;
+    let mut removed = 0;
+    for (idx, key) in oldest {
+        if next - idx <= retention {
+            break;
+        }
+        ACK_RESULT_KEYS.remove(store, idx);
+        ACKNOWLEDGEMENT_RESULTS.remove(store, key);
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+pub fn create_deposit(
+    store: &mut dyn Storage,
+    depositor: Addr,
+    input: Coin,
+    now: Timestamp,
+) -> StdResult<Deposit> {
+    let id = DEPOSIT_SEQ.may_load(store)?.unwrap_or_default() + 1;
+    DEPOSIT_SEQ.save(store, &id)?;
+
+    let deposit = Deposit {
+        id,
+        depositor,
+        input,
+        stage: DepositStage::Transferring,
+        transferred: None,
+        swapped: None,
+        deployed: None,
+        shares: None,
+        fee: None,
+        refunded: None,
+        created_at: now,
+        updated_at: now,
+    };
+    DEPOSITS.save(store, id, &deposit)?;
+    USER_DEPOSITS.save(store, (&deposit.depositor, id), &())?;
+    Ok(deposit)
+}
+
+pub fn update_deposit<F: FnOnce(&mut Deposit)>(
+    store: &mut dyn Storage,
+    id: u64,
+    now: Timestamp,
+    action: F,
+) -> StdResult<Deposit> {
+    let mut deposit = DEPOSITS
+        .may_load(store, id)?
+        .ok_or_else(|| StdError::generic_err(format!("Deposit {} not found", id)))?;
+    action(&mut deposit);
+    deposit.updated_at = now;
+    DEPOSITS.save(store, id, &deposit)?;
+    Ok(deposit)
+}
+
+pub fn open_epoch(store: &mut dyn Storage, id: u64, now: Timestamp) -> StdResult<()> {
+    CURRENT_EPOCH.save(store, &id)?;
+    EPOCHS.save(
+        store,
+        id,
+        &WithdrawalEpoch {
+            id,
+            stage: EpochStage::Open,
+            shares: Uint128::zero(),
+            value: Uint128::zero(),
+            returned: Uint128::zero(),
+            landed: Uint128::zero(),
+            updated_at: now,
+        },
+    )
+}
+
+pub fn create_ticket(
+    store: &mut dyn Storage,
+    owner: Addr,
+    shares: Uint128,
+    now: Timestamp,
+) -> StdResult<WithdrawalTicket> {
+    let id = TICKET_SEQ.may_load(store)?.unwrap_or_default() + 1;
+    TICKET_SEQ.save(store, &id)?;
+
+    let mut epoch = EPOCHS.load(store, CURRENT_EPOCH.load(store)?)?;
+    epoch.shares += shares;
+    epoch.updated_at = now;
+    EPOCHS.save(store, epoch.id, &epoch)?;
+
+    let ticket = WithdrawalTicket {
+        id,
+        owner,
+        epoch: epoch.id,
+        shares,
+        created_at: now,
+    };
+    TICKETS.save(store, id, &ticket)?;
+    USER_TICKETS.save(store, (&ticket.owner, id), &())?;
+    Ok(ticket)
+}
+
+pub fn create_return(
+    store: &mut dyn Storage,
+    amount: Uint128,
+    purpose: ReturnPurpose,
+    now: Timestamp,
+) -> StdResult<ReturnTransfer> {
+    let id = RETURN_SEQ.may_load(store)?.unwrap_or_default() + 1;
+    RETURN_SEQ.save(store, &id)?;
+
+    let transfer = ReturnTransfer {
+        id,
+        amount,
+        purpose,
+        created_at: now,
+    };
+    RETURNS.save(store, id, &transfer)?;
+    Ok(transfer)
+}
@@ -0,0 +1,649 @@
+use bech32::FromBase32;
+use cosmos_sdk_proto::cosmos::base::abci::v1beta1::MsgData;
+use cosmos_sdk_proto::traits::Message;
+use cosmwasm_std::{
+    from_slice, Binary, Decimal, Order, StdError, StdResult, Storage, Timestamp, Uint128,
+};
+use neutron_sdk::{
+    bindings::types::{KVKey, ProtobufAny, StorageValue},
+    interchain_txs::helpers::decode_message_response,
+};
+use osmosis_std::types::cosmwasm::wasm::v1::{MsgExecuteContract, MsgExecuteContractResponse};
+use serde::Deserialize;
+
+use crate::{
+    msg::StrategyKind,
+    storage::{Allocation, StrategyInfo, HOST_CHAINS, REBALANCE, STRATEGIES},
+};
+
+// Strategies put the target denom held by an interchain account to work on its host chain.
+// A strategy builds the interchain messages entering and exiting its position, decodes their
+// acknowledgements and values its position from interchain query results. Each strategy is
+// bound to a host chain and an interchain tx only reaches the strategies of one chain.
+// Deposits are split between the strategies of a chain by weight, withdrawals by the value held
+// in each, and the keeper rebalances the strategies of a chain towards their weights. Funds move
+// between chains through the buffer on Neutron.
+
+/// id of the Red Bank strategy registered at instantiate
+pub const REDBANK_STRATEGY_ID: &str = "redbank";
+pub const DEFAULT_REBALANCE_THRESHOLD: Decimal = Decimal::percent(1);
+
+pub trait Strategy {
+    /// messages for `ica` to put `amount` of the target denom into the strategy
+    fn deposit_msgs(&self, ica: &str, amount: Uint128) -> StdResult<Vec<ProtobufAny>>;
+    /// messages for `ica` to take `amount` of the target denom out of the strategy
+    fn withdraw_msgs(&self, ica: &str, amount: Uint128) -> StdResult<Vec<ProtobufAny>>;
+    /// consumes the acknowledged responses to `deposit_msgs`
+    fn decode_deposit_responses(
+        &self,
+        responses: &mut dyn Iterator<Item = &MsgData>,
+    ) -> StdResult<()>;
+    /// consumes the acknowledged responses to `withdraw_msgs`
+    fn decode_withdraw_responses(
+        &self,
+        responses: &mut dyn Iterator<Item = &MsgData>,
+    ) -> StdResult<()>;
+    /// keys on the host chain the position of `ica` is stored under
+    fn position_keys(&self, ica: &str) -> StdResult<Vec<KVKey>>;
+    /// value of the position in the target denom from the values of `position_keys`
+    fn position_value(&self, kv_results: &[StorageValue], now: Timestamp) -> StdResult<Uint128>;
+}
+
+impl StrategyKind {
+    /// the strategy investing `target_denom`
+    pub fn strategy(&self, target_denom: &str) -> Box<dyn Strategy> {
+        match self {
+            StrategyKind::RedBank { addr } => Box::new(RedBank {
+                addr: addr.clone(),
+                denom: target_denom.to_string(),
+            }),
+        }
+    }
+}
+
+/// registered strategies ordered by id
+pub fn load_strategies(store: &dyn Storage) -> StdResult<Vec<(String, StrategyInfo)>> {
+    STRATEGIES
+        .range(store, None, None, Order::Ascending)
+        .collect()
+}
+
+/// strategies on the host chain `chain` ordered by id
+pub fn chain_strategies(
+    store: &dyn Storage,
+    chain: &str,
+) -> StdResult<Vec<(String, StrategyInfo)>> {
+    Ok(load_strategies(store)?
+        .into_iter()
+        .filter(|(_, info)| info.chain == chain)
+        .collect())
+}
+
+/// host chains with at least one strategy, ordered by id
+pub fn strategy_chains(store: &dyn Storage) -> StdResult<Vec<String>> {
+    let mut chains: Vec<String> = load_strategies(store)?
+        .into_iter()
+        .map(|(_, info)| info.chain)
+        .collect();
+    chains.sort();
+    chains.dedup();
+    Ok(chains)
+}
+
+// the strategy of `info` investing the target denom of its host chain
+fn load_strategy(store: &dyn Storage, info: &StrategyInfo) -> StdResult<Box<dyn Strategy>> {
+    let chain = HOST_CHAINS.load(store, &info.chain)?;
+    Ok(info.kind.strategy(&chain.remote_denom))
+}
+
+/// splits a deposit of `amount` between the strategies on `chain` by weight, what a strategy
+/// can not take because of its cap goes to the others. The rounding remainder goes to the
+/// first strategy with room left.
+pub fn allocate_deposit(
+    store: &dyn Storage,
+    chain: &str,
+    amount: Uint128,
+) -> StdResult<Vec<Allocation>> {
+    // a cap limits the value a strategy holds, only the room left takes the deposit
+    let strategies: Vec<(String, StrategyInfo, Uint128)> = expected_values(store, chain)?
+        .into_iter()
+        .map(|(id, mut info, value)| {
+            info.cap = info.cap.map(|cap| cap.saturating_sub(value));
+            (id, info, value)
+        })
+        .collect();
+    if strategies.iter().all(|(_, info, _)| info.weight == 0) {
+        return Err(StdError::generic_err(format!(
+            "No strategy to deploy to on {}",
+            chain
+        )));
+    }
+    let mut allocations: Vec<Allocation> = strategies
+        .iter()
+        .zip(target_values(&strategies, amount))
+        .map(|((id, _, _), target)| Allocation {
+            strategy: id.clone(),
+            amount: target,
+            withdraw: false,
+        })
+        .collect();
+    let mut remainder = amount - allocations.iter().map(|a| a.amount).sum::<Uint128>();
+    for ((_, info, _), allocation) in strategies.iter().zip(allocations.iter_mut()) {
+        if info.weight == 0 {
+            continue;
+        }
+        let room = info
+            .cap
+            .map_or(remainder, |cap| cap.saturating_sub(allocation.amount));
+        let added = remainder.min(room);
+        allocation.amount += added;
+        remainder -= added;
+    }
+    if !remainder.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Strategies on {} are at their caps",
+            chain
+        )));
+    }
+    Ok(allocations
+        .into_iter()
+        .filter(|a| !a.amount.is_zero())
+        .collect())
+}
+
+/// splits a withdrawal of `amount` between the strategies on `chain` by the value they hold,
+/// the rounding remainder comes from the largest. Without known values it is split like a
+/// deposit.
+pub fn allocate_withdrawal(
+    store: &dyn Storage,
+    chain: &str,
+    amount: Uint128,
+) -> StdResult<Vec<Allocation>> {
+    let values: Vec<(String, Uint128)> = expected_values(store, chain)?
+        .into_iter()
+        .map(|(id, _, value)| (id, value))
+        .collect();
+    if values.iter().all(|(_, value)| value.is_zero()) {
+        let mut allocations = allocate_deposit(store, chain, amount)?;
+        for allocation in allocations.iter_mut() {
+            allocation.withdraw = true;
+        }
+        return Ok(allocations);
+    }
+    Ok(split(amount, &values, true))
+}
+
+/// allocations moving funds from the strategies on `chain` above their target value to the
+/// ones below, withdrawals first. Targets split the value deployed on the chain by weight,
+/// what a strategy can not take because of its cap goes to the others. None if the strategies
+/// are within the threshold.
+pub fn plan_rebalance(
+    store: &dyn Storage,
+    chain: &str,
+    threshold: Decimal,
+) -> StdResult<Option<Vec<Allocation>>> {
+    let strategies = expected_values(store, chain)?;
+    let total: Uint128 = strategies.iter().map(|(_, _, value)| *value).sum();
+    let targets = target_values(&strategies, total);
+    let min_move = total * threshold;
+
+    let mut excess = vec![];
+    let mut deficit = vec![];
+    let mut off_target = false;
+    for ((id, _, value), target) in strategies.iter().zip(targets) {
+        off_target |= (*value).max(target) - (*value).min(target) > min_move;
+        excess.push((id.clone(), value.saturating_sub(target)));
+        deficit.push((id.clone(), target.saturating_sub(*value)));
+    }
+    let moved = excess
+        .iter()
+        .map(|(_, amount)| *amount)
+        .sum::<Uint128>()
+        .min(deficit.iter().map(|(_, amount)| *amount).sum());
+    if !off_target || moved.is_zero() {
+        return Ok(None);
+    }
+
+    let mut allocations = split(moved, &excess, true);
+    allocations.extend(split(moved, &deficit, false));
+    Ok(Some(allocations))
+}
+
+/// host chain `amount` swept from the buffer goes to: the one furthest below its share of
+/// the total weight once the amount is deployed
+pub fn deposit_chain(store: &dyn Storage, amount: Uint128) -> StdResult<String> {
+    let chains = chain_values(store)?;
+    let total_weight: u64 = chains.iter().map(|(_, weight, _)| *weight).sum();
+    let total_value: Uint128 = chains.iter().map(|(_, _, value)| *value).sum();
+    let mut best: Option<(String, Uint128)> = None;
+    for (chain, weight, value) in chains {
+        if weight == 0 {
+            continue;
+        }
+        let deficit = (total_value + amount)
+            .multiply_ratio(weight, total_weight)
+            .saturating_sub(value);
+        let further = match &best {
+            Some((_, best)) => deficit > *best,
+            None => true,
+        };
+        if further {
+            best = Some((chain, deficit));
+        }
+    }
+    best.map(|(chain, _)| chain)
+        .ok_or_else(|| StdError::generic_err("No strategy to deploy to"))
+}
+
+/// host chain `amount` is withdrawn from next: the one holding the most value, with the part
+/// of `amount` it can cover
+pub fn withdrawal_chain(store: &dyn Storage, amount: Uint128) -> StdResult<(String, Uint128)> {
+    let chains = chain_values(store)?;
+    let mut richest: Option<&(String, u64, Uint128)> = None;
+    for chain in chains.iter() {
+        let richer = match richest {
+            Some((_, _, value)) => chain.2 > *value,
+            None => true,
+        };
+        if richer {
+            richest = Some(chain);
+        }
+    }
+    match richest {
+        Some((chain, _, value)) if !value.is_zero() => Ok((chain.clone(), amount.min(*value))),
+        // without known values the deposit split is withdrawn from
+        _ => chains
+            .into_iter()
+            .find(|(_, weight, _)| *weight > 0)
+            .map(|(chain, _, _)| (chain, amount))
+            .ok_or_else(|| StdError::generic_err("No strategy to withdraw from")),
+    }
+}
+
+// total weight and expected value of the strategies on each host chain
+fn chain_values(store: &dyn Storage) -> StdResult<Vec<(String, u64, Uint128)>> {
+    let mut chains = vec![];
+    for chain in strategy_chains(store)? {
+        let strategies = expected_values(store, &chain)?;
+        let weight = strategies
+            .iter()
+            .map(|(_, info, _)| info.weight as u64)
+            .sum();
+        let value = strategies.iter().map(|(_, _, value)| *value).sum();
+        chains.push((chain, weight, value));
+    }
+    Ok(chains)
+}
+
+// value of each strategy on `chain` once the rebalance in flight is acknowledged. The
+// interchain account executes its txs in order, so anything sent now sees the rebalanced
+// positions.
+fn expected_values(
+    store: &dyn Storage,
+    chain: &str,
+) -> StdResult<Vec<(String, StrategyInfo, Uint128)>> {
+    let rebalance = REBALANCE.may_load(store)?.unwrap_or_default();
+    Ok(chain_strategies(store, chain)?
+        .into_iter()
+        .map(|(id, info)| {
+            let value = rebalance
+                .iter()
+                .filter(|allocation| allocation.strategy == id)
+                .fold(info.value, |value, allocation| {
+                    if allocation.withdraw {
+                        value.saturating_sub(allocation.amount)
+                    } else {
+                        value + allocation.amount
+                    }
+                });
+            (id, info, value)
+        })
+        .collect())
+}
+
+// splits `total` by weight, strategies whose share exceeds their cap get the cap and the rest
+// is split between the others again
+fn target_values(strategies: &[(String, StrategyInfo, Uint128)], total: Uint128) -> Vec<Uint128> {
+    let mut targets: Vec<Option<Uint128>> = strategies
+        .iter()
+        .map(|(_, info, _)| (info.weight == 0).then(Uint128::zero))
+        .collect();
+    let mut remaining = total;
+    loop {
+        let total_weight: u64 = strategies
+            .iter()
+            .zip(&targets)
+            .filter(|(_, target)| target.is_none())
+            .map(|((_, info, _), _)| info.weight as u64)
+            .sum();
+        if total_weight == 0 {
+            break;
+        }
+        let mut capped = false;
+        for ((_, info, _), target) in strategies.iter().zip(targets.iter_mut()) {
+            if target.is_some() {
+                continue;
+            }
+            match info.cap {
+                Some(cap) if remaining.multiply_ratio(info.weight, total_weight) > cap => {
+                    *target = Some(cap);
+                    remaining -= cap;
+                    capped = true;
+                }
+                _ => {}
+            }
+        }
+        if !capped {
+            for ((_, info, _), target) in strategies.iter().zip(targets.iter_mut()) {
+                if target.is_none() {
+                    *target = Some(remaining.multiply_ratio(info.weight, total_weight));
+                }
+            }
+            break;
+        }
+    }
+    targets.into_iter().map(Option::unwrap_or_default).collect()
+}
+
+// splits `amount` in proportion to `parts`, the rounding remainder goes to the largest part
+fn split(amount: Uint128, parts: &[(String, Uint128)], withdraw: bool) -> Vec<Allocation> {
+    let total: Uint128 = parts.iter().map(|(_, part)| *part).sum();
+    if total.is_zero() {
+        return vec![];
+    }
+    let mut allocations: Vec<Allocation> = parts
+        .iter()
+        .map(|(id, part)| Allocation {
+            strategy: id.clone(),
+            amount: amount.multiply_ratio(*part, total),
+            withdraw,
+        })
+        .collect();
+    let allocated: Uint128 = allocations.iter().map(|a| a.amount).sum();
+    let largest = parts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, part))| *part)
+        .map(|(i, _)| i)
+        .unwrap_or_default();
+    allocations[largest].amount += amount - allocated;
+    allocations
+        .into_iter()
+        .filter(|a| !a.amount.is_zero())
+        .collect()
+}
+
+/// interchain messages entering or exiting the strategies for the allocations, which all
+/// belong to the host chain of `ica`
+pub fn allocation_msgs(
+    store: &dyn Storage,
+    ica: &str,
+    allocations: &[Allocation],
+) -> StdResult<Vec<ProtobufAny>> {
+    let mut msgs = vec![];
+    for allocation in allocations {
+        let info = STRATEGIES.load(store, &allocation.strategy)?;
+        let strategy = load_strategy(store, &info)?;
+        if allocation.withdraw {
+            msgs.extend(strategy.withdraw_msgs(ica, allocation.amount)?);
+        } else {
+            msgs.extend(strategy.deposit_msgs(ica, allocation.amount)?);
+        }
+    }
+    Ok(msgs)
+}
+
+/// lets each strategy decode the responses to its messages and books the acknowledged
+/// deposits or withdrawals on the strategy values
+pub fn apply_allocations(
+    store: &mut dyn Storage,
+    allocations: &[Allocation],
+    responses: &mut dyn Iterator<Item = &MsgData>,
+) -> StdResult<()> {
+    for allocation in allocations {
+        let mut info = STRATEGIES.load(store, &allocation.strategy)?;
+        let strategy = load_strategy(store, &info)?;
+        if allocation.withdraw {
+            strategy.decode_withdraw_responses(responses)?;
+            info.value = info.value.saturating_sub(allocation.amount);
+        } else {
+            strategy.decode_deposit_responses(responses)?;
+            info.value += allocation.amount;
+        }
+        STRATEGIES.save(store, &allocation.strategy, &info)?;
+    }
+    Ok(())
+}
+
+/// keys of the positions of the strategies on `chain`, queried together
+pub fn position_keys(store: &dyn Storage, chain: &str, ica: &str) -> StdResult<Vec<KVKey>> {
+    let mut keys = vec![];
+    for (_, info) in chain_strategies(store, chain)? {
+        keys.extend(load_strategy(store, &info)?.position_keys(ica)?);
+    }
+    Ok(keys)
+}
+
+/// values each strategy on `chain` from its part of the results of `position_keys` and
+/// returns their sum, or None if the results were queried for a different set of strategies
+pub fn update_positions(
+    store: &mut dyn Storage,
+    chain: &str,
+    ica: &str,
+    kv_results: &[StorageValue],
+    now: Timestamp,
+) -> StdResult<Option<Uint128>> {
+    let mut values = vec![];
+    let mut offset = 0;
+    for (id, info) in chain_strategies(store, chain)? {
+        let strategy = load_strategy(store, &info)?;
+        let end = offset + strategy.position_keys(ica)?.len();
+        let results = match kv_results.get(offset..end) {
+            Some(results) => results,
+            None => return Ok(None),
+        };
+        values.push((id, info, strategy.position_value(results, now)?));
+        offset = end;
+    }
+    if offset != kv_results.len() {
+        return Ok(None);
+    }
+
+    let mut total = Uint128::zero();
+    for (id, mut info, value) in values {
+        info.value = value;
+        total += value;
+        STRATEGIES.save(store, &id, &info)?;
+    }
+    Ok(Some(total))
+}
+
+fn execute_contract_msg(
+    sender: &str,
+    contract: &str,
+    msg: String,
+    funds: Vec<osmosis_std::types::cosmos::base::v1beta1::Coin>,
+) -> StdResult<ProtobufAny> {
+    let execute_msg = MsgExecuteContract {
+        sender: sender.to_string(),
+        contract: contract.to_string(),
+        msg: msg.into(),
+        funds,
+    };
+    let mut buf = Vec::with_capacity(execute_msg.encoded_len());
+    execute_msg
+        .encode(&mut buf)
+        .map_err(|e| StdError::generic_err(format!("Encode error: {}", e)))?;
+
+    Ok(ProtobufAny {
+        type_url: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+        value: Binary::from(buf),
+    })
+}
+
+fn decode_execute_contract_response(
+    responses: &mut dyn Iterator<Item = &MsgData>,
+) -> StdResult<MsgExecuteContractResponse> {
+    let response = responses
+        .next()
+        .ok_or_else(|| StdError::generic_err("strategy response not found"))?;
+    if response.msg_type != "/cosmwasm.wasm.v1.MsgExecuteContract" {
+        return Err(StdError::generic_err(format!(
+            "Unexpected strategy response {}",
+            response.msg_type
+        )));
+    }
+    decode_message_response(&response.data)
+}
+
+const WASM_STORE_KEY: &str = "wasm";
+// prefix of contract state in the wasm module store
+const CONTRACT_STORE_PREFIX: u8 = 0x03;
+
+// cw-storage-plus namespaces of the Red Bank maps
+// COLLATERALS: Map<(&Addr, &str), Collateral> keyed by user and denom
+const REDBANK_COLLATERALS_NAMESPACE: &[u8] = b"collaterals";
+// MARKETS: Map<&str, Market> keyed by denom
+const REDBANK_MARKETS_NAMESPACE: &[u8] = b"markets";
+
+// Red Bank scales collateral amounts by this factor on top of the liquidity index
+const REDBANK_SCALING_FACTOR: u128 = 1_000_000;
+const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+/// the part of a Red Bank collateral position the vault needs
+#[derive(Deserialize)]
+pub struct RedBankCollateral {
+    pub amount_scaled: Uint128,
+}
+
+/// the part of a Red Bank market the vault needs
+#[derive(Deserialize)]
+pub struct RedBankMarket {
+    pub liquidity_index: Decimal,
+    pub liquidity_rate: Decimal,
+    pub indexes_last_updated: u64,
+}
+
+/// RedBank lends the target denom on Mars Red Bank.
+pub struct RedBank {
+    pub addr: String,
+    pub denom: String,
+}
+
+impl Strategy for RedBank {
+    fn deposit_msgs(&self, ica: &str, amount: Uint128) -> StdResult<Vec<ProtobufAny>> {
+        Ok(vec![execute_contract_msg(
+            ica,
+            &self.addr,
+            "{\"deposit\":{}}".to_string(),
+            vec![osmosis_std::types::cosmos::base::v1beta1::Coin {
+                denom: self.denom.clone(),
+                amount: amount.to_string(),
+            }],
+        )?])
+    }
+
+    fn withdraw_msgs(&self, ica: &str, amount: Uint128) -> StdResult<Vec<ProtobufAny>> {
+        Ok(vec![execute_contract_msg(
+            ica,
+            &self.addr,
+            format!(
+                "{{\"withdraw\":{{\"denom\":\"{}\",\"amount\":\"{}\"}}}}",
+                self.denom, amount
+            ),
+            vec![],
+        )?])
+    }
+
+    fn decode_deposit_responses(
+        &self,
+        responses: &mut dyn Iterator<Item = &MsgData>,
+    ) -> StdResult<()> {
+        decode_execute_contract_response(responses)?;
+        Ok(())
+    }
+
+    fn decode_withdraw_responses(
+        &self,
+        responses: &mut dyn Iterator<Item = &MsgData>,
+    ) -> StdResult<()> {
+        decode_execute_contract_response(responses)?;
+        Ok(())
+    }
+
+    fn position_keys(&self, ica: &str) -> StdResult<Vec<KVKey>> {
+        redbank_position_keys(&self.addr, ica, &self.denom)
+    }
+
+    fn position_value(&self, kv_results: &[StorageValue], now: Timestamp) -> StdResult<Uint128> {
+        redbank_position_value(kv_results, now)
+    }
+}
+
+fn length_prefixed(key: &[u8]) -> StdResult<Vec<u8>> {
+    let len = u16::try_from(key.len())
+        .map_err(|_| StdError::generic_err("storage key element too long"))?;
+    let mut out = len.to_be_bytes().to_vec();
+    out.extend_from_slice(key);
+    Ok(out)
+}
+
+// key of `storage_key` in the state of `contract` in the host chain's wasm store
+fn contract_store_key(contract: &str, storage_key: &[u8]) -> StdResult<Binary> {
+    let (_, data, _) = bech32::decode(contract)
+        .map_err(|e| StdError::generic_err(format!("Invalid address {}: {}", contract, e)))?;
+    let canonical = Vec::<u8>::from_base32(&data)
+        .map_err(|e| StdError::generic_err(format!("Invalid address {}: {}", contract, e)))?;
+
+    let mut key = vec![CONTRACT_STORE_PREFIX];
+    key.extend(canonical);
+    key.extend_from_slice(storage_key);
+    Ok(Binary::from(key))
+}
+
+/// keys of the Red Bank collateral of `account` in `denom` and of the `denom` market
+pub fn redbank_position_keys(redbank: &str, account: &str, denom: &str) -> StdResult<Vec<KVKey>> {
+    let mut collateral = length_prefixed(REDBANK_COLLATERALS_NAMESPACE)?;
+    collateral.extend(length_prefixed(account.as_bytes())?);
+    collateral.extend_from_slice(denom.as_bytes());
+
+    let mut market = length_prefixed(REDBANK_MARKETS_NAMESPACE)?;
+    market.extend_from_slice(denom.as_bytes());
+
+    Ok(vec![
+        KVKey {
+            path: WASM_STORE_KEY.to_string(),
+            key: contract_store_key(redbank, &collateral)?,
+        },
+        KVKey {
+            path: WASM_STORE_KEY.to_string(),
+            key: contract_store_key(redbank, &market)?,
+        },
+    ])
+}
+
+/// underlying value of the Red Bank position from the values of `redbank_position_keys`,
+/// with interest accrued up to `now` like Red Bank does
+pub fn redbank_position_value(kv_results: &[StorageValue], now: Timestamp) -> StdResult<Uint128> {
+    let (collateral, market) = match kv_results {
+        [collateral, market] => (collateral, market),
+        _ => {
+            return Err(StdError::generic_err(format!(
+                "Expected 2 Red Bank storage values, got {}",
+                kv_results.len()
+            )))
+        }
+    };
+    // no collateral stored means no position
+    if collateral.value.is_empty() {
+        return Ok(Uint128::zero());
+    }
+    let collateral: RedBankCollateral = from_slice(collateral.value.as_slice())?;
+    let market: RedBankMarket = from_slice(market.value.as_slice())?;
+
+    let elapsed = now.seconds().saturating_sub(market.indexes_last_updated);
+    let liquidity_index = market.liquidity_index
+        * (Decimal::one() + market.liquidity_rate * Decimal::from_ratio(elapsed, SECONDS_PER_YEAR));
+    Ok((collateral.amount_scaled * liquidity_index)
+        .checked_div(Uint128::new(REDBANK_SCALING_FACTOR))?)
+}
@@ -1,80 +1,3526 @@
 use std::marker::PhantomData;
 
 use crate::{
-    contract::query_errors_queue,
-    storage::{add_error_to_queue, read_errors_from_queue, ERRORS_QUEUE},
+    branch::StorageBranch,
+    contract::{execute, instantiate, migrate, query, query_errors_queue, reply, sudo},
+    error::ContractError,
+    msg::{
+        BufferResponse, ClaimableWithdrawal, ExecuteMsg, FeeSplit, HostChain, HostChainResponse,
+        IcaStatus, InstantiateMsg, MigrateMsg, NavResponse, QueryMsg, ShareBackend, StrategyKind,
+        StrategyResponse,
+    },
+    shares::{share_price, shares_to_value, value_to_shares},
+    storage::{
+        add_error_to_queue, create_deposit, read_errors_from_queue, read_reply_payload,
+        read_sudo_payload, save_ack_result, save_reply_payload, save_sudo_payload, update_deposit,
+        AcknowledgementResult, Allocation, Config, Deposit, DepositStage, EpochStage, ErrorEntry,
+        ErrorKind, FeeState, NavQueries, NavState, Roles, SudoOperation, SudoPayload,
+        WithdrawalEpoch, WithdrawalTicket, BUFFER, EPOCHS, ERRORS_QUEUE, HOME_CHAIN_ID, ICA_STATUS,
+        NAV, NAV_QUERIES, RESERVED_VALUE, STRATEGIES, SUDO_PAYLOAD_REPLY_ID_START, TOTAL_VALUE,
+    },
+    strategy::{allocate_deposit, allocate_withdrawal},
+    token::{mint, mint_shares},
+    validation::{
+        validate_ack_retention, validate_channel_id, validate_connection_id, validate_remote_addr,
+        MAX_ACK_RETENTION,
+    },
 };
 
+use cosmos_sdk_proto::cosmos::base::abci::v1beta1::{MsgData, TxMsgData};
+use cosmos_sdk_proto::ibc::applications::transfer::v1::{MsgTransfer, MsgTransferResponse};
+use cosmos_sdk_proto::traits::Message;
 use cosmwasm_std::{
     from_binary,
-    testing::{MockApi, MockQuerier, MockStorage},
-    OwnedDeps,
+    testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR},
+    to_binary, Addr, BankMsg, Binary, Coin, ContractResult, CosmosMsg, Decimal, Decimal256,
+    DepsMut, Order, OwnedDeps, QuerierWrapper, Reply, StdResult, Storage, SubMsgResponse,
+    SubMsgResult, SystemError, SystemResult, Uint128,
 };
+use cw20::{AllAccountsResponse, AllowanceResponse, BalanceResponse, TokenInfoResponse};
+use osmosis_std::types::osmosis::gamm::v1beta1::MsgSwapExactAmountInResponse;
 
-use neutron_sdk::bindings::query::NeutronQuery;
+use neutron_sdk::{
+    bindings::{
+        msg::{IbcFee, NeutronMsg},
+        query::{NeutronQuery, QueryRegisteredQueryResultResponse},
+        types::{InterchainQueryResult, ProtobufAny, StorageValue},
+    },
+    interchain_txs::helpers::get_port_id,
+    query::min_ibc_fee::MinIbcFeeResponse,
+    sudo::msg::{RequestPacket, SudoMsg},
+};
+
+type MockDeps = OwnedDeps<MockStorage, MockApi, MockQuerier<NeutronQuery>, NeutronQuery>;
 
-pub fn mock_dependencies() -> OwnedDeps<MockStorage, MockApi, MockQuerier, NeutronQuery> {
+pub fn mock_dependencies() -> MockDeps {
     OwnedDeps {
         storage: MockStorage::default(),
         api: MockApi::default(),
-        querier: MockQuerier::default(),
+        querier: mock_querier(100),
         custom_query_type: PhantomData,
     }
 }
 
+// querier answering the NAV queries with results proven at `height`
+fn mock_querier(height: u64) -> MockQuerier<NeutronQuery> {
+    MockQuerier::<NeutronQuery>::new(&[]).with_custom_handler(move |query| match query {
+        NeutronQuery::MinIbcFee {} => SystemResult::Ok(ContractResult::Ok(
+            to_binary(&MinIbcFeeResponse {
+                min_fee: IbcFee {
+                    recv_fee: vec![],
+                    ack_fee: vec![Coin::new(1000, "untrn")],
+                    timeout_fee: vec![Coin::new(1000, "untrn")],
+                },
+            })
+            .unwrap(),
+        )),
+        NeutronQuery::InterchainQueryResult { query_id } => SystemResult::Ok(ContractResult::Ok(
+            to_binary(&QueryRegisteredQueryResultResponse {
+                result: nav_query_result(*query_id, height),
+            })
+            .unwrap(),
+        )),
+        _ => SystemResult::Err(SystemError::UnsupportedRequest {
+            kind: format!("{:?}", query),
+        }),
+    })
+}
+
+// remote state proven by the NAV queries: 5000 uusdc on the interchain account (query 1),
+// 1e10 scaled Red Bank collateral at a liquidity index of 1.1 (query 2) and 40 umars of
+// claimed rewards (query 3)
+fn nav_query_result(query_id: u64, height: u64) -> InterchainQueryResult {
+    let balance = |denom: &str, amount: &str| StorageValue {
+        storage_prefix: "bank".to_string(),
+        key: Binary::default(),
+        value: Binary::from(
+            cosmos_sdk_proto::cosmos::base::v1beta1::Coin {
+                denom: denom.to_string(),
+                amount: amount.to_string(),
+            }
+            .encode_to_vec(),
+        ),
+    };
+    let kv_results = match query_id {
+        1 => vec![balance("uusdc", "5000")],
+        3 => vec![balance("umars", "40")],
+        _ => vec![
+            StorageValue {
+                storage_prefix: "wasm".to_string(),
+                key: Binary::default(),
+                value: Binary::from(
+                    br#"{"amount_scaled":"10000000000","enabled":true}"#.to_vec(),
+                ),
+            },
+            StorageValue {
+                storage_prefix: "wasm".to_string(),
+                key: Binary::default(),
+                value: Binary::from(
+                    format!(
+                        r#"{{"denom":"uusdc","liquidity_index":"1.1","liquidity_rate":"0","indexes_last_updated":{}}}"#,
+                        mock_env().block.time.seconds()
+                    )
+                    .into_bytes(),
+                ),
+            },
+        ],
+    };
+    InterchainQueryResult {
+        kv_results,
+        height,
+        revision: 1,
+    }
+}
+
+pub fn instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        connection_id: "connection-10".to_string(),
+        interchain_account_id: "babu_neutron_osmosis_v1".to_string(),
+        redbank_addr: "osmo1c3ljch9dfw5kf52nfwpxd2zmj2ese7agnx0p9tenkrryasrle5sqf3ftpg".to_string(),
+        incentives_addr: Some(
+            "osmo1krxwf5e308jmclyhfd9u92kp369l083wyvdwepen93jzzppspwtsgv0w8x".to_string(),
+        ),
+        reward_denom: Some("umars".to_string()),
+        transfer_channel: "channel-186".to_string(),
+        return_channel: "channel-3515".to_string(),
+        target_denom: "uusdc".to_string(),
+        local_denom: "ibc/B559A80D62249C8AA07A380E2A2BEA6E5CA9A6F079C912C3A9E9B494105E4F81"
+            .to_string(),
+        fee_denom: "untrn".to_string(),
+        max_slippage: Decimal::percent(5),
+        buffer_ratio: Decimal::percent(10),
+        rebalance_threshold: None,
+        nav_update_period: None,
+        nav_max_age: None,
+        performance_fee: Decimal::zero(),
+        management_fee: Decimal::zero(),
+        fee_recipient: None,
+        entry_fee_bps: 0,
+        exit_fee_bps: 0,
+        fee_splits: vec![],
+        ack_retention: None,
+        remote_bech32_prefix: "osmo".to_string(),
+        owner: None,
+        keeper: None,
+        guardian: None,
+        name: "Babushka Vault Share".to_string(),
+        symbol: "BABU".to_string(),
+        decimals: 6,
+        marketing: None,
+        share_backend: ShareBackend::Cw20,
+    }
+}
+
 #[test]
-fn test_query_errors_queue() {
+fn test_instantiate_saves_config() {
+    let mut deps = mock_dependencies();
+
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        instantiate_msg(),
+    )
+    .unwrap();
+
+    let config: Config =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        query_strategies(&deps),
+        vec![StrategyResponse {
+            id: "redbank".to_string(),
+            chain: "osmosis".to_string(),
+            kind: StrategyKind::RedBank {
+                addr: instantiate_msg().redbank_addr
+            },
+            weight: 1,
+            value: Uint128::zero(),
+            cap: None,
+        }]
+    );
+    assert_eq!(config.max_slippage, Decimal::percent(5));
+    let chains = query_host_chains(&deps);
+    assert_eq!(chains.len(), 1);
+    assert_eq!(chains[0].id, "osmosis");
+    assert_eq!(chains[0].chain.transfer_channel, "channel-186");
+    assert_eq!(chains[0].chain.return_channel, "channel-3515");
+    assert_eq!(chains[0].chain.remote_denom, "uusdc");
+    assert_eq!(chains[0].interchain_account, None);
+    assert_eq!(chains[0].ica_status, Some(IcaStatus::Registering));
+
+    let mut msg = instantiate_msg();
+    msg.max_slippage = Decimal::percent(101);
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+
+    let mut msg = instantiate_msg();
+    msg.buffer_ratio = Decimal::percent(101);
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+}
+
+#[test]
+fn test_migrate() {
+    let mut deps = mock_dependencies();
+    fn migrate_deps(deps: &mut MockDeps) -> DepsMut<'_> {
+        DepsMut {
+            storage: &mut deps.storage,
+            api: &deps.api,
+            querier: QuerierWrapper::new(&deps.querier),
+        }
+    }
+
+    // a vault without a stored config has to be instantiated again
+    migrate(migrate_deps(&mut deps), mock_env(), MigrateMsg {}).unwrap_err();
+    setup_vault(&mut deps);
+    migrate(migrate_deps(&mut deps), mock_env(), MigrateMsg {}).unwrap();
+}
+
+#[test]
+fn test_config_validation() {
+    validate_channel_id("channel-186").unwrap();
+    validate_channel_id("channel-").unwrap_err();
+    validate_channel_id("channel-abc").unwrap_err();
+    validate_channel_id("connection-10").unwrap_err();
+
+    validate_connection_id("connection-10").unwrap();
+    validate_connection_id("channel-10").unwrap_err();
+
+    let redbank = instantiate_msg().redbank_addr;
+    validate_remote_addr(&redbank, "osmo").unwrap();
+    validate_remote_addr(&redbank, "neutron").unwrap_err();
+    validate_remote_addr("osmo1notanaddress", "osmo").unwrap_err();
+
+    validate_ack_retention(1).unwrap();
+    validate_ack_retention(MAX_ACK_RETENTION).unwrap();
+    validate_ack_retention(0).unwrap_err();
+    validate_ack_retention(MAX_ACK_RETENTION + 1).unwrap_err();
+
+    // a retention of 0 would evict every result as soon as it is saved
+    let mut deps = mock_dependencies();
+    let mut msg = instantiate_msg();
+    msg.ack_retention = Some(0);
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+    setup_vault(&mut deps);
+    let update = |ack_retention| ExecuteMsg::UpdateConfig {
+        max_slippage: None,
+        fee_denom: None,
+        buffer_ratio: None,
+        rebalance_threshold: None,
+        nav_max_age: None,
+        performance_fee: None,
+        management_fee: None,
+        fee_recipient: None,
+        entry_fee_bps: None,
+        exit_fee_bps: None,
+        fee_splits: None,
+        ack_retention: Some(ack_retention),
+    };
+    for ack_retention in [0, MAX_ACK_RETENTION + 1] {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            update(ack_retention),
+        )
+        .unwrap_err();
+    }
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        update(10),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_roles() {
+    let mut deps = mock_dependencies();
+    let mut msg = instantiate_msg();
+    msg.guardian = Some("guardian".to_string());
+    instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+    let register = ExecuteMsg::Register {
+        connection_id: "connection-10".to_string(),
+        interchain_account_id: "babu_neutron_osmosis_v1".to_string(),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        register.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized));
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), register).unwrap();
+
+    let transfer = ExecuteMsg::TransferOwnership {
+        new_owner: "new_owner".to_string(),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        transfer.clone(),
+    )
+    .unwrap_err();
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), transfer).unwrap();
+
+    // ownership only moves once the new owner accepts
+    let roles: Roles =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Roles {}).unwrap()).unwrap();
+    assert_eq!(roles.owner.as_str(), "owner");
+    assert_eq!(roles.pending_owner.unwrap().as_str(), "new_owner");
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap_err();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("new_owner", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap();
+
+    let roles: Roles =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Roles {}).unwrap()).unwrap();
+    assert_eq!(roles.owner.as_str(), "new_owner");
+    assert_eq!(roles.pending_owner, None);
+
+    // the guardian can pause but not unpause
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("guardian", &[]),
+        ExecuteMsg::Pause {},
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100, "uatom")]),
+        ExecuteMsg::Fund {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Paused));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("guardian", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("new_owner", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap();
+
+    // a deposit is exactly one coin
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Fund {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::NoFunds));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100, "uatom"), Coin::new(5, "untrn")]),
+        ExecuteMsg::Fund {},
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidFunds { .. }));
+}
+
+fn query_share_balance(deps: &MockDeps, addr: &str) -> Uint128 {
+    let res: BalanceResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Balance {
+                addr: addr.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    res.balance
+}
+
+#[test]
+fn test_cw20_shares() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner", &[]),
+        instantiate_msg(),
+    )
+    .unwrap();
+    mint(
+        &mut deps.storage,
+        &Addr::unchecked("alice"),
+        Uint128::new(1_000),
+    )
+    .unwrap();
+
+    let token_info: TokenInfoResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::TokenInfo {}).unwrap()).unwrap();
+    assert_eq!(token_info.symbol, "BABU");
+    assert_eq!(token_info.total_supply, Uint128::new(1_000));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(300),
+        },
+    )
+    .unwrap();
+    assert_eq!(query_share_balance(&deps, "alice"), Uint128::new(700));
+    assert_eq!(query_share_balance(&deps, "bob"), Uint128::new(300));
+
+    // spending without allowance fails
+    let transfer_from = ExecuteMsg::TransferFrom {
+        owner: "alice".to_string(),
+        recipient: "carol".to_string(),
+        amount: Uint128::new(200),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("bob", &[]),
+        transfer_from.clone(),
+    )
+    .unwrap_err();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::IncreaseAllowance {
+            spender: "bob".to_string(),
+            amount: Uint128::new(250),
+            expires: None,
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("bob", &[]),
+        transfer_from,
+    )
+    .unwrap();
+    assert_eq!(query_share_balance(&deps, "carol"), Uint128::new(200));
+
+    let allowance: AllowanceResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Allowance {
+                owner: "alice".to_string(),
+                spender: "bob".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(allowance.allowance, Uint128::new(50));
+
+    // burning reduces the supply
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("carol", &[]),
+        ExecuteMsg::Burn {
+            amount: Uint128::new(200),
+        },
+    )
+    .unwrap();
+    let token_info: TokenInfoResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::TokenInfo {}).unwrap()).unwrap();
+    assert_eq!(token_info.total_supply, Uint128::new(800));
+
+    let accounts: AllAccountsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::AllAccounts {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(accounts.accounts, vec!["alice", "bob", "carol"]);
+}
+
+#[test]
+fn test_token_factory_shares() {
     let mut deps = mock_dependencies();
+    let mut msg = instantiate_msg();
+    msg.share_backend = ShareBackend::TokenFactory;
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        NeutronMsg::submit_create_denom("babu").into()
+    );
+
+    // shares are minted as native tokens instead of cw20 balances
+    let msgs = mint_shares(
+        &mut deps.storage,
+        &Addr::unchecked("alice"),
+        Uint128::new(100),
+    )
+    .unwrap();
+    assert_eq!(
+        msgs,
+        vec![NeutronMsg::submit_mint_tokens(
+            format!("factory/{}/babu", mock_env().contract.address),
+            Uint128::new(100),
+            "alice",
+        )]
+    );
 
-    let result = query_errors_queue(deps.as_ref()).unwrap();
-    let result: Vec<(Vec<u8>, String)> = from_binary(&result).unwrap();
+    // and can only be moved through the bank module
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+        },
+    )
+    .unwrap_err();
 
-    assert_eq!(0, result.len());
+    // a withdrawal only takes the shares it redeems
+    let shares = Coin::new(100, format!("factory/{}/babu", mock_env().contract.address));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[shares, Coin::new(5, "untrn")]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(100),
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidFunds { .. }));
+}
 
-    let error_msg = "Error message".to_string();
+#[test]
+fn test_deposit_records() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let alice = Addr::unchecked("alice");
+    let bob = Addr::unchecked("bob");
 
-    ERRORS_QUEUE
-        .save(&mut deps.storage, 0u32, &error_msg)
+    for depositor in [&alice, &bob, &alice, &alice] {
+        create_deposit(
+            deps.as_mut().storage,
+            depositor.clone(),
+            Coin::new(100, "untrn"),
+            env.block.time,
+        )
         .unwrap();
+    }
+
+    // acks advance the stage and record the observed amounts
+    let later = env.block.time.plus_seconds(60);
+    update_deposit(deps.as_mut().storage, 3, later, |deposit| {
+        deposit.transferred = Some(deposit.input.amount);
+        deposit.stage = DepositStage::Swapping;
+    })
+    .unwrap();
+
+    let deposit: Deposit =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Deposit { id: 3 }).unwrap())
+            .unwrap();
+    assert_eq!(deposit.depositor, alice);
+    assert_eq!(deposit.stage, DepositStage::Swapping);
+    assert_eq!(deposit.transferred, Some(Uint128::new(100)));
+    assert_eq!(deposit.swapped, None);
+    assert_eq!(deposit.created_at, env.block.time);
+    assert_eq!(deposit.updated_at, later);
+
+    assert!(update_deposit(deps.as_mut().storage, 5, later, |_| {}).is_err());
+    assert!(query(deps.as_ref(), env.clone(), QueryMsg::Deposit { id: 5 }).is_err());
+
+    let deposits: Vec<Deposit> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::DepositsByUser {
+                addr: "alice".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let ids: Vec<u64> = deposits.iter().map(|d| d.id).collect();
+    assert_eq!(ids, vec![1, 3, 4]);
+
+    let deposits: Vec<Deposit> = from_binary(
+        &query(
+            deps.as_ref(),
+            env,
+            QueryMsg::DepositsByUser {
+                addr: "alice".to_string(),
+                start_after: Some(1),
+                limit: Some(1),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let ids: Vec<u64> = deposits.iter().map(|d| d.id).collect();
+    assert_eq!(ids, vec![3]);
+}
+
+// delivers the open ack of an interchain account registered by the contract
+// interchain account of the vault on osmosis, interchain queries decode it
+const ICA_ADDR: &str = "osmo130k3e2qhmkr6zandzgxplzm55635ade9nykl3d";
+
+fn open_ica(deps: &mut MockDeps, interchain_account_id: &str, address: &str, connection_id: &str) {
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::OpenAck {
+            port_id: get_port_id(MOCK_CONTRACT_ADDR, interchain_account_id),
+            channel_id: "channel-0".to_string(),
+            counterparty_channel_id: "channel-1".to_string(),
+            counterparty_version: format!(
+                r#"{{"version":"ics27-1","controller_connection_id":"{}","host_connection_id":"connection-0","address":"{}","encoding":"proto3","tx_type":"sdk_multi_msg"}}"#,
+                connection_id, address
+            ),
+        },
+    )
+    .unwrap();
+}
+
+// instantiates the vault with an open interchain account
+fn setup_vault(deps: &mut MockDeps) {
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        instantiate_msg(),
+    )
+    .unwrap();
+    open_ica(deps, "babu_neutron_osmosis_v1", ICA_ADDR, "connection-10");
+}
+
+fn setup_refund(deps: &mut MockDeps, stage: DepositStage) -> u64 {
+    setup_vault(deps);
+
+    let deposit = create_deposit(
+        deps.as_mut().storage,
+        Addr::unchecked("alice"),
+        Coin::new(100, "untrn"),
+        mock_env().block.time,
+    )
+    .unwrap();
+    update_deposit(
+        deps.as_mut().storage,
+        deposit.id,
+        mock_env().block.time,
+        |deposit| {
+            if stage != DepositStage::Transferring {
+                deposit.transferred = Some(Uint128::new(100));
+            }
+            if stage == DepositStage::Deploying {
+                deposit.swapped = Some(Uint128::new(95));
+            }
+            deposit.stage = stage;
+        },
+    )
+    .unwrap();
+    deposit.id
+}
+
+// stores the payload the contract would have saved for an interchain tx and returns its packet
+fn sent_packet(deps: &mut MockDeps, seq: u64, operation: SudoOperation) -> RequestPacket {
+    save_packet(
+        deps,
+        seq,
+        SudoPayload {
+            port_id: get_port_id(MOCK_CONTRACT_ADDR, "babu_neutron_osmosis_v1"),
+            sender: MOCK_CONTRACT_ADDR.to_string(),
+            operation,
+        },
+    )
+}
+
+// the whole amount allocated to the Red Bank strategy registered at instantiate
+fn redbank_allocation(amount: u128, withdraw: bool) -> Vec<Allocation> {
+    vec![Allocation {
+        strategy: "redbank".to_string(),
+        amount: Uint128::new(amount),
+        withdraw,
+    }]
+}
+
+fn save_packet(deps: &mut MockDeps, seq: u64, payload: SudoPayload) -> RequestPacket {
+    save_sudo_payload(deps.as_mut().storage, "channel-0".to_string(), seq, payload).unwrap();
+    RequestPacket {
+        sequence: Some(seq),
+        source_port: None,
+        source_channel: Some("channel-0".to_string()),
+        destination_port: None,
+        destination_channel: None,
+        data: None,
+        timeout_height: None,
+        timeout_timestamp: None,
+    }
+}
+
+fn transfer_ack() -> Binary {
+    let data = TxMsgData {
+        data: vec![transfer_msg_data()],
+    };
+    Binary::from(data.encode_to_vec())
+}
+
+fn transfer_msg_data() -> MsgData {
+    MsgData {
+        msg_type: "/ibc.applications.transfer.v1.MsgTransfer".to_string(),
+        data: MsgTransferResponse {}.encode_to_vec(),
+    }
+}
+
+fn load_deposit(deps: &MockDeps, id: u64) -> Deposit {
+    from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Deposit { id }).unwrap()).unwrap()
+}
 
-    let result = query_errors_queue(deps.as_ref()).unwrap();
-    let result: Vec<(Vec<u8>, String)> = from_binary(&result).unwrap();
+fn is_submit_tx(msg: &CosmosMsg<NeutronMsg>) -> bool {
+    matches!(msg, CosmosMsg::Custom(NeutronMsg::SubmitTx { .. }))
+}
 
-    assert_eq!(1, result.len());
+fn submitted_msgs(msg: &CosmosMsg<NeutronMsg>) -> Vec<ProtobufAny> {
+    match msg {
+        CosmosMsg::Custom(NeutronMsg::SubmitTx { msgs, .. }) => msgs.clone(),
+        _ => panic!("not an interchain tx: {:?}", msg),
+    }
 }
 
 #[test]
-fn test_errors_queue() {
-    let mut store = MockStorage::new();
+fn test_refund_failed_transfer() {
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Transferring);
+
+    // funds never left the contract and are sent back right away
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::TransferDeposit {
+            deposit_id: id,
+            amount: Uint128::new(100),
+        },
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Error {
+            request,
+            details: "transfer failed".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![Coin::new(100, "untrn")],
+        })
+    );
+    let deposit = load_deposit(&deps, id);
+    assert_eq!(deposit.stage, DepositStage::Refunded);
+    assert_eq!(deposit.refunded, Some(Coin::new(100, "untrn")));
+
+    // a refunded deposit can not be refunded twice
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RefundDeposit { deposit_id: id },
+    )
+    .unwrap_err();
+
+    // same on timeout
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Transferring);
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::TransferDeposit {
+            deposit_id: id,
+            amount: Uint128::new(100),
+        },
+    );
+    let res = sudo(deps.as_mut(), mock_env(), SudoMsg::Timeout { request }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::Refunded);
+}
+
+#[test]
+fn test_refund_failed_swap() {
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Swapping);
+
+    // the funds are brought back from the interchain account first
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::SwapDeposit {
+            deposit_id: id,
+            min_out: Uint128::new(95),
+        },
+    );
+    let payload: Option<SudoPayload> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::SudoPayload {
+                channel_id: "channel-0".to_string(),
+                sequence_id: 1,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        payload.unwrap().operation,
+        SudoOperation::SwapDeposit {
+            deposit_id: id,
+            min_out: Uint128::new(95)
+        }
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Error {
+            request,
+            details: "slippage exceeded".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    let msgs = submitted_msgs(&res.messages[0].msg);
+    let transfer = MsgTransfer::decode(msgs[0].value.as_slice()).unwrap();
+    // the funds are held as a voucher of the home chain's end of the channel
+    let token = transfer.token.unwrap();
+    assert_eq!(
+        token.denom,
+        "ibc/A2B94488EC42B89E4D4ACCEB9E2AC23935167AA8316472F5AB987CDDCBB5C23C"
+    );
+    assert_eq!(token.amount, "100");
+    assert_eq!(transfer.source_channel, "channel-3515");
+    assert!(transfer.timeout_timestamp > mock_env().block.time.nanos());
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::RefundInFlight);
+
+    // and passed on to the depositor once they arrived
+    let request = sent_packet(
+        &mut deps,
+        2,
+        SudoOperation::RefundDeposit {
+            deposit_id: id,
+            amount: Uint128::new(100),
+        },
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: transfer_ack(),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![Coin::new(100, "untrn")],
+        })
+    );
+    let deposit = load_deposit(&deps, id);
+    assert_eq!(deposit.stage, DepositStage::Refunded);
+    assert_eq!(deposit.refunded, Some(Coin::new(100, "untrn")));
+}
+
+#[test]
+fn test_swap_below_min_out() {
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Swapping);
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::SwapDeposit {
+            deposit_id: id,
+            min_out: Uint128::new(95),
+        },
+    );
+    let data = TxMsgData {
+        data: vec![MsgData {
+            msg_type: "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn".to_string(),
+            data: MsgSwapExactAmountInResponse {
+                token_out_amount: "90".to_string(),
+            }
+            .encode_to_vec(),
+        }],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+
+    // the output is not accounted and the packet is queued for the keeper
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::Swapping);
+    assert_eq!(query_errors(&deps, None, None).len(), 1);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetryError { id: 0 },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SlippageExceeded { amount, min_out }
+            if amount == Uint128::new(90) && min_out == Uint128::new(95)
+    ));
+}
+
+#[test]
+fn test_refund_failed_deploy() {
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Deploying);
+
+    // a timeout closes the interchain account channel, nothing can be sent yet
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::DeployDeposit {
+            deposit_id: id,
+            amount: Uint128::new(95),
+            allocations: redbank_allocation(95, false),
+        },
+    );
+    let res = sudo(deps.as_mut(), mock_env(), SudoMsg::Timeout { request }).unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::Refunding);
+    assert_eq!(
+        query_host_chains(&deps)[0].ica_status,
+        Some(IcaStatus::Closed)
+    );
+    let refund = ExecuteMsg::RefundDeposit { deposit_id: id };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        refund.clone(),
+    )
+    .unwrap_err();
+
+    // the keeper reopens the account, it takes transactions again once the channel is open
+    let reopen = ExecuteMsg::ReopenIca {
+        chain: "osmosis".to_string(),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        reopen.clone(),
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        reopen.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        NeutronMsg::register_interchain_account(
+            "connection-10".to_string(),
+            "babu_neutron_osmosis_v1".to_string()
+        )
+        .into()
+    );
+    execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), reopen).unwrap_err();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        refund.clone(),
+    )
+    .unwrap_err();
+    let chains = query_host_chains(&deps);
+    assert_eq!(chains[0].interchain_account, Some(ICA_ADDR.to_string()));
+    assert_eq!(chains[0].ica_status, Some(IcaStatus::Registering));
+    open_ica(
+        &mut deps,
+        "babu_neutron_osmosis_v1",
+        ICA_ADDR,
+        "connection-10",
+    );
+
+    // only the keeper can send it back
+    execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), refund).unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RefundDeposit { deposit_id: id },
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::RefundInFlight);
+
+    // a failed refund goes back to refunding for the keeper to retry
+    let request = sent_packet(
+        &mut deps,
+        2,
+        SudoOperation::RefundDeposit {
+            deposit_id: id,
+            amount: Uint128::new(95),
+        },
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Error {
+            request,
+            details: "transfer failed".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::Refunding);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RefundDeposit { deposit_id: id },
+    )
+    .unwrap();
+
+    // swapped funds come back in the target denom
+    let request = sent_packet(
+        &mut deps,
+        3,
+        SudoOperation::RefundDeposit {
+            deposit_id: id,
+            amount: Uint128::new(95),
+        },
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: transfer_ack(),
+        },
+    )
+    .unwrap();
+    let refund = Coin::new(95, instantiate_msg().local_denom);
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![refund.clone()],
+        })
+    );
+    let deposit = load_deposit(&deps, id);
+    assert_eq!(deposit.stage, DepositStage::Refunded);
+    assert_eq!(deposit.refunded, Some(refund));
+}
+
+#[test]
+fn test_refund_deposit_twice() {
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Refunding);
+    let refund = ExecuteMsg::RefundDeposit { deposit_id: id };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        refund.clone(),
+    )
+    .unwrap();
+
+    // a second transfer is rejected while the first one is in flight
+    let err = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), refund).unwrap_err();
+    assert!(matches!(err, ContractError::OperationInFlight { .. }));
+
+    // the depositor is paid once, a second ack for the deposit is ignored
+    for seq in 1..=2 {
+        let request = sent_packet(
+            &mut deps,
+            seq,
+            SudoOperation::RefundDeposit {
+                deposit_id: id,
+                amount: Uint128::new(100),
+            },
+        );
+        let res = sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::Response {
+                request,
+                data: transfer_ack(),
+            },
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), if seq == 1 { 1 } else { 0 });
+    }
+    let deposit = load_deposit(&deps, id);
+    assert_eq!(deposit.stage, DepositStage::Refunded);
+    assert_eq!(deposit.refunded, Some(Coin::new(100, "untrn")));
+}
+
+fn epoch_packet(deps: &mut MockDeps, seq: u64, epoch_id: u64) -> RequestPacket {
+    let amount = load_epoch(deps, epoch_id).value;
+    sent_packet(
+        deps,
+        seq,
+        SudoOperation::WithdrawEpoch {
+            epoch_id,
+            chain: HOME_CHAIN_ID.to_string(),
+            amount,
+            allocations: redbank_allocation(amount.u128(), true),
+        },
+    )
+}
+
+fn load_epoch(deps: &MockDeps, id: u64) -> WithdrawalEpoch {
+    from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::WithdrawalEpoch { id }).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn test_withdrawal_queue() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    let alice = Addr::unchecked("alice");
+    let bob = Addr::unchecked("bob");
+    mint(deps.as_mut().storage, &alice, Uint128::new(600)).unwrap();
+    mint(deps.as_mut().storage, &bob, Uint128::new(400)).unwrap();
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1000))
+        .unwrap();
+
+    // withdrawing locks the shares into tickets of the current epoch
+    for (user, shares) in [("alice", 300), ("bob", 100)] {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(user, &[]),
+            ExecuteMsg::Withdraw {
+                shares: Uint128::new(shares),
+            },
+        )
+        .unwrap();
+    }
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(301),
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientShares));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(5, "untrn")]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(100),
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidFunds { .. }));
+    assert_eq!(query_share_balance(&deps, "alice"), Uint128::new(300));
+    assert_eq!(
+        query_share_balance(&deps, MOCK_CONTRACT_ADDR),
+        Uint128::new(400)
+    );
+    assert_eq!(load_epoch(&deps, 1).shares, Uint128::new(400));
+
+    let pending: Vec<WithdrawalTicket> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PendingWithdrawals {
+                addr: "alice".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].shares, Uint128::new(300));
+
+    let claim = ExecuteMsg::ClaimWithdrawal {
+        ticket_ids: vec![pending[0].id],
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        claim.clone(),
+    )
+    .unwrap_err();
+
+    // the keeper burns the shares of the epoch and withdraws their value in one tx
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::ProcessWithdrawals {},
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::ProcessWithdrawals {},
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert!(is_submit_tx(&res.messages[0].msg));
+    let msgs = submitted_msgs(&res.messages[0].msg);
+    let transfer = MsgTransfer::decode(msgs[msgs.len() - 1].value.as_slice()).unwrap();
+    assert!(transfer.timeout_timestamp > mock_env().block.time.nanos());
+    assert_eq!(
+        query_share_balance(&deps, MOCK_CONTRACT_ADDR),
+        Uint128::zero()
+    );
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(600)
+    );
+    let epoch = load_epoch(&deps, 1);
+    assert_eq!(epoch.stage, EpochStage::Withdrawing);
+    assert_eq!(epoch.value, Uint128::new(400));
+    assert_eq!(load_epoch(&deps, 2).stage, EpochStage::Open);
+
+    // a failed withdrawal is resent by the keeper
+    let request = epoch_packet(&mut deps, 1, 1);
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Error {
+            request,
+            details: "withdraw failed".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(load_epoch(&deps, 1).stage, EpochStage::Processing);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetryWithdrawals { epoch_id: 1 },
+    )
+    .unwrap();
+    assert_eq!(load_epoch(&deps, 1).stage, EpochStage::Withdrawing);
+
+    let request = epoch_packet(&mut deps, 2, 1);
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            transfer_msg_data(),
+        ],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+
+    // the acknowledgement only proves the funds left the host chain
+    assert_eq!(load_epoch(&deps, 1).stage, EpochStage::Returning);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        claim.clone(),
+    )
+    .unwrap_err();
+    let buffer = BUFFER.load(deps.as_ref().storage).unwrap();
+    let local_denom = instantiate_msg().local_denom;
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(buffer.u128() + 399, &local_denom)],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ConfirmReturns {},
+    )
+    .unwrap();
+    assert_eq!(load_epoch(&deps, 1).stage, EpochStage::Returning);
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(buffer.u128() + 400, &local_denom)],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ConfirmReturns {},
+    )
+    .unwrap();
+    let epoch = load_epoch(&deps, 1);
+    assert_eq!(epoch.stage, EpochStage::Claimable);
+    assert_eq!(epoch.landed, Uint128::new(400));
+
+    // tickets pay out their pro-rata part of the epoch once
+    let claimable: Vec<ClaimableWithdrawal> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ClaimableWithdrawals {
+                addr: "alice".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        claimable,
+        vec![ClaimableWithdrawal {
+            ticket_id: pending[0].id,
+            epoch: 1,
+            amount: Uint128::new(300),
+        }]
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("bob", &[]),
+        claim.clone(),
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        claim.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![Coin::new(300, instantiate_msg().local_denom)],
+        })
+    );
+    execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), claim).unwrap_err();
+}
+
+fn query_buffer(deps: &MockDeps) -> BufferResponse {
+    from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Buffer {}).unwrap()).unwrap()
+}
+
+#[test]
+fn test_withdrawal_buffer() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    let alice = Addr::unchecked("alice");
+    mint(deps.as_mut().storage, &alice, Uint128::new(1000)).unwrap();
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1000))
+        .unwrap();
+    assert_eq!(
+        query_buffer(&deps),
+        BufferResponse {
+            amount: Uint128::zero(),
+            in_transit: Uint128::zero(),
+            target: Uint128::new(100),
+        }
+    );
+
+    // the keeper tops up the buffer from Red Bank
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::RebalanceBuffer {},
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RebalanceBuffer {},
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::TopUpBuffer {
+            chain: HOME_CHAIN_ID.to_string(),
+            amount: Uint128::new(100),
+            allocations: redbank_allocation(100, true),
+        },
+    );
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            transfer_msg_data(),
+        ],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+
+    // the buffer is credited once the funds arrived, they are not topped up twice meanwhile
+    let buffer = query_buffer(&deps);
+    assert_eq!(buffer.amount, Uint128::zero());
+    assert_eq!(buffer.in_transit, Uint128::new(100));
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(1000)
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RebalanceBuffer {},
+    )
+    .unwrap_err();
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(100, instantiate_msg().local_denom)],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ConfirmReturns {},
+    )
+    .unwrap();
+    let buffer = query_buffer(&deps);
+    assert_eq!(buffer.amount, Uint128::new(100));
+    assert_eq!(buffer.in_transit, Uint128::zero());
+
+    // small withdrawals are paid out of the buffer right away
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(50),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![Coin::new(50, instantiate_msg().local_denom)],
+        })
+    );
+    assert_eq!(query_share_balance(&deps, "alice"), Uint128::new(950));
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(950)
+    );
+    assert_eq!(query_buffer(&deps).amount, Uint128::new(50));
+
+    // larger ones are queued
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(200),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(load_epoch(&deps, 1).shares, Uint128::new(200));
+    assert_eq!(query_buffer(&deps).amount, Uint128::new(50));
+
+    // an excess buffer is swept back to the host chain
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig {
+            max_slippage: None,
+            fee_denom: None,
+            buffer_ratio: Some(Decimal::zero()),
+            rebalance_threshold: None,
+            nav_max_age: None,
+            performance_fee: None,
+            management_fee: None,
+            fee_recipient: None,
+            entry_fee_bps: None,
+            exit_fee_bps: None,
+            fee_splits: None,
+            ack_retention: None,
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RebalanceBuffer {},
+    )
+    .unwrap();
+    assert!(matches!(
+        res.messages[0].msg,
+        CosmosMsg::Custom(NeutronMsg::IbcTransfer { .. })
+    ));
+    assert_eq!(query_buffer(&deps).amount, Uint128::zero());
+
+    // a failed transfer brings the funds back into the buffer
+    let request = sent_packet(
+        &mut deps,
+        2,
+        SudoOperation::SweepBuffer {
+            chain: HOME_CHAIN_ID.to_string(),
+            amount: Uint128::new(50),
+        },
+    );
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Error {
+            request,
+            details: "transfer failed".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(query_buffer(&deps).amount, Uint128::new(50));
+
+    // once the transfer arrived the funds are supplied to Red Bank
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RebalanceBuffer {},
+    )
+    .unwrap();
+    let request = sent_packet(
+        &mut deps,
+        3,
+        SudoOperation::SweepBuffer {
+            chain: HOME_CHAIN_ID.to_string(),
+            amount: Uint128::new(50),
+        },
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(b"{\"result\":\"AQ==\"}".to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    assert_eq!(query_buffer(&deps).amount, Uint128::zero());
+}
+
+fn query_host_chains(deps: &MockDeps) -> Vec<HostChainResponse> {
+    from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::HostChains {}).unwrap()).unwrap()
+}
+
+fn query_nav(deps: &MockDeps, env: cosmwasm_std::Env) -> NavResponse {
+    from_binary(&query(deps.as_ref(), env, QueryMsg::Nav {}).unwrap()).unwrap()
+}
+
+#[test]
+fn test_nav_oracle() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    mint(
+        deps.as_mut().storage,
+        &Addr::unchecked("bob"),
+        Uint128::new(10_000),
+    )
+    .unwrap();
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(10_000))
+        .unwrap();
+
+    // only the keeper registers the queries, their ids arrive in the replies
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::RegisterNavQueries {
+            chain: "osmosis".to_string(),
+        },
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RegisterNavQueries {
+            chain: "osmosis".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+    for (sub_msg, query_id) in res.messages.iter().zip([1u64, 2, 3]) {
+        reply(
+            deps.as_mut().into_empty(),
+            mock_env(),
+            Reply {
+                id: sub_msg.id,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: Some(Binary::from(
+                        format!("{{\"id\":{}}}", query_id).into_bytes(),
+                    )),
+                }),
+            },
+        )
+        .unwrap();
+    }
+    let nav = query_nav(&deps, mock_env());
+    assert_eq!(nav.chains[0].balance_query_id, Some(1));
+    assert_eq!(nav.chains[0].position_query_id, Some(2));
+    assert_eq!(nav.chains[0].reward_query_id, Some(3));
+    assert!(!nav.fresh);
+
+    // a deployed deposit waits for a fresh NAV before its shares are priced
+    let id = setup_deployed_deposit(&mut deps);
+    let request = save_packet(
+        &mut deps,
+        1,
+        SudoPayload {
+            port_id: get_port_id(MOCK_CONTRACT_ADDR, "babu_neutron_osmosis_v1"),
+            sender: MOCK_CONTRACT_ADDR.to_string(),
+            operation: SudoOperation::DeployDeposit {
+                deposit_id: id,
+                amount: Uint128::new(1000),
+                allocations: redbank_allocation(1000, false),
+            },
+        },
+    );
+    let data = TxMsgData {
+        data: vec![MsgData {
+            msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+            data: vec![],
+        }],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::AwaitingPrice);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::CompleteDeposit { deposit_id: id },
+    )
+    .unwrap_err();
+
+    // the total value is recomputed once both queries delivered, without the reserved deposit
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 1 },
+    )
+    .unwrap();
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(10_000)
+    );
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 2 },
+    )
+    .unwrap();
+    let nav = query_nav(&deps, mock_env());
+    assert_eq!(nav.chains[0].ica_balance, Some(Uint128::new(5000)));
+    assert_eq!(nav.chains[0].position, Some(Uint128::new(11_000)));
+    assert_eq!(nav.total_value, Uint128::new(15_000));
+    assert!(nav.fresh);
+    // claimed rewards are only counted once harvested
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 3 },
+    )
+    .unwrap();
+    let nav = query_nav(&deps, mock_env());
+    assert_eq!(nav.chains[0].reward_balance, Some(Uint128::new(40)));
+    assert_eq!(nav.total_value, Uint128::new(15_000));
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 7 },
+    )
+    .unwrap_err();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::CompleteDeposit { deposit_id: id },
+    )
+    .unwrap();
+    let deposit = load_deposit(&deps, id);
+    assert_eq!(deposit.stage, DepositStage::Completed);
+    assert_eq!(
+        deposit.shares,
+        Some(
+            value_to_shares(
+                Uint128::new(1000),
+                Uint128::new(10_000),
+                Uint128::new(15_000)
+            )
+            .unwrap()
+        )
+    );
+    assert_eq!(
+        RESERVED_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        query_nav(&deps, mock_env()).total_value,
+        Uint128::new(16_000)
+    );
+
+    // without new results the NAV goes stale
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(60 * 60 + 1);
+    assert!(!query_nav(&deps, env).fresh);
+}
+
+#[test]
+fn test_nav_release_after_withdrawal() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    NAV_QUERIES
+        .save(
+            deps.as_mut().storage,
+            HOME_CHAIN_ID,
+            &NavQueries {
+                balance_query_id: Some(1),
+                position_query_id: Some(2),
+                reward_query_id: None,
+            },
+        )
+        .unwrap();
+    for query_id in [1, 2] {
+        sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::KVQueryResult { query_id },
+        )
+        .unwrap();
+    }
+    assert_eq!(
+        query_nav(&deps, mock_env()).total_value,
+        Uint128::new(16_000)
+    );
+
+    // the withdrawn value still shows in the remote state proven before the acknowledgement
+    let mut epoch = load_epoch(&deps, 1);
+    epoch.stage = EpochStage::Withdrawing;
+    epoch.value = Uint128::new(400);
+    EPOCHS.save(deps.as_mut().storage, 1, &epoch).unwrap();
+    RESERVED_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(400))
+        .unwrap();
+    let request = epoch_packet(&mut deps, 1, 1);
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            transfer_msg_data(),
+        ],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert_eq!(load_epoch(&deps, 1).stage, EpochStage::Returning);
+    assert_eq!(
+        RESERVED_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(400)
+    );
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 1 },
+    )
+    .unwrap();
+    assert_eq!(
+        query_nav(&deps, mock_env()).total_value,
+        Uint128::new(15_600)
+    );
 
-    let errors = read_errors_from_queue(&store);
-    let errors = errors.unwrap();
+    // the value is released once balance and positions were proven at a later height
+    deps.querier = mock_querier(101);
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 1 },
+    )
+    .unwrap();
+    assert_eq!(
+        RESERVED_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(400)
+    );
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::KVQueryResult { query_id: 2 },
+    )
+    .unwrap();
+    assert_eq!(
+        RESERVED_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        query_nav(&deps, mock_env()).total_value,
+        Uint128::new(16_000)
+    );
+}
+
+// a deposit whose swapped funds were supplied to Red Bank, waiting for the deploy ack
+fn setup_deployed_deposit(deps: &mut MockDeps) -> u64 {
+    let deposit = create_deposit(
+        deps.as_mut().storage,
+        Addr::unchecked("alice"),
+        Coin::new(1000, "untrn"),
+        mock_env().block.time,
+    )
+    .unwrap();
+    update_deposit(
+        deps.as_mut().storage,
+        deposit.id,
+        mock_env().block.time,
+        |deposit| {
+            deposit.transferred = Some(Uint128::new(1000));
+            deposit.swapped = Some(Uint128::new(1000));
+            deposit.stage = DepositStage::Deploying;
+        },
+    )
+    .unwrap();
+    RESERVED_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1000))
+        .unwrap();
+    deposit.id
+}
+
+#[test]
+fn test_harvest() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1000))
+        .unwrap();
+
+    let harvest = ExecuteMsg::Harvest {
+        min_out: Uint128::new(20),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        harvest.clone(),
+    )
+    .unwrap_err();
+    // without proven rewards only the claim is sent
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        harvest.clone(),
+    )
+    .unwrap();
+    let msgs = submitted_msgs(&res.messages[0].msg);
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].type_url, "/cosmwasm.wasm.v1.MsgExecuteContract");
+
+    // the reward balance proven by the reward query is swapped once
+    NAV.save(
+        deps.as_mut().storage,
+        HOME_CHAIN_ID,
+        &NavState {
+            reward_balance: Some(Uint128::new(50)),
+            reward_height: 100,
+            ..NavState::default()
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        harvest.clone(),
+    )
+    .unwrap();
+    let msgs = submitted_msgs(&res.messages[0].msg);
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(
+        msgs[1].type_url,
+        "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn"
+    );
+    assert!(res
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "reward_amount" && attr.value == "50"));
+    let nav = NAV.load(deps.as_ref().storage, HOME_CHAIN_ID).unwrap();
+    assert_eq!(nav.reward_balance, None);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        harvest,
+    )
+    .unwrap();
+    assert_eq!(submitted_msgs(&res.messages[0].msg).len(), 1);
+
+    // the swap output is supplied to Red Bank and counted once that is acknowledged
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::Harvest {
+            reward_amount: Uint128::new(50),
+            min_out: Uint128::new(20),
+        },
+    );
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            MsgData {
+                msg_type: "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn".to_string(),
+                data: MsgSwapExactAmountInResponse {
+                    token_out_amount: "30".to_string(),
+                }
+                .encode_to_vec(),
+            },
+        ],
+    };
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    assert_eq!(
+        RESERVED_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(30)
+    );
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(1000)
+    );
+
+    let request = sent_packet(
+        &mut deps,
+        2,
+        SudoOperation::DeployHarvest {
+            amount: Uint128::new(30),
+            allocations: redbank_allocation(30, false),
+        },
+    );
+    let data = TxMsgData {
+        data: vec![MsgData {
+            msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+            data: vec![],
+        }],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        RESERVED_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(1030)
+    );
+
+    // the ack of a claim without a swap leaves the value alone
+    let request = sent_packet(
+        &mut deps,
+        3,
+        SudoOperation::Harvest {
+            reward_amount: Uint128::zero(),
+            min_out: Uint128::new(20),
+        },
+    );
+    let data = TxMsgData {
+        data: vec![MsgData {
+            msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+            data: vec![],
+        }],
+    };
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    assert_eq!(
+        TOTAL_VALUE.load(deps.as_ref().storage).unwrap(),
+        Uint128::new(1030)
+    );
+
+    // harvesting needs the incentives contract and reward denom
+    let mut deps = mock_dependencies();
+    let mut msg = instantiate_msg();
+    msg.incentives_addr = None;
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Harvest {
+            min_out: Uint128::new(20),
+        },
+    )
+    .unwrap_err();
+}
+
+#[test]
+fn test_fees() {
+    let mut deps = mock_dependencies();
+    let mut msg = instantiate_msg();
+    msg.performance_fee = Decimal::percent(31);
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+
+    let mut msg = instantiate_msg();
+    msg.performance_fee = Decimal::percent(20);
+    msg.management_fee = Decimal::percent(2);
+    msg.fee_recipient = Some("treasury".to_string());
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+    mint(
+        deps.as_mut().storage,
+        &Addr::unchecked("alice"),
+        Uint128::new(1_000_000),
+    )
+    .unwrap();
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1_000_000))
+        .unwrap();
+
+    // a year with 10% yield: 2% of the value as management fee and 20% of the
+    // increase of the share price as performance fee
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(31_536_000);
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1_100_000))
+        .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::AccrueFees {},
+    )
+    .unwrap();
+
+    let fee_shares = value_to_shares(
+        Uint128::new(32_000),
+        Uint128::new(1_000_000),
+        Uint128::new(1_068_000),
+    )
+    .unwrap();
+    assert_eq!(query_share_balance(&deps, "treasury"), fee_shares);
+    let state: FeeState =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::FeeState {}).unwrap()).unwrap();
+    assert_eq!(state.last_accrual, env.block.time);
+    assert_eq!(state.management_fees + state.performance_fees, fee_shares);
+    assert!(state.performance_fees < state.management_fees);
+    assert_eq!(
+        state.high_water_mark,
+        share_price(
+            Uint128::new(1_000_000) + fee_shares,
+            Uint128::new(1_100_000)
+        )
+    );
+
+    // accruing again right away charges nothing
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::AccrueFees {},
+    )
+    .unwrap();
+    assert_eq!(query_share_balance(&deps, "treasury"), fee_shares);
 
-    assert_eq!(0, errors.len());
+    // fees have hard maximums
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig {
+            max_slippage: None,
+            fee_denom: None,
+            buffer_ratio: None,
+            rebalance_threshold: None,
+            nav_max_age: None,
+            performance_fee: None,
+            management_fee: Some(Decimal::percent(6)),
+            fee_recipient: None,
+            entry_fee_bps: None,
+            exit_fee_bps: None,
+            fee_splits: None,
+            ack_retention: None,
+        },
+    )
+    .unwrap_err();
+}
+
+#[test]
+fn test_transaction_fees() {
+    let mut deps = mock_dependencies();
+    let mut msg = instantiate_msg();
+    msg.entry_fee_bps = 100;
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+
+    let mut msg = instantiate_msg();
+    msg.entry_fee_bps = 501;
+    msg.fee_splits = vec![FeeSplit {
+        recipient: "treasury".to_string(),
+        weight: 1,
+    }];
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+
+    let mut msg = instantiate_msg();
+    msg.entry_fee_bps = 100;
+    msg.exit_fee_bps = 200;
+    msg.fee_splits = vec![
+        FeeSplit {
+            recipient: "treasury".to_string(),
+            weight: 3,
+        },
+        FeeSplit {
+            recipient: "insurance".to_string(),
+            weight: 1,
+        },
+    ];
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+    // the entry fee is minted as shares to the recipients, the remainder goes to the first
+    let id = setup_deployed_deposit(&mut deps);
+    update_deposit(
+        deps.as_mut().storage,
+        id,
+        mock_env().block.time,
+        |deposit| {
+            deposit.deployed = Some(Uint128::new(1000));
+            deposit.stage = DepositStage::AwaitingPrice;
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::CompleteDeposit { deposit_id: id },
+    )
+    .unwrap();
+    assert_eq!(load_deposit(&deps, id).shares, Some(Uint128::new(990)));
+    assert_eq!(load_deposit(&deps, id).fee, Some(Uint128::new(10)));
+    assert_eq!(query_share_balance(&deps, "alice"), Uint128::new(990));
+    assert_eq!(query_share_balance(&deps, "treasury"), Uint128::new(8));
+    assert_eq!(query_share_balance(&deps, "insurance"), Uint128::new(2));
+    assert_eq!(
+        res.events
+            .iter()
+            .filter(|event| event.ty == "vault_fee")
+            .count(),
+        2
+    );
 
-    let error = "some error message".to_string();
+    // the exit fee is paid out of the redeemed value in the local denom
+    BUFFER
+        .save(deps.as_mut().storage, &Uint128::new(500))
+        .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(400),
+        },
+    )
+    .unwrap();
+    let local_denom = instantiate_msg().local_denom;
+    let sends: Vec<_> = res.messages.iter().map(|msg| msg.msg.clone()).collect();
+    assert_eq!(
+        sends,
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: vec![Coin::new(392, &local_denom)],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: vec![Coin::new(6, &local_denom)],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "insurance".to_string(),
+                amount: vec![Coin::new(2, &local_denom)],
+            }),
+        ]
+    );
+    let fee_event = res
+        .events
+        .iter()
+        .find(|event| event.ty == "vault_fee")
+        .unwrap();
+    assert!(fee_event
+        .attributes
+        .iter()
+        .any(|attr| attr.key == "kind" && attr.value == "exit"));
+}
 
-    add_error_to_queue(&mut store, error.clone()).unwrap();
+fn query_strategies(deps: &MockDeps) -> Vec<StrategyResponse> {
+    from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Strategies {}).unwrap()).unwrap()
+}
 
-    let errors = read_errors_from_queue(&store);
-    let errors = errors.unwrap();
+fn set_strategy(deps: &mut MockDeps, sender: &str, id: &str, addr: &str, weight: u32) -> bool {
+    set_capped_strategy(deps, sender, id, addr, weight, None)
+}
 
-    assert_eq!(1, errors.len());
-    assert_eq!(errors, vec![(0u32.to_be_bytes().to_vec(), error.clone())]);
+fn set_capped_strategy(
+    deps: &mut MockDeps,
+    sender: &str,
+    id: &str,
+    addr: &str,
+    weight: u32,
+    cap: Option<Uint128>,
+) -> bool {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(sender, &[]),
+        ExecuteMsg::SetStrategy {
+            id: id.to_string(),
+            chain: "osmosis".to_string(),
+            kind: StrategyKind::RedBank {
+                addr: addr.to_string(),
+            },
+            weight,
+            cap,
+        },
+    )
+    .is_ok()
+}
 
-    add_error_to_queue(&mut store, error.clone()).unwrap();
-    add_error_to_queue(&mut store, error.clone()).unwrap();
+#[test]
+fn test_strategies() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    let redbank = instantiate_msg().redbank_addr;
+    let other = instantiate_msg().incentives_addr.unwrap();
 
-    let errors = read_errors_from_queue(&store);
-    let errors = errors.unwrap();
+    // only the owner registers strategies
+    assert!(!set_strategy(&mut deps, "alice", "redbank_2", &other, 3));
+    assert!(!set_strategy(
+        &mut deps,
+        "creator",
+        "redbank_2",
+        "neutron1notanaddress",
+        3
+    ));
+    assert!(set_strategy(&mut deps, "creator", "redbank_2", &other, 3));
+    assert_eq!(query_strategies(&deps).len(), 2);
 
-    assert_eq!(3, errors.len());
+    // deposits are split by weight
+    let allocations =
+        allocate_deposit(deps.as_ref().storage, "osmosis", Uint128::new(1001)).unwrap();
     assert_eq!(
-        errors,
+        allocations,
         vec![
-            (0u32.to_be_bytes().to_vec(), error.clone()),
-            (1u32.to_be_bytes().to_vec(), error.clone()),
-            (2u32.to_be_bytes().to_vec(), error)
+            Allocation {
+                strategy: "redbank".to_string(),
+                amount: Uint128::new(251),
+                withdraw: false,
+            },
+            Allocation {
+                strategy: "redbank_2".to_string(),
+                amount: Uint128::new(750),
+                withdraw: false,
+            },
+        ]
+    );
+
+    // the deploy tx of a deposit carries its allocations
+    let id = setup_deployed_deposit(&mut deps);
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::SwapDeposit {
+            deposit_id: id,
+            min_out: Uint128::new(95),
+        },
+    );
+    let data = TxMsgData {
+        data: vec![MsgData {
+            msg_type: "/osmosis.cosmwasmpool.v1beta1.SwapExactAmountIn".to_string(),
+            data: MsgSwapExactAmountInResponse {
+                token_out_amount: "1000".to_string(),
+            }
+            .encode_to_vec(),
+        }],
+    };
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    let payload = read_reply_payload(deps.as_ref().storage, res.messages[0].id).unwrap();
+    assert_eq!(payload.operation.allocations().len(), 2);
+
+    // each strategy is credited once its deposit is acknowledged
+    let request = save_packet(&mut deps, 2, payload);
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+        ],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    let values: Vec<Uint128> = query_strategies(&deps)
+        .into_iter()
+        .map(|strategy| strategy.value)
+        .collect();
+    assert_eq!(values, vec![Uint128::new(250), Uint128::new(750)]);
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::Completed);
+
+    // withdrawals are split by the value held
+    let allocations =
+        allocate_withdrawal(deps.as_ref().storage, "osmosis", Uint128::new(100)).unwrap();
+    assert_eq!(allocations[0].amount, Uint128::new(25));
+    assert_eq!(allocations[1].amount, Uint128::new(75));
+
+    // a strategy holding value keeps its address
+    assert!(!set_strategy(&mut deps, "creator", "redbank", &other, 1));
+
+    // an empty one moves to a new address and the position query follows it
+    NAV_QUERIES
+        .save(
+            deps.as_mut().storage,
+            HOME_CHAIN_ID,
+            &NavQueries {
+                balance_query_id: Some(1),
+                position_query_id: Some(2),
+                reward_query_id: None,
+            },
+        )
+        .unwrap();
+    assert!(set_strategy(&mut deps, "creator", "redbank_3", &other, 1));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::SetStrategy {
+            id: "redbank_3".to_string(),
+            chain: "osmosis".to_string(),
+            kind: StrategyKind::RedBank {
+                addr: redbank.clone(),
+            },
+            weight: 1,
+            cap: None,
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        res.messages[0].msg,
+        CosmosMsg::Custom(NeutronMsg::UpdateInterchainQuery { query_id: 2, .. })
+    ));
+    assert_eq!(
+        query_strategies(&deps)[2].kind,
+        StrategyKind::RedBank {
+            addr: redbank.clone()
+        }
+    );
+
+    // a strategy is retired with a zero weight, but one has to stay active
+    assert!(set_strategy(&mut deps, "creator", "redbank_3", &redbank, 0));
+    assert!(set_strategy(&mut deps, "creator", "redbank", &redbank, 0));
+    assert!(!set_strategy(&mut deps, "creator", "redbank_2", &other, 0));
+}
+
+fn strategy_values(deps: &MockDeps) -> Vec<Uint128> {
+    query_strategies(deps)
+        .into_iter()
+        .map(|strategy| strategy.value)
+        .collect()
+}
+
+#[test]
+fn test_rebalance() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    let other = instantiate_msg().incentives_addr.unwrap();
+    assert!(set_capped_strategy(
+        &mut deps,
+        "creator",
+        "redbank_2",
+        &other,
+        1,
+        Some(Uint128::new(300))
+    ));
+    STRATEGIES
+        .update(deps.as_mut().storage, "redbank", |info| -> StdResult<_> {
+            let mut info = info.unwrap();
+            info.value = Uint128::new(1000);
+            Ok(info)
+        })
+        .unwrap();
+
+    // deposits fill a capped strategy up to its cap, the rest goes to the others
+    let allocations =
+        allocate_deposit(deps.as_ref().storage, "osmosis", Uint128::new(800)).unwrap();
+    assert_eq!(allocations[0].amount, Uint128::new(500));
+    assert_eq!(allocations[1].amount, Uint128::new(300));
+
+    // only the keeper rebalances
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+
+    // the even split is capped, the rest stays in the first strategy
+    let payload = read_reply_payload(deps.as_ref().storage, res.messages[0].id).unwrap();
+    assert_eq!(
+        payload.operation.allocations(),
+        [
+            Allocation {
+                strategy: "redbank".to_string(),
+                amount: Uint128::new(300),
+                withdraw: true,
+            },
+            Allocation {
+                strategy: "redbank_2".to_string(),
+                amount: Uint128::new(300),
+                withdraw: false,
+            },
         ]
     );
+
+    // one rebalance at a time, withdrawals meanwhile see the rebalanced values
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+    let allocations =
+        allocate_withdrawal(deps.as_ref().storage, "osmosis", Uint128::new(100)).unwrap();
+    assert_eq!(allocations[0].amount, Uint128::new(70));
+    assert_eq!(allocations[1].amount, Uint128::new(30));
+    let allocations =
+        allocate_deposit(deps.as_ref().storage, "osmosis", Uint128::new(100)).unwrap();
+    assert_eq!(
+        allocations,
+        vec![Allocation {
+            strategy: "redbank".to_string(),
+            amount: Uint128::new(100),
+            withdraw: false,
+        }]
+    );
+    assert_eq!(
+        strategy_values(&deps),
+        vec![Uint128::new(1000), Uint128::zero()]
+    );
+
+    // the acknowledgement books the moved funds
+    let request = save_packet(&mut deps, 1, payload);
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+        ],
+    };
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        strategy_values(&deps),
+        vec![Uint128::new(700), Uint128::new(300)]
+    );
+
+    // nothing left to move
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+
+    // small deviations stay below the threshold
+    STRATEGIES
+        .update(deps.as_mut().storage, "redbank", |info| -> StdResult<_> {
+            let mut info = info.unwrap();
+            info.value = Uint128::new(705);
+            Ok(info)
+        })
+        .unwrap();
+    assert!(set_capped_strategy(
+        &mut deps,
+        "creator",
+        "redbank_2",
+        &other,
+        1,
+        Some(Uint128::new(305))
+    ));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap_err();
+}
+
+// a second host chain next to the home chain, with its own interchain account
+fn umee_chain() -> HostChain {
+    HostChain {
+        connection_id: "connection-20".to_string(),
+        interchain_account_id: "babu_neutron_umee_v1".to_string(),
+        transfer_channel: "channel-20".to_string(),
+        return_channel: "channel-21".to_string(),
+        remote_denom: "uusdc".to_string(),
+        remote_bech32_prefix: "umee".to_string(),
+    }
+}
+
+fn set_chain_strategy(deps: &mut MockDeps, id: &str, chain: &str, addr: &str, weight: u32) -> bool {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::SetStrategy {
+            id: id.to_string(),
+            chain: chain.to_string(),
+            kind: StrategyKind::RedBank {
+                addr: addr.to_string(),
+            },
+            weight,
+            cap: None,
+        },
+    )
+    .is_ok()
+}
+
+#[test]
+fn test_host_chains() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    let umee_addr = "umee1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusqdc6uc6";
+
+    // only the owner adds host chains, with valid channels
+    let set_chain = |chain: HostChain| ExecuteMsg::SetHostChain {
+        id: "umee".to_string(),
+        chain,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        set_chain(umee_chain()),
+    )
+    .unwrap_err();
+    let mut invalid = umee_chain();
+    invalid.return_channel = "channel-".to_string();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        set_chain(invalid),
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        set_chain(umee_chain()),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        NeutronMsg::register_interchain_account(
+            "connection-20".to_string(),
+            "babu_neutron_umee_v1".to_string()
+        )
+        .into()
+    );
+    open_ica(
+        &mut deps,
+        "babu_neutron_umee_v1",
+        "umee1ica",
+        "connection-20",
+    );
+    let chains = query_host_chains(&deps);
+    assert_eq!(chains.len(), 2);
+    assert_eq!(chains[1].id, "umee");
+    assert_eq!(chains[1].interchain_account, Some("umee1ica".to_string()));
+
+    // the interchain account of a chain is fixed once added, and so is its connection
+    let mut moved = umee_chain();
+    moved.interchain_account_id = "babu_neutron_umee_v2".to_string();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        set_chain(moved),
+    )
+    .unwrap_err();
+    let mut moved = umee_chain();
+    moved.connection_id = "connection-21".to_string();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        set_chain(moved),
+    )
+    .unwrap_err();
+    let mut rechanneled = umee_chain();
+    rechanneled.return_channel = "channel-7".to_string();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        set_chain(rechanneled),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        set_chain(umee_chain()),
+    )
+    .unwrap();
+
+    // strategies live on a known chain and use its address prefix
+    assert!(!set_chain_strategy(
+        &mut deps,
+        "umee_lend",
+        "cosmoshub",
+        umee_addr,
+        1
+    ));
+    let redbank = instantiate_msg().redbank_addr;
+    assert!(!set_chain_strategy(
+        &mut deps,
+        "umee_lend",
+        "umee",
+        &redbank,
+        1
+    ));
+    assert!(set_chain_strategy(
+        &mut deps,
+        "umee_lend",
+        "umee",
+        umee_addr,
+        1
+    ));
+    assert!(!set_chain_strategy(
+        &mut deps,
+        "umee_lend",
+        "osmosis",
+        &redbank,
+        1
+    ));
+    assert_eq!(query_strategies(&deps)[1].chain, "umee");
+
+    let alice = Addr::unchecked("alice");
+    mint(deps.as_mut().storage, &alice, Uint128::new(1000)).unwrap();
+    TOTAL_VALUE
+        .save(deps.as_mut().storage, &Uint128::new(1000))
+        .unwrap();
+    for (id, value) in [("redbank", 600), ("umee_lend", 400)] {
+        STRATEGIES
+            .update(deps.as_mut().storage, id, |info| -> StdResult<_> {
+                let mut info = info.unwrap();
+                info.value = Uint128::new(value);
+                Ok(info)
+            })
+            .unwrap();
+    }
+
+    // an epoch is withdrawn from the richest chain first and the rest from the next one
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        ExecuteMsg::Withdraw {
+            shares: Uint128::new(800),
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::ProcessWithdrawals {},
+    )
+    .unwrap();
+    let payload = read_reply_payload(deps.as_ref().storage, res.messages[0].id).unwrap();
+    assert_eq!(
+        payload.operation,
+        SudoOperation::WithdrawEpoch {
+            epoch_id: 1,
+            chain: HOME_CHAIN_ID.to_string(),
+            amount: Uint128::new(600),
+            allocations: redbank_allocation(600, true),
+        }
+    );
+
+    let data = TxMsgData {
+        data: vec![
+            MsgData {
+                msg_type: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                data: vec![],
+            },
+            transfer_msg_data(),
+        ],
+    };
+    let request = save_packet(&mut deps, 1, payload);
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        &res.messages[0].msg,
+        CosmosMsg::Custom(NeutronMsg::SubmitTx { connection_id, .. }) if connection_id == "connection-20"
+    ));
+    let epoch = load_epoch(&deps, 1);
+    assert_eq!(epoch.stage, EpochStage::Withdrawing);
+    assert_eq!(epoch.returned, Uint128::new(600));
+
+    let payload = read_reply_payload(deps.as_ref().storage, res.messages[0].id).unwrap();
+    assert!(matches!(
+        &payload.operation,
+        SudoOperation::WithdrawEpoch { chain, amount, allocations, .. }
+            if chain == "umee" && *amount == Uint128::new(200) && allocations[0].strategy == "umee_lend"
+    ));
+    let request = save_packet(&mut deps, 2, payload);
+    sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(data.encode_to_vec()),
+        },
+    )
+    .unwrap();
+    let epoch = load_epoch(&deps, 1);
+    assert_eq!(epoch.stage, EpochStage::Returning);
+    assert_eq!(epoch.returned, Uint128::new(800));
+    assert_eq!(
+        strategy_values(&deps),
+        vec![Uint128::zero(), Uint128::new(200)]
+    );
+
+    // the buffer is swept to the chain furthest below its weight
+    assert!(set_chain_strategy(
+        &mut deps,
+        "umee_lend",
+        "umee",
+        umee_addr,
+        3
+    ));
+    BUFFER
+        .save(deps.as_mut().storage, &Uint128::new(500))
+        .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RebalanceBuffer {},
+    )
+    .unwrap();
+    assert!(matches!(
+        &res.messages[0].msg,
+        CosmosMsg::Custom(NeutronMsg::IbcTransfer { source_channel, receiver, .. })
+            if source_channel == "channel-20" && receiver == "umee1ica"
+    ));
+    assert!(matches!(
+        read_reply_payload(deps.as_ref().storage, res.messages[0].id).unwrap().operation,
+        SudoOperation::SweepBuffer { chain, .. } if chain == "umee"
+    ));
+}
+
+#[test]
+fn test_reply_payloads() {
+    let mut deps = mock_dependencies();
+    let payload = |deposit_id| SudoPayload {
+        port_id: get_port_id(MOCK_CONTRACT_ADDR, "babu_neutron_osmosis_v1"),
+        sender: MOCK_CONTRACT_ADDR.to_string(),
+        operation: SudoOperation::TransferDeposit {
+            deposit_id,
+            amount: Uint128::new(100),
+        },
+    };
+
+    // two txs submitted in one message keep their own payloads
+    let first = save_reply_payload(deps.as_mut().storage, payload(1)).unwrap();
+    let second = save_reply_payload(deps.as_mut().storage, payload(2)).unwrap();
+    assert_ne!(first, second);
+    assert!(first >= SUDO_PAYLOAD_REPLY_ID_START);
+
+    for (id, seq) in [(second, 7u64), (first, 8)] {
+        reply(
+            deps.as_mut().into_empty(),
+            mock_env(),
+            Reply {
+                id,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: Some(Binary::from(
+                        format!("{{\"sequence_id\":{},\"channel\":\"channel-0\"}}", seq)
+                            .into_bytes(),
+                    )),
+                }),
+            },
+        )
+        .unwrap();
+    }
+    let store = deps.as_ref().storage;
+    assert_eq!(
+        read_sudo_payload(store, "channel-0".to_string(), 7).unwrap(),
+        payload(2)
+    );
+    assert_eq!(
+        read_sudo_payload(store, "channel-0".to_string(), 8).unwrap(),
+        payload(1)
+    );
+    // the payloads are only kept until the reply
+    read_reply_payload(store, first).unwrap_err();
+    read_reply_payload(store, second).unwrap_err();
+}
+
+fn query_ack_result(deps: &MockDeps, seq: u64) -> Option<AcknowledgementResult> {
+    from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::AcknowledgementResult {
+                interchain_account_id: "babu_neutron_osmosis_v1".to_string(),
+                sequence_id: seq,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_prune_state() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig {
+            max_slippage: None,
+            fee_denom: None,
+            buffer_ratio: None,
+            rebalance_threshold: None,
+            nav_max_age: None,
+            performance_fee: None,
+            management_fee: None,
+            fee_recipient: None,
+            entry_fee_bps: None,
+            exit_fee_bps: None,
+            fee_splits: None,
+            ack_retention: Some(2),
+        },
+    )
+    .unwrap();
+
+    // handled payloads are removed and only the latest results are kept
+    for seq in 1..=3 {
+        let request = sent_packet(
+            &mut deps,
+            seq,
+            SudoOperation::Rebalance {
+                chain: HOME_CHAIN_ID.to_string(),
+                allocations: vec![],
+            },
+        );
+        sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::Error {
+                request,
+                details: "failed".to_string(),
+            },
+        )
+        .unwrap();
+    }
+    assert!(read_sudo_payload(deps.as_ref().storage, "channel-0".to_string(), 3).is_err());
+    assert_eq!(query_ack_result(&deps, 1), None);
+    assert_eq!(
+        query_ack_result(&deps, 3),
+        Some(AcknowledgementResult::Error((
+            "rebalance".to_string(),
+            "failed".to_string()
+        )))
+    );
+
+    // payloads of packets with a result are pruned in batches, pending payloads are kept
+    let port_id = get_port_id(MOCK_CONTRACT_ADDR, "babu_neutron_osmosis_v1");
+    for seq in [4, 6] {
+        sent_packet(
+            &mut deps,
+            seq,
+            SudoOperation::Rebalance {
+                chain: HOME_CHAIN_ID.to_string(),
+                allocations: vec![],
+            },
+        );
+    }
+    let result = AcknowledgementResult::Timeout("rebalance".to_string());
+    save_ack_result(deps.as_mut().storage, port_id.clone(), 4, result.clone()).unwrap();
+    // a kept result rejects a replay of its packet
+    save_ack_result(deps.as_mut().storage, port_id, 4, result).unwrap_err();
+    let prune = |limit| ExecuteMsg::PruneState { limit };
+    execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), prune(1)).unwrap_err();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        prune(0),
+    )
+    .unwrap_err();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        prune(1),
+    )
+    .unwrap();
+    assert!(read_sudo_payload(deps.as_ref().storage, "channel-0".to_string(), 4).is_err());
+    assert!(query_ack_result(&deps, 4).is_some());
+    // the pending payload uses up the limit of the next call
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        prune(1),
+    )
+    .unwrap();
+    assert!(query_ack_result(&deps, 4).is_some());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        prune(10),
+    )
+    .unwrap();
+    assert!(query_ack_result(&deps, 4).is_some());
+    assert!(read_sudo_payload(deps.as_ref().storage, "channel-0".to_string(), 6).is_ok());
+    assert!(query_ack_result(&deps, 3).is_some());
+}
+
+fn query_errors(deps: &MockDeps, start_after: Option<u64>, limit: Option<u32>) -> Vec<ErrorEntry> {
+    from_binary(&query_errors_queue(deps.as_ref(), start_after, limit).unwrap()).unwrap()
+}
+
+#[test]
+fn test_failed_packet_is_queued() {
+    let mut deps = mock_dependencies();
+    let id = setup_refund(&mut deps, DepositStage::Transferring);
+    let port_id = get_port_id(MOCK_CONTRACT_ADDR, "babu_neutron_osmosis_v1");
+    ICA_STATUS
+        .save(deps.as_mut().storage, port_id, &IcaStatus::Closed)
+        .unwrap();
+
+    // the swap can't be sent, nothing of the handling is kept and the channel stays open
+    let request = sent_packet(
+        &mut deps,
+        1,
+        SudoOperation::TransferDeposit {
+            deposit_id: id,
+            amount: Uint128::new(100),
+        },
+    );
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: transfer_ack(),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    let deposit = load_deposit(&deps, id);
+    assert_eq!(deposit.stage, DepositStage::Transferring);
+    assert_eq!(deposit.transferred, None);
+    assert_eq!(query_ack_result(&deps, 1), None);
+    let errors = query_errors(&deps, None, None);
+    assert_eq!(
+        errors[0].kind,
+        ErrorKind::Response {
+            data: transfer_ack()
+        }
+    );
+    assert_eq!(errors[0].sequence, 1);
+
+    // once the account is open again the acknowledgement is handled as a whole
+    open_ica(
+        &mut deps,
+        "babu_neutron_osmosis_v1",
+        ICA_ADDR,
+        "connection-10",
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetryError { id: errors[0].id },
+    )
+    .unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    assert_eq!(load_deposit(&deps, id).stage, DepositStage::Swapping);
+    assert!(query_ack_result(&deps, 1).is_some());
+    assert_eq!(query_errors(&deps, None, None), vec![]);
+}
+
+#[test]
+fn test_storage_branch() {
+    let mut store = MockStorage::new();
+    store.set(b"a", b"1");
+    store.set(b"b", b"2");
+    store.set(b"d", b"4");
+
+    let mut branch = StorageBranch::new(&mut store);
+    branch.set(b"c", b"3");
+    branch.remove(b"b");
+    branch.set(b"d", b"5");
+    assert_eq!(branch.get(b"b"), None);
+    assert_eq!(branch.get(b"d"), Some(b"5".to_vec()));
+    let records = |order| {
+        branch
+            .range(None, None, order)
+            .map(|(key, value)| [key, value].concat())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        records(Order::Ascending),
+        vec![b"a1".to_vec(), b"c3".to_vec(), b"d5".to_vec()]
+    );
+    assert_eq!(
+        records(Order::Descending),
+        vec![b"d5".to_vec(), b"c3".to_vec(), b"a1".to_vec()]
+    );
+    assert_eq!(
+        branch
+            .range(Some(b"b"), Some(b"d"), Order::Ascending)
+            .collect::<Vec<_>>(),
+        vec![(b"c".to_vec(), b"3".to_vec())]
+    );
+
+    // nothing reaches the storage before the commit
+    drop(branch);
+    assert_eq!(store.get(b"c"), None);
+    let mut branch = StorageBranch::new(&mut store);
+    branch.set(b"c", b"3");
+    branch.remove(b"a");
+    branch.commit();
+    assert_eq!(store.get(b"a"), None);
+    assert_eq!(store.get(b"c"), Some(b"3".to_vec()));
+}
+
+#[test]
+fn test_query_errors_queue() {
+    let mut deps = mock_dependencies();
+    assert_eq!(query_errors(&deps, None, None), vec![]);
+
+    for seq in 1..=3 {
+        add_error_to_queue(
+            deps.as_mut().storage,
+            mock_env().block.time,
+            ErrorKind::MissingPayload,
+            "channel-0".to_string(),
+            seq,
+            None,
+            "Error message".to_string(),
+        )
+        .unwrap();
+    }
+
+    let page = query_errors(&deps, None, Some(2));
+    assert_eq!(page.iter().map(|e| e.id).collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(page[1].sequence, 2);
+    let page = query_errors(&deps, Some(1), Some(2));
+    assert_eq!(page.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2]);
+}
+
+#[test]
+fn test_errors_queue() {
+    let mut store = MockStorage::new();
+    assert_eq!(read_errors_from_queue(&store, None, 10).unwrap(), vec![]);
+
+    let add = |store: &mut MockStorage| {
+        add_error_to_queue(
+            store,
+            mock_env().block.time,
+            ErrorKind::WithdrawEpoch { epoch_id: 1 },
+            "channel-0".to_string(),
+            7,
+            None,
+            "some error message".to_string(),
+        )
+        .unwrap()
+    };
+    assert_eq!(add(&mut store), 0);
+    assert_eq!(add(&mut store), 1);
+    assert_eq!(
+        read_errors_from_queue(&store, None, 10).unwrap()[1],
+        ErrorEntry {
+            id: 1,
+            timestamp: mock_env().block.time,
+            kind: ErrorKind::WithdrawEpoch { epoch_id: 1 },
+            channel: "channel-0".to_string(),
+            sequence: 7,
+            payload: None,
+            details: "some error message".to_string(),
+        }
+    );
+
+    // ids of cleared errors are not handed out again
+    ERRORS_QUEUE.remove(&mut store, 1);
+    assert_eq!(add(&mut store), 2);
+}
+
+#[test]
+fn test_retry_error() {
+    let mut deps = mock_dependencies();
+    setup_vault(&mut deps);
+
+    // a sweep that can't be supplied while the interchain account is closed is queued
+    let port_id = get_port_id(MOCK_CONTRACT_ADDR, "babu_neutron_osmosis_v1");
+    ICA_STATUS
+        .save(deps.as_mut().storage, port_id, &IcaStatus::Closed)
+        .unwrap();
+    let operation = SudoOperation::SweepBuffer {
+        chain: HOME_CHAIN_ID.to_string(),
+        amount: Uint128::new(50),
+    };
+    let request = sent_packet(&mut deps, 1, operation.clone());
+    let res = sudo(
+        deps.as_mut(),
+        mock_env(),
+        SudoMsg::Response {
+            request,
+            data: Binary::from(b"{\"result\":\"AQ==\"}".to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    let errors = query_errors(&deps, None, None);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].kind,
+        ErrorKind::DeployBuffer {
+            chain: HOME_CHAIN_ID.to_string(),
+            amount: Uint128::new(50),
+        }
+    );
+    assert_eq!(errors[0].channel, "channel-0");
+    assert_eq!(errors[0].sequence, 1);
+    assert_eq!(
+        errors[0].payload.as_ref().map(|p| &p.operation),
+        Some(&operation)
+    );
+
+    // the retry fails as long as the cause is there and keeps the error
+    let retry = ExecuteMsg::RetryError { id: 0 };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        retry.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized));
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        retry.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::IcaNotReady { .. }));
+    assert_eq!(query_errors(&deps, None, None).len(), 1);
+
+    open_ica(
+        &mut deps,
+        "babu_neutron_osmosis_v1",
+        ICA_ADDR,
+        "connection-10",
+    );
+    let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), retry).unwrap();
+    assert!(is_submit_tx(&res.messages[0].msg));
+    assert_eq!(query_errors(&deps, None, None), vec![]);
+
+    // a packet without payload can only be cleared
+    let request = RequestPacket {
+        sequence: Some(2),
+        source_port: None,
+        source_channel: Some("channel-0".to_string()),
+        destination_port: None,
+        destination_channel: None,
+        data: None,
+        timeout_height: None,
+        timeout_timestamp: None,
+    };
+    sudo(deps.as_mut(), mock_env(), SudoMsg::Timeout { request }).unwrap();
+    let errors = query_errors(&deps, None, None);
+    assert_eq!(errors[0].id, 1);
+    assert_eq!(errors[0].kind, ErrorKind::MissingPayload);
+    assert_eq!(errors[0].payload, None);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::RetryError { id: 1 },
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::NotRetryable { id: 1 }));
+    let clear = ExecuteMsg::ClearError { id: 1 };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        clear.clone(),
+    )
+    .unwrap_err();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        clear.clone(),
+    )
+    .unwrap();
+    assert_eq!(query_errors(&deps, None, None), vec![]);
+    execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), clear).unwrap_err();
+}
+
+// deterministic xorshift so the property tests are reproducible
+fn next_rand(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[test]
+fn test_share_conversion() {
+    // first deposit is priced 1:1
+    assert_eq!(
+        value_to_shares(Uint128::new(1_000), Uint128::zero(), Uint128::zero()).unwrap(),
+        Uint128::new(1_000)
+    );
+    assert_eq!(
+        share_price(Uint128::zero(), Uint128::zero()),
+        Decimal256::one()
+    );
+
+    // no integer division before multiplying: 2M shares worth 3M value
+    assert_eq!(
+        value_to_shares(
+            Uint128::new(3_000_000),
+            Uint128::new(2_000_000),
+            Uint128::new(3_000_000)
+        )
+        .unwrap(),
+        Uint128::new(2_250_000)
+    );
+    assert_eq!(
+        shares_to_value(
+            Uint128::new(2_000_000),
+            Uint128::new(2_000_000),
+            Uint128::new(3_000_000)
+        )
+        .unwrap(),
+        Uint128::new(2_666_666)
+    );
+
+    // rounding is down on mint and redeem
+    assert_eq!(
+        value_to_shares(Uint128::new(2), Uint128::new(2), Uint128::new(3)).unwrap(),
+        Uint128::new(1)
+    );
+    assert_eq!(
+        shares_to_value(Uint128::new(1), Uint128::new(2), Uint128::new(3)).unwrap(),
+        Uint128::new(1)
+    );
+
+    // an empty but valued vault doesn't divide by zero
+    assert_eq!(
+        value_to_shares(Uint128::new(1), Uint128::new(1), Uint128::zero()).unwrap(),
+        Uint128::new(1)
+    );
+    // redemption is capped at what the vault holds
+    assert_eq!(
+        shares_to_value(Uint128::new(100), Uint128::new(100), Uint128::new(50)).unwrap(),
+        Uint128::new(50)
+    );
+
+    // large values don't overflow the intermediate product
+    assert_eq!(
+        value_to_shares(Uint128::MAX, Uint128::MAX, Uint128::MAX).unwrap(),
+        Uint128::MAX
+    );
+
+    shares_to_value(Uint128::new(2), Uint128::new(1), Uint128::new(1)).unwrap_err();
+}
+
+#[test]
+fn test_share_conversion_cannot_extract_value() {
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..10_000 {
+        let supply = Uint128::from(next_rand(&mut seed) % 1_000_000_000_000 + 1);
+        let value = Uint128::from(next_rand(&mut seed) % 1_000_000_000_000 + 1);
+        let deposit = Uint128::from(next_rand(&mut seed) % 1_000_000_000);
+
+        let minted = value_to_shares(deposit, supply, value).unwrap();
+        let new_supply = supply + minted;
+        let new_value = value + deposit;
+
+        // the depositor can't redeem more than they put in
+        let redeemed = shares_to_value(minted, new_supply, new_value).unwrap();
+        assert!(redeemed <= deposit);
+
+        // existing holders don't lose value to the deposit
+        let before = shares_to_value(supply, supply, value).unwrap();
+        let after = shares_to_value(supply, new_supply, new_value).unwrap();
+        assert!(after >= before);
+    }
+}
+
+#[test]
+fn test_first_depositor_inflation_attack() {
+    // attacker mints a single share into the empty vault
+    let attacker_deposit = Uint128::new(1);
+    let attacker_shares =
+        value_to_shares(attacker_deposit, Uint128::zero(), Uint128::zero()).unwrap();
+    assert_eq!(attacker_shares, Uint128::new(1));
+
+    // and donates 10k USDC to inflate the share price
+    let donation = Uint128::new(10_000_000_000);
+    let supply = attacker_shares;
+    let value = attacker_deposit + donation;
+
+    // without the offset the victim's 1k USDC would round down to zero shares
+    let victim_deposit = Uint128::new(1_000_000_000);
+    assert!(victim_deposit.multiply_ratio(supply, value).is_zero());
+
+    let victim_shares = value_to_shares(victim_deposit, supply, value).unwrap();
+    assert!(!victim_shares.is_zero());
+    let supply = supply + victim_shares;
+    let value = value + victim_deposit;
+
+    // the attacker loses almost all of the donation
+    let attacker_out = shares_to_value(attacker_shares, supply, value).unwrap();
+    assert!(attacker_out < (attacker_deposit + donation).multiply_ratio(1u128, 100u128));
+
+    // and the victim loses less than 0.01% to rounding
+    let victim_out = shares_to_value(victim_shares, supply, value).unwrap();
+    assert!(victim_out >= victim_deposit.multiply_ratio(9_999u128, 10_000u128));
 }
@@ -0,0 +1,538 @@
+use cosmwasm_std::{
+    to_binary, Addr, Binary, BlockInfo, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Order,
+    Response, StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+use cw20::{
+    AllAccountsResponse, AllAllowancesResponse, AllowanceInfo, BalanceResponse, Cw20ReceiveMsg,
+    Expiration, MarketingInfoResponse, TokenInfoResponse,
+};
+use cw_storage_plus::Bound;
+use neutron_sdk::{
+    bindings::{msg::NeutronMsg, query::NeutronQuery},
+    NeutronResult,
+};
+
+use crate::{
+    error::{ContractError, ContractResult},
+    msg::ShareBackend,
+    storage::{ALLOWANCES, BALANCES, CONFIG, MARKETING_INFO, SHARE_DENOM, TOKEN_INFO},
+};
+
+// CW20 interface of the vault shares, modelled after cw20-base but working on the
+// vault's own share ledger so shares can be used in other protocols.
+// With the TokenFactory backend the shares are a native denom instead, the cw20 messages
+// are rejected and balances live in the bank module. TokenInfo still tracks the supply
+// the vault minted, so share pricing does not depend on the backend.
+
+pub const DEFAULT_LIMIT: u32 = 10;
+pub const MAX_LIMIT: u32 = 30;
+
+pub const SHARE_SUBDENOM: &str = "babu";
+
+pub fn share_denom(contract: &Addr) -> String {
+    format!("factory/{}/{}", contract, SHARE_SUBDENOM)
+}
+
+fn increase_supply(store: &mut dyn Storage, amount: Uint128) -> ContractResult<()> {
+    let mut token_info = TOKEN_INFO.load(store)?;
+    token_info.total_supply += amount;
+    if let Some(limit) = token_info.get_cap() {
+        if token_info.total_supply > limit {
+            return Err(ContractError::CapExceeded);
+        }
+    }
+    Ok(TOKEN_INFO.save(store, &token_info)?)
+}
+
+fn decrease_supply(store: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    TOKEN_INFO.update(store, |mut token_info| -> StdResult<_> {
+        token_info.total_supply = token_info.total_supply.checked_sub(amount)?;
+        Ok(token_info)
+    })?;
+    Ok(())
+}
+
+// mints cw20 shares to the recipient, only called by the vault itself
+pub fn mint(store: &mut dyn Storage, recipient: &Addr, amount: Uint128) -> ContractResult<()> {
+    increase_supply(store, amount)?;
+    BALANCES.update(store, recipient.clone(), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })?;
+    Ok(())
+}
+
+// burns cw20 shares of the owner and reduces the supply
+pub fn burn(store: &mut dyn Storage, owner: &Addr, amount: Uint128) -> StdResult<()> {
+    BALANCES.update(store, owner.clone(), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    decrease_supply(store, amount)
+}
+
+// mints shares in the configured backend, native shares are minted by the returned messages
+pub fn mint_shares(
+    store: &mut dyn Storage,
+    recipient: &Addr,
+    amount: Uint128,
+) -> ContractResult<Vec<NeutronMsg>> {
+    match CONFIG.load(store)?.share_backend {
+        ShareBackend::Cw20 => {
+            mint(store, recipient, amount)?;
+            Ok(vec![])
+        }
+        ShareBackend::TokenFactory => {
+            increase_supply(store, amount)?;
+            Ok(vec![NeutronMsg::submit_mint_tokens(
+                SHARE_DENOM.load(store)?,
+                amount,
+                recipient.to_string(),
+            )])
+        }
+    }
+}
+
+// burns shares in the configured backend, native shares have to be held by the vault already
+pub fn burn_shares(
+    store: &mut dyn Storage,
+    owner: &Addr,
+    amount: Uint128,
+) -> StdResult<Vec<NeutronMsg>> {
+    match CONFIG.load(store)?.share_backend {
+        ShareBackend::Cw20 => {
+            burn(store, owner, amount)?;
+            Ok(vec![])
+        }
+        ShareBackend::TokenFactory => {
+            decrease_supply(store, amount)?;
+            Ok(vec![NeutronMsg::submit_burn_tokens(
+                SHARE_DENOM.load(store)?,
+                amount,
+            )])
+        }
+    }
+}
+
+// moves shares of the owner to the vault until they are burnt, native shares were
+// already sent along with the message
+pub fn lock_shares(
+    store: &mut dyn Storage,
+    owner: &Addr,
+    vault: &Addr,
+    amount: Uint128,
+) -> StdResult<()> {
+    if CONFIG.load(store)?.share_backend == ShareBackend::Cw20 {
+        BALANCES.update(store, owner.clone(), |balance| -> StdResult<_> {
+            Ok(balance.unwrap_or_default().checked_sub(amount)?)
+        })?;
+        BALANCES.update(store, vault.clone(), |balance| -> StdResult<_> {
+            Ok(balance.unwrap_or_default() + amount)
+        })?;
+    }
+    Ok(())
+}
+
+// checks that the sender can redeem `amount` shares: cw20 shares have to be in their
+// balance with no coins sent, native shares have to be the only coin sent along
+pub fn ensure_redeemable(
+    store: &dyn Storage,
+    info: &MessageInfo,
+    amount: Uint128,
+) -> ContractResult<()> {
+    match CONFIG.load(store)?.share_backend {
+        ShareBackend::Cw20 => {
+            // coins sent along would be locked in the vault
+            if !info.funds.is_empty() {
+                return Err(ContractError::InvalidFunds {
+                    expected: "no coins".to_string(),
+                });
+            }
+            let balance = BALANCES
+                .may_load(store, info.sender.clone())?
+                .unwrap_or_default();
+            if balance < amount {
+                return Err(ContractError::InsufficientShares);
+            }
+        }
+        ShareBackend::TokenFactory => {
+            let denom = SHARE_DENOM.load(store)?;
+            match info.funds.as_slice() {
+                [coin] if coin.denom == denom && coin.amount == amount => {}
+                _ => {
+                    return Err(ContractError::InvalidFunds {
+                        expected: format!("{}{}", amount, denom),
+                    })
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn ensure_cw20_backend(store: &dyn Storage) -> StdResult<()> {
+    if CONFIG.load(store)?.share_backend != ShareBackend::Cw20 {
+        return Err(StdError::generic_err(
+            "Shares are a native denom, use bank messages instead",
+        ));
+    }
+    Ok(())
+}
+
+// supply of shares as reported by the backend
+pub fn query_share_supply(deps: Deps<NeutronQuery>) -> StdResult<Uint128> {
+    match CONFIG.load(deps.storage)?.share_backend {
+        ShareBackend::Cw20 => Ok(TOKEN_INFO.load(deps.storage)?.total_supply),
+        ShareBackend::TokenFactory => Ok(deps
+            .querier
+            .query_supply(SHARE_DENOM.load(deps.storage)?)?
+            .amount),
+    }
+}
+
+fn move_balance(store: &mut dyn Storage, from: &Addr, to: &Addr, amount: Uint128) -> StdResult<()> {
+    BALANCES.update(store, from.clone(), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    BALANCES.update(store, to.clone(), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })?;
+    Ok(())
+}
+
+// reduces the allowance of the spender, fails if it is expired or too small
+fn deduct_allowance(
+    store: &mut dyn Storage,
+    owner: &Addr,
+    spender: &Addr,
+    block: &BlockInfo,
+    amount: Uint128,
+) -> StdResult<()> {
+    ALLOWANCES.update(store, (owner, spender), |current| -> StdResult<_> {
+        let mut allowance = current.ok_or_else(|| StdError::generic_err("No allowance"))?;
+        if allowance.expires.is_expired(block) {
+            return Err(StdError::generic_err("Allowance is expired"));
+        }
+        allowance.allowance = allowance.allowance.checked_sub(amount)?;
+        Ok(allowance)
+    })?;
+    Ok(())
+}
+
+fn ensure_non_zero(amount: Uint128) -> StdResult<()> {
+    if amount.is_zero() {
+        return Err(StdError::generic_err("Invalid zero amount"));
+    }
+    Ok(())
+}
+
+pub fn execute_transfer(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+    recipient: String,
+    amount: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    ensure_non_zero(amount)?;
+    let rcpt_addr = deps.api.addr_validate(&recipient)?;
+    move_balance(deps.storage, &info.sender, &rcpt_addr, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer")
+        .add_attribute("from", info.sender)
+        .add_attribute("to", recipient)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_send(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+    contract: String,
+    amount: Uint128,
+    msg: Binary,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    ensure_non_zero(amount)?;
+    let rcpt_addr = deps.api.addr_validate(&contract)?;
+    move_balance(deps.storage, &info.sender, &rcpt_addr, amount)?;
+
+    Ok(Response::new()
+        .add_message(receive_msg(&info.sender, &contract, amount, msg)?)
+        .add_attribute("action", "send")
+        .add_attribute("from", info.sender)
+        .add_attribute("to", contract)
+        .add_attribute("amount", amount))
+}
+
+// notifies `contract` of shares sent to it, like `Cw20ReceiveMsg::into_cosmos_msg` but for
+// the neutron message type of the vault's responses
+fn receive_msg(
+    sender: &Addr,
+    contract: &str,
+    amount: Uint128,
+    msg: Binary,
+) -> StdResult<CosmosMsg<NeutronMsg>> {
+    Ok(WasmMsg::Execute {
+        contract_addr: contract.to_string(),
+        msg: Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount,
+            msg,
+        }
+        .into_binary()?,
+        funds: vec![],
+    }
+    .into())
+}
+
+pub fn execute_burn(
+    deps: DepsMut<NeutronQuery>,
+    info: MessageInfo,
+    amount: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    ensure_non_zero(amount)?;
+    burn(deps.storage, &info.sender, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "burn")
+        .add_attribute("from", info.sender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_increase_allowance(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    let spender_addr = deps.api.addr_validate(&spender)?;
+    if spender_addr == info.sender {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Cannot set allowance to own account",
+        )));
+    }
+
+    ALLOWANCES.update(
+        deps.storage,
+        (&info.sender, &spender_addr),
+        |allowance| -> StdResult<_> {
+            let mut val = allowance.unwrap_or_default();
+            if let Some(exp) = expires {
+                if exp.is_expired(&env.block) {
+                    return Err(StdError::generic_err("Invalid expiration value"));
+                }
+                val.expires = exp;
+            }
+            val.allowance += amount;
+            Ok(val)
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "increase_allowance")
+        .add_attribute("owner", info.sender)
+        .add_attribute("spender", spender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_decrease_allowance(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    let spender_addr = deps.api.addr_validate(&spender)?;
+    if spender_addr == info.sender {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Cannot set allowance to own account",
+        )));
+    }
+
+    let key = (&info.sender, &spender_addr);
+    let mut allowance = ALLOWANCES.load(deps.storage, key)?;
+    if amount < allowance.allowance {
+        allowance.allowance -= amount;
+        if let Some(exp) = expires {
+            if exp.is_expired(&env.block) {
+                return Err(ContractError::Std(StdError::generic_err(
+                    "Invalid expiration value",
+                )));
+            }
+            allowance.expires = exp;
+        }
+        ALLOWANCES.save(deps.storage, key, &allowance)?;
+    } else {
+        ALLOWANCES.remove(deps.storage, key);
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "decrease_allowance")
+        .add_attribute("owner", info.sender)
+        .add_attribute("spender", spender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_transfer_from(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    recipient: String,
+    amount: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    ensure_non_zero(amount)?;
+    let owner_addr = deps.api.addr_validate(&owner)?;
+    let rcpt_addr = deps.api.addr_validate(&recipient)?;
+
+    deduct_allowance(deps.storage, &owner_addr, &info.sender, &env.block, amount)?;
+    move_balance(deps.storage, &owner_addr, &rcpt_addr, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_from")
+        .add_attribute("from", owner)
+        .add_attribute("to", recipient)
+        .add_attribute("by", info.sender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_send_from(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    contract: String,
+    amount: Uint128,
+    msg: Binary,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    ensure_non_zero(amount)?;
+    let owner_addr = deps.api.addr_validate(&owner)?;
+    let rcpt_addr = deps.api.addr_validate(&contract)?;
+
+    deduct_allowance(deps.storage, &owner_addr, &info.sender, &env.block, amount)?;
+    move_balance(deps.storage, &owner_addr, &rcpt_addr, amount)?;
+
+    Ok(Response::new()
+        .add_message(receive_msg(&info.sender, &contract, amount, msg)?)
+        .add_attribute("action", "send_from")
+        .add_attribute("from", owner)
+        .add_attribute("to", contract)
+        .add_attribute("by", info.sender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_burn_from(
+    deps: DepsMut<NeutronQuery>,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    amount: Uint128,
+) -> ContractResult<Response<NeutronMsg>> {
+    ensure_cw20_backend(deps.storage)?;
+    ensure_non_zero(amount)?;
+    let owner_addr = deps.api.addr_validate(&owner)?;
+
+    deduct_allowance(deps.storage, &owner_addr, &info.sender, &env.block, amount)?;
+    burn(deps.storage, &owner_addr, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "burn_from")
+        .add_attribute("from", owner)
+        .add_attribute("by", info.sender)
+        .add_attribute("amount", amount))
+}
+
+pub fn query_balance(deps: Deps<NeutronQuery>, addr: String) -> NeutronResult<Binary> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let balance = match CONFIG.load(deps.storage)?.share_backend {
+        ShareBackend::Cw20 => BALANCES.may_load(deps.storage, addr)?.unwrap_or_default(),
+        ShareBackend::TokenFactory => {
+            deps.querier
+                .query_balance(addr, SHARE_DENOM.load(deps.storage)?)?
+                .amount
+        }
+    };
+    Ok(to_binary(&BalanceResponse { balance })?)
+}
+
+pub fn query_token_info(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let info = TOKEN_INFO.load(deps.storage)?;
+    Ok(to_binary(&TokenInfoResponse {
+        name: info.name,
+        symbol: info.symbol,
+        decimals: info.decimals,
+        total_supply: query_share_supply(deps)?,
+    })?)
+}
+
+pub fn query_allowance(
+    deps: Deps<NeutronQuery>,
+    owner: String,
+    spender: String,
+) -> NeutronResult<Binary> {
+    let owner_addr = deps.api.addr_validate(&owner)?;
+    let spender_addr = deps.api.addr_validate(&spender)?;
+    let allowance = ALLOWANCES
+        .may_load(deps.storage, (&owner_addr, &spender_addr))?
+        .unwrap_or_default();
+    Ok(to_binary(&allowance)?)
+}
+
+pub fn query_all_accounts(
+    deps: Deps<NeutronQuery>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> NeutronResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|s| Bound::ExclusiveRaw(s.into_bytes()));
+
+    let accounts = BALANCES
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|addr| addr.map(String::from))
+        .collect::<StdResult<_>>()?;
+    Ok(to_binary(&AllAccountsResponse { accounts })?)
+}
+
+pub fn query_all_allowances(
+    deps: Deps<NeutronQuery>,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> NeutronResult<Binary> {
+    let owner_addr = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|s| Bound::ExclusiveRaw(s.into_bytes()));
+
+    let allowances = ALLOWANCES
+        .prefix(&owner_addr)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(spender, allowance)| AllowanceInfo {
+                spender: spender.to_string(),
+                allowance: allowance.allowance,
+                expires: allowance.expires,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(to_binary(&AllAllowancesResponse { allowances })?)
+}
+
+pub fn query_marketing_info(deps: Deps<NeutronQuery>) -> NeutronResult<Binary> {
+    let info = MARKETING_INFO
+        .may_load(deps.storage)?
+        .unwrap_or(MarketingInfoResponse {
+            project: None,
+            description: None,
+            logo: None,
+            marketing: None,
+        });
+    Ok(to_binary(&info)?)
+}
@@ -0,0 +1,158 @@
+use cosmwasm_std::{Decimal, StdError, StdResult};
+
+use crate::msg::{HostChain, StrategyKind};
+
+pub const MAX_PERFORMANCE_FEE: Decimal = Decimal::percent(30);
+pub const MAX_MANAGEMENT_FEE: Decimal = Decimal::percent(5);
+pub const MAX_TRANSACTION_FEE_BPS: u16 = 500;
+pub const MAX_FEE_RECIPIENTS: usize = 10;
+pub const MAX_STRATEGIES: usize = 10;
+pub const MAX_HOST_CHAINS: usize = 5;
+/// bounds the acknowledgement results kept in storage
+pub const MAX_ACK_RETENTION: u64 = 100_000;
+
+// checks that an address on the host chain is valid bech32 with the expected prefix
+pub fn validate_remote_addr(addr: &str, prefix: &str) -> StdResult<()> {
+    let (hrp, _data, _variant) = bech32::decode(addr)
+        .map_err(|e| StdError::generic_err(format!("Invalid address {}: {}", addr, e)))?;
+    if hrp != prefix {
+        return Err(StdError::generic_err(format!(
+            "Invalid address {}: expected prefix {}, got {}",
+            addr, prefix, hrp
+        )));
+    }
+    Ok(())
+}
+
+// checks that an IBC channel id is of the form `channel-<n>`
+pub fn validate_channel_id(channel_id: &str) -> StdResult<()> {
+    validate_numbered_id(channel_id, "channel-")
+}
+
+// checks that an IBC connection id is of the form `connection-<n>`
+pub fn validate_connection_id(connection_id: &str) -> StdResult<()> {
+    validate_numbered_id(connection_id, "connection-")
+}
+
+pub fn validate_slippage(slippage: Decimal) -> StdResult<()> {
+    if slippage > Decimal::one() {
+        return Err(StdError::generic_err("max_slippage must not exceed 1"));
+    }
+    Ok(())
+}
+
+pub fn validate_buffer_ratio(buffer_ratio: Decimal) -> StdResult<()> {
+    if buffer_ratio > Decimal::one() {
+        return Err(StdError::generic_err("buffer_ratio must not exceed 1"));
+    }
+    Ok(())
+}
+
+pub fn validate_rebalance_threshold(rebalance_threshold: Decimal) -> StdResult<()> {
+    if rebalance_threshold > Decimal::one() {
+        return Err(StdError::generic_err(
+            "rebalance_threshold must not exceed 1",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_fees(performance_fee: Decimal, management_fee: Decimal) -> StdResult<()> {
+    if performance_fee > MAX_PERFORMANCE_FEE {
+        return Err(StdError::generic_err(format!(
+            "performance_fee must not exceed {}",
+            MAX_PERFORMANCE_FEE
+        )));
+    }
+    if management_fee > MAX_MANAGEMENT_FEE {
+        return Err(StdError::generic_err(format!(
+            "management_fee must not exceed {}",
+            MAX_MANAGEMENT_FEE
+        )));
+    }
+    Ok(())
+}
+
+// checks the entry and exit fees and the weights they are split by
+pub fn validate_transaction_fees(
+    entry_fee_bps: u16,
+    exit_fee_bps: u16,
+    weights: &[u32],
+) -> StdResult<()> {
+    if entry_fee_bps > MAX_TRANSACTION_FEE_BPS || exit_fee_bps > MAX_TRANSACTION_FEE_BPS {
+        return Err(StdError::generic_err(format!(
+            "entry and exit fees must not exceed {} bps",
+            MAX_TRANSACTION_FEE_BPS
+        )));
+    }
+    if weights.len() > MAX_FEE_RECIPIENTS {
+        return Err(StdError::generic_err(format!(
+            "at most {} fee recipients are allowed",
+            MAX_FEE_RECIPIENTS
+        )));
+    }
+    if weights.contains(&0) {
+        return Err(StdError::generic_err(
+            "fee recipient weights must be positive",
+        ));
+    }
+    if (entry_fee_bps > 0 || exit_fee_bps > 0) && weights.is_empty() {
+        return Err(StdError::generic_err(
+            "entry and exit fees need at least one fee recipient",
+        ));
+    }
+    Ok(())
+}
+
+// checks a strategy before it is registered, the addresses it deploys to live on the host chain
+pub fn validate_strategy(id: &str, kind: &StrategyKind, prefix: &str) -> StdResult<()> {
+    if id.is_empty() {
+        return Err(StdError::generic_err("strategy id must not be empty"));
+    }
+    match kind {
+        StrategyKind::RedBank { addr } => validate_remote_addr(addr, prefix),
+    }
+}
+
+// checks a host chain before it is added or updated
+pub fn validate_host_chain(id: &str, chain: &HostChain) -> StdResult<()> {
+    if id.is_empty() {
+        return Err(StdError::generic_err("host chain id must not be empty"));
+    }
+    validate_connection_id(&chain.connection_id)?;
+    validate_channel_id(&chain.transfer_channel)?;
+    validate_channel_id(&chain.return_channel)?;
+    if chain.interchain_account_id.is_empty()
+        || chain.remote_denom.is_empty()
+        || chain.remote_bech32_prefix.is_empty()
+    {
+        return Err(StdError::generic_err(
+            "interchain_account_id, remote_denom and remote_bech32_prefix must not be empty",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_nav_max_age(nav_max_age: u64) -> StdResult<()> {
+    if nav_max_age == 0 {
+        return Err(StdError::generic_err("nav_max_age must be positive"));
+    }
+    Ok(())
+}
+
+fn validate_numbered_id(id: &str, prefix: &str) -> StdResult<()> {
+    id.strip_prefix(prefix)
+        .and_then(|n| n.parse::<u64>().ok())
+        .map(|_| ())
+        .ok_or_else(|| StdError::generic_err(format!("Invalid {}<n> id: {}", prefix, id)))
+}
+
+pub fn validate_ack_retention(ack_retention: u64) -> StdResult<()> {
+    if ack_retention == 0 || ack_retention > MAX_ACK_RETENTION {
+        return Err(StdError::generic_err(format!(
+            "ack_retention must be between 1 and {}",
+            MAX_ACK_RETENTION
+        )));
+    }
+    Ok(())
+}